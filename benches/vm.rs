@@ -0,0 +1,136 @@
+//! Benchmarks for compiling and executing programs.
+//!
+//! - `compile` only compiles the programs to bytecode
+//! - `execute` runs the precompiled bytecode in the stack VM, so parsing and compiling are not
+//!   measured. Loading is, every iteration deserializes and verifies the bytecode before running
+//!   it, because the VM consumes the runtime that owns the loaded constants
+//! - `vm` runs every program from source in the stack VM and the register VM
+//!
+//! Compare changes to the VM by saving a baseline before them with
+//! `cargo bench --bench vm -- "execute|vm/stack" --save-baseline before` and then running the same
+//! command with `--baseline before` after them.
+//!
+//! Compare the value representations with
+//! `cargo bench --bench vm -- --save-baseline enum` and then
+//! `cargo bench --bench vm --features nanbox -- --baseline enum`
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const PROGRAMS: [(&str, &str); 8] = [
+    ("constants.dil", include_str!("programs/constants.dil")),
+    ("calls.dil", include_str!("programs/calls.dil")),
+    ("fib.dil", include_str!("programs/fib.dil")),
+    ("fizzbuzz.dil", include_str!("programs/fizzbuzz.dil")),
+    ("arithmetic.dil", include_str!("programs/arithmetic.dil")),
+    ("objects.dil", include_str!("programs/objects.dil")),
+    ("fields.dil", include_str!("programs/fields.dil")),
+    ("strings.dil", include_str!("programs/strings.dil")),
+];
+
+fn compile_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compile");
+
+    for (name, source) in PROGRAMS {
+        group.bench_function(name, |b| {
+            b.iter(|| compile(black_box(source)));
+        });
+    }
+
+    group.finish();
+}
+
+fn execute_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("execute");
+
+    for (name, source) in PROGRAMS {
+        let bytecode = compile(source);
+        assert_runs_without_errors(name, false, |cfg| dilaria::run_bytecode(&bytecode, cfg));
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                with_config(false, |cfg| {
+                    dilaria::run_bytecode(black_box(&bytecode), cfg)
+                })
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn vm_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm");
+
+    for (name, source) in PROGRAMS {
+        for (vm, register) in [("stack", false), ("register", true)] {
+            assert_runs_without_errors(name, register, |cfg| dilaria::run_program(source, cfg));
+
+            group.bench_function(format!("{vm}/{name}"), |b| {
+                b.iter(|| {
+                    with_config(register, |cfg| dilaria::run_program(black_box(source), cfg))
+                });
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn compile(source: &str) -> Vec<u8> {
+    with_config(false, |cfg| dilaria::compile_program(source, cfg))
+        .expect("benchmark program failed to compile")
+}
+
+/// A broken program would only benchmark its error path, so every program is run once first
+fn assert_runs_without_errors(
+    name: &str,
+    register: bool,
+    run: impl FnOnce(&mut dilaria::Config<'_>),
+) {
+    let mut stdout = std::io::sink();
+    let mut stdin = std::io::empty();
+    let mut stderr = Vec::new();
+
+    run(&mut config(register, &mut stdout, &mut stdin, &mut stderr));
+
+    assert!(
+        stderr.is_empty(),
+        "{name} failed: {}",
+        String::from_utf8_lossy(&stderr)
+    );
+}
+
+/// Runs `f` with a config that discards all output
+fn with_config<R>(register: bool, f: impl FnOnce(&mut dilaria::Config<'_>) -> R) -> R {
+    let mut stdout = std::io::sink();
+    let mut stdin = std::io::empty();
+    let mut stderr = std::io::sink();
+
+    f(&mut config(register, &mut stdout, &mut stdin, &mut stderr))
+}
+
+fn config<'io>(
+    register: bool,
+    stdout: &'io mut dyn std::io::Write,
+    stdin: &'io mut dyn std::io::BufRead,
+    stderr: &'io mut dyn std::io::Write,
+) -> dilaria::Config<'io> {
+    dilaria::Config {
+        filename: "bench.dil",
+        debug: false,
+        step: false,
+        profile: false,
+        profile_folded: None,
+        coverage: false,
+        coverage_lcov: None,
+        stdout,
+        stdin,
+        stderr,
+        parse_only: false,
+        optimize: false,
+        register,
+    }
+}
+
+criterion_group!(benches, compile_benchmark, execute_benchmark, vm_benchmark);
+criterion_main!(benches);
@@ -1,5 +1,8 @@
 //! The compiler that compiles the AST down to bytecode
 
+mod optimize;
+pub mod register;
+
 use std::{cell::RefCell, rc::Rc};
 
 use bumpalo::{collections::Vec, Bump};
@@ -7,20 +10,24 @@ use bumpalo::{collections::Vec, Bump};
 use crate::{
     errors::{CompilerError, Span},
     runtime::{
-        bytecode::{FnBlock, Instr},
+        bytecode::{FnBlock, Function, Instr, LocalVar},
         gc::{RtAlloc, Symbol},
+        native,
+        stack_frame::CALLCONV_OFFSET_DATA,
         vm::Value,
     },
     syntax::ast::{
         Assignment, BinaryOp, BinaryOpKind, Block, Call, CallKind, Declaration, ElsePart, Expr,
-        FnDecl, Ident, IfStmt, Literal, Program, Stmt, UnaryOp, WhileStmt,
+        FnDecl, Ident, IfStmt, Literal, MatchStmt, Param, Pattern, Program, Stmt, UnaryOp,
+        UnaryOpKind, WhileStmt,
     },
     HashMap,
 };
 
 type CResult<T = ()> = Result<T, CompilerError>;
 
-const CALLCONV_OFFSET_DATA: usize = 3;
+/// The end of the `LocalVar` debuginfo of a variable whose scope has not ended yet
+const OPEN_SCOPE: usize = usize::MAX;
 
 #[derive(Debug, PartialEq, Eq)]
 enum OuterEnvKind {
@@ -31,38 +38,129 @@ enum OuterEnvKind {
 #[derive(Debug)]
 struct Env {
     locals: HashMap<Symbol, usize>,
+    /// The functions declared in this env. There are no closures yet, but nested functions can
+    /// still call them, since they are known when the nested function is compiled.
+    functions: HashMap<Symbol, Function>,
+    /// The constants declared in this env, which can't be assigned to
+    constants: HashMap<Symbol, Constant>,
     outer: Option<Rc<RefCell<Env>>>,
     outer_kind: OuterEnvKind,
+    /// The index of the first `LocalVar` of this env in the debuginfo of the current block
+    debug_locals_start: usize,
+}
+
+/// A variable declared with `const`
+#[derive(Debug, Clone, Copy)]
+struct Constant {
+    /// The name in the declaration
+    span: Span,
+    /// Top-level constants with a literal value have no local, every use is replaced by the value.
+    /// This also makes them usable in functions.
+    inlined: Option<Value>,
+}
+
+/// What an identifier refers to
+#[derive(Debug, Clone, Copy)]
+enum Binding {
+    /// A local variable of the current function at the offset
+    Local(usize),
+    /// A function declared in an enclosing function
+    Function(Function),
+    /// An inlined constant with this value
+    Const(Value),
 }
 
 impl Env {
+    fn lookup(&self, name: &Ident) -> CResult<Binding> {
+        self.resolve(name).map(|(binding, _)| binding)
+    }
+
+    /// Looks up a local variable of the current function, functions of enclosing functions and
+    /// constants can't be assigned to
     fn lookup_local(&self, name: &Ident) -> CResult<usize> {
-        fn lookup_inner(env: &Env, name: &Ident) -> Option<usize> {
-            env.locals.get(&name.sym).copied().or_else(|| {
+        match self.resolve(name)? {
+            (_, Some(constant)) => Err(CompilerError::with_note(
+                name.span,
+                format!("can't assign to the constant `{}`", name.sym.as_str()),
+                format!("`{}` is declared with `const` here", name.sym.as_str()),
+            )
+            .note_at(constant.span)),
+            (Binding::Local(offset), None) => Ok(offset),
+            (Binding::Function(_) | Binding::Const(_), None) => Err(not_found(name)),
+        }
+    }
+
+    /// Looks up what the name refers to, and whether it is a constant
+    fn resolve(&self, name: &Ident) -> CResult<(Binding, Option<Constant>)> {
+        fn resolve_inner(
+            env: &Env,
+            name: &Ident,
+            in_closure: bool,
+        ) -> Option<(Binding, Option<Constant>)> {
+            let constant = env.constants.get(&name.sym).copied();
+            if let Some(Constant {
+                inlined: Some(value),
+                ..
+            }) = constant
+            {
+                return Some((Binding::Const(value), constant));
+            }
+
+            if in_closure {
                 // TODO: closure handling lol 👀
-                if env.outer_kind == OuterEnvKind::Closure {
+                // only the functions of the outer function can be used, other locals shadow them
+                if let Some(function) = env.functions.get(&name.sym) {
+                    return Some((Binding::Function(*function), None));
+                }
+                if env.locals.contains_key(&name.sym) {
                     return None;
                 }
+            } else if let Some(offset) = env.locals.get(&name.sym) {
+                return Some((Binding::Local(*offset), constant));
+            }
 
-                env.outer
-                    .as_ref()
-                    .and_then(|outer| lookup_inner(&outer.borrow(), name))
-            })
+            let in_closure = in_closure || env.outer_kind == OuterEnvKind::Closure;
+            env.outer
+                .as_ref()
+                .and_then(|outer| resolve_inner(&outer.borrow(), name, in_closure))
         }
 
-        lookup_inner(self, name).ok_or_else(|| {
-            CompilerError::new(
-                name.span,
-                format!("variable {} not found", name.sym.as_str()),
-            )
-        })
+        resolve_inner(self, name, false).ok_or_else(|| not_found(name))
+    }
+
+    /// Makes the name a constant, after it has been declared as a local, or instead of that if
+    /// the constant is inlined
+    fn declare_constant(&mut self, name: &Ident, inlined: Option<Value>) {
+        if inlined.is_some() {
+            self.locals.remove(&name.sym);
+        }
+        self.functions.remove(&name.sym);
+        self.constants.insert(
+            name.sym,
+            Constant {
+                span: name.span,
+                inlined,
+            },
+        );
     }
 
-    fn new_inner(outer: Rc<RefCell<Self>>, outer_kind: OuterEnvKind) -> Rc<RefCell<Self>> {
+    /// Whether this is the env of the global script, outside of all blocks
+    fn is_top_level(&self) -> bool {
+        self.outer.is_none()
+    }
+
+    fn new_inner(
+        outer: Rc<RefCell<Self>>,
+        outer_kind: OuterEnvKind,
+        debug_locals_start: usize,
+    ) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Self {
             locals: HashMap::default(),
+            functions: HashMap::default(),
+            constants: HashMap::default(),
             outer: Some(outer),
             outer_kind,
+            debug_locals_start,
         }))
     }
 }
@@ -82,10 +180,13 @@ struct Compiler<'bc, 'gc> {
     breaks: HashMap<usize, std::vec::Vec<usize>>,
 }
 
-pub fn compile<'ast, 'bc, 'gc>(
-    ast: &'ast Program,
+/// Compiles the program, the first block is the global script. If `optimize` is set, the
+/// bytecode is optimized afterwards, see [`optimize`]
+pub fn compile<'bc>(
+    ast: &Program,
     bytecode_bump: &'bc Bump,
-    rt: &'gc mut RtAlloc,
+    rt: &mut RtAlloc,
+    optimize: bool,
 ) -> Result<&'bc [FnBlock<'bc>], CompilerError> {
     let mut compiler = Compiler {
         blocks: Vec::new_in(bytecode_bump),
@@ -93,8 +194,11 @@ pub fn compile<'ast, 'bc, 'gc>(
         bump: bytecode_bump,
         env: Rc::new(RefCell::new(Env {
             locals: HashMap::default(),
+            functions: HashMap::default(),
+            constants: HashMap::default(),
             outer: None,
             outer_kind: OuterEnvKind::Block,
+            debug_locals_start: 0,
         })),
         rt,
         loop_nesting: 0,
@@ -103,6 +207,12 @@ pub fn compile<'ast, 'bc, 'gc>(
 
     compiler.compile(ast)?;
 
+    if optimize {
+        for block in compiler.blocks.iter_mut() {
+            optimize::optimize(block);
+        }
+    }
+
     Ok(compiler.blocks.into_bump_slice())
 }
 
@@ -111,19 +221,22 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         let global_block = FnBlock {
             name: self.rt.intern_string("<main>"),
             code: Vec::new_in(self.bump),
+            consts: Vec::new_in(self.bump),
+            field_sites: Vec::new_in(self.bump),
             stack_sizes: Vec::new_in(self.bump),
             spans: Vec::new_in(self.bump),
+            locals: Vec::new_in(self.bump),
             arity: 0,
+            required: 0,
+            variadic: false,
+            is_method: false,
         };
         self.blocks.push(global_block);
         self.current_block_idx = self.blocks.len() - 1;
 
         self.compile_fn_body(ast)?;
-        self.push_instr(
-            Instr::PushVal(Value::Null),
-            StackChange::Grow,
-            Span::dummy(),
-        );
+        self.close_scope_locals();
+        self.push_value(Value::Null, Span::dummy());
         // exit the program. here, we use `exit` instead of `return` because there is no stack frame
         self.push_instr(Instr::Exit, StackChange::None, Span::dummy());
         Ok(())
@@ -145,8 +258,9 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
                 Stmt::If(inner) => self.compile_if(inner),
                 Stmt::Loop(block, span) => self.compile_loop(block, *span),
                 Stmt::While(inner) => self.compile_while(inner),
+                Stmt::Match(inner) => self.compile_match(inner, false),
                 Stmt::Break(span) => self.compile_break(*span),
-                Stmt::Return(expr, span) => self.compile_return(expr, *span),
+                Stmt::Return(expr, span) => self.compile_return(expr.as_ref(), *span),
                 Stmt::Print(expr, span) => self.compile_print(expr, *span),
                 Stmt::Block(inner) => self.compile_block(inner),
                 Stmt::Expr(inner) => self.compile_expr(inner),
@@ -157,26 +271,82 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
     }
 
     fn compile_declaration(&mut self, declaration: &Declaration) -> CResult {
+        let inlined = inlined_constant(declaration, &self.env.borrow());
+        if let Some((name, value)) = inlined {
+            self.env.borrow_mut().declare_constant(name, Some(value));
+            return Ok(());
+        }
+
         // Compile the expression, the result of the expression will be the last thing left on the stack
         self.compile_expr(&declaration.init)?;
         // Now just remember that the value at this stack location is this variable name
         let stack_pos = self.current_stack_top();
-        self.env
-            .borrow_mut()
-            .locals
-            .insert(declaration.name.sym, stack_pos);
+        match &declaration.pattern {
+            Pattern::Binding(name) => self.declare_local(name.sym, stack_pos),
+            // the value stays on the stack as a hidden local
+            pattern => self.compile_destructure(pattern, stack_pos),
+        }
+
+        // the variables shadow functions with the same name
+        let mut bindings = std::vec::Vec::new();
+        declaration.pattern.bindings(&mut bindings);
+        for binding in bindings {
+            let mut env = self.env.borrow_mut();
+            env.functions.remove(&binding.sym);
+            if declaration.constant {
+                env.declare_constant(&binding, None);
+            }
+        }
         Ok(())
     }
 
     fn compile_assignment(&mut self, assignment: &Assignment) -> CResult {
         let local = match &assignment.lhs {
             Expr::Ident(ident) => ident,
-            _ => todo!(),
+            Expr::Call(
+                call @ Call {
+                    callee,
+                    kind: CallKind::Field(field),
+                    ..
+                },
+            ) => {
+                if has_null_safe_link(call) {
+                    return Err(null_safe_assignment(&assignment.lhs));
+                }
+
+                self.compile_expr(callee)?;
+
+                if let Some(op) = assignment.op {
+                    // the object is needed for reading and writing, but only evaluated once
+                    self.push_instr(Instr::Dup, StackChange::Grow, assignment.span);
+                    let site = self.blocks[self.current_block_idx].add_field_site(field.sym);
+                    self.push_instr(Instr::GetField(site), StackChange::None, assignment.span);
+                    self.compile_expr(&assignment.rhs)?;
+                    self.push_instr(binary_instr(op), StackChange::Shrink, assignment.span);
+                } else {
+                    self.compile_expr(&assignment.rhs)?;
+                }
+
+                let site = self.blocks[self.current_block_idx].add_field_site(field.sym);
+                self.push_instr(
+                    Instr::SetField(site),
+                    StackChange::ShrinkN(2),
+                    assignment.span,
+                );
+                return Ok(());
+            }
+            _ => return Err(invalid_assignment_target(&assignment.lhs)),
         };
 
         let stack_pos = self.env.borrow().lookup_local(local)?;
 
-        self.compile_expr(&assignment.rhs)?;
+        if let Some(op) = assignment.op {
+            self.push_instr(Instr::Load(stack_pos), StackChange::Grow, assignment.span);
+            self.compile_expr(&assignment.rhs)?;
+            self.push_instr(binary_instr(op), StackChange::Shrink, assignment.span);
+        } else {
+            self.compile_expr(&assignment.rhs)?;
+        }
 
         self.push_instr(
             Instr::Store(stack_pos),
@@ -191,8 +361,11 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         let block = FnBlock {
             name: decl.name.sym,
             code: Vec::new_in(self.bump),
+            consts: Vec::new_in(self.bump),
+            field_sites: Vec::new_in(self.bump),
             stack_sizes: Vec::new_in(self.bump),
             spans: Vec::new_in(self.bump),
+            locals: Vec::new_in(self.bump),
             arity: decl.params.len().try_into().map_err(|_| {
                 CompilerError::new(
                     decl.params[u8::MAX as usize]
@@ -201,6 +374,9 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
                     "Too many parameters. How the fuck did you do this.".to_string(),
                 )
             })?,
+            required: required_args(decl.params),
+            variadic: decl.params.last().is_some_and(|param| param.rest),
+            is_method: decl.object.is_some(),
         };
 
         // set the new block as the current block
@@ -209,15 +385,24 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         let old_block_idx = self.current_block_idx;
         self.current_block_idx = new_block_idx;
 
+        // the function can call itself, methods are only reachable through their object
+        if decl.object.is_none() {
+            self.env
+                .borrow_mut()
+                .functions
+                .insert(decl.name.sym, new_block_idx);
+        }
+
         // compile the body with a captured environment
-        let inner_env = Env::new_inner(self.env.clone(), OuterEnvKind::Closure);
+        let inner_env = Env::new_inner(self.env.clone(), OuterEnvKind::Closure, 0);
         self.env = inner_env;
 
         {
-            // insert params as locals
-            let mut env_mut = self.env.borrow_mut();
+            // insert params as locals, destructured params are declared after their defaults
             for (i, param) in decl.params.iter().enumerate() {
-                env_mut.locals.insert(param.sym, i);
+                if let Pattern::Binding(name) = param.pattern {
+                    self.declare_local(name.sym, i);
+                }
             }
 
             let block = &mut self.blocks[self.current_block_idx];
@@ -228,36 +413,52 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
                 .push(decl.params.len() + CALLCONV_OFFSET_DATA);
         }
 
+        // missing arguments are `null`, so a default is just `param = param ?? default`
+        for (i, param) in decl.params.iter().enumerate() {
+            if let Some(default) = &param.default {
+                self.push_instr(Instr::Load(i), StackChange::Grow, param.span);
+                self.compile_default(default, param.span)?;
+                self.push_instr(Instr::Store(i), StackChange::Shrink, param.span);
+            }
+            if !matches!(param.pattern, Pattern::Binding(_)) {
+                self.compile_destructure(&param.pattern, i);
+            }
+        }
+
         self.compile_stmts(decl.body.stmts)?;
 
-        self.push_instr(Instr::PushVal(Value::Null), StackChange::Grow, decl.span);
-        self.push_instr(Instr::Return, StackChange::None, decl.span);
+        // the value of the body is returned, just like with `return`
+        self.compile_return(decl.body.value.as_ref(), decl.span)?;
 
+        self.close_scope_locals();
         let outer = self.env.borrow().outer.clone().expect("outer env got lost");
         self.env = outer;
 
         self.current_block_idx = old_block_idx;
 
+        if let Some(object) = decl.object {
+            // store the method in its object
+            self.compile_expr_ident(&object)?;
+            self.push_value(Value::Function(new_block_idx), decl.span);
+
+            let site = self.blocks[self.current_block_idx].add_field_site(decl.name.sym);
+            self.push_instr(Instr::SetField(site), StackChange::ShrinkN(2), decl.span);
+            return Ok(());
+        }
+
         // save the function as a local variable
-        self.push_instr(
-            Instr::PushVal(Value::Function(new_block_idx)),
-            StackChange::Grow,
-            decl.span,
-        );
+        self.push_value(Value::Function(new_block_idx), decl.span);
 
         let stack_pos = self.current_stack_top();
 
-        self.env
-            .borrow_mut()
-            .locals
-            .insert(decl.name.sym, stack_pos);
+        self.declare_local(decl.name.sym, stack_pos);
 
         Ok(())
     }
 
     fn compile_if(&mut self, if_stmt: &IfStmt) -> CResult {
         /*
-           0 PushVal (true)
+           0 PushTrue
          ╭─1 JumpCond (2)
          │ 2 // it is true
         ╭│─4 Jmp (1)           │ this is optional only for else
@@ -299,6 +500,37 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         Ok(())
     }
 
+    /// Like [`compile_if`](Self::compile_if), but leaves the value of the taken branch on the stack
+    fn compile_if_expr(&mut self, if_stmt: &IfStmt) -> CResult {
+        self.compile_expr(&if_stmt.cond)?;
+
+        // the offset will be fixed later
+        let jmp_idx = self.push_instr(Instr::JmpFalse(0), StackChange::Shrink, if_stmt.span);
+        let branch_stack_size = self.current_stack_size();
+
+        self.compile_block_expr(&if_stmt.body)?;
+
+        let else_skip_jmp_idx = self.push_instr(Instr::Jmp(0), StackChange::None, if_stmt.span);
+        // the else branch starts without the value of the first branch
+        self.set_jmp_target_stack_size(branch_stack_size);
+
+        let jmp_pos = self.forward_jmp_offset(jmp_idx as isize);
+        self.change_instr(jmp_idx, Instr::JmpFalse(jmp_pos));
+
+        match if_stmt.else_part {
+            Some(ElsePart::Else(block, _)) => self.compile_block_expr(block)?,
+            Some(ElsePart::ElseIf(if_stmt, _)) => self.compile_if_expr(if_stmt)?,
+            None => {
+                self.push_value(Value::Null, if_stmt.span);
+            }
+        }
+
+        let jmp_pos = self.forward_jmp_offset(else_skip_jmp_idx as isize);
+        self.change_instr(else_skip_jmp_idx, Instr::Jmp(jmp_pos));
+
+        Ok(())
+    }
+
     fn compile_loop(&mut self, ast_block: &Block, span: Span) -> CResult {
         /*
         ╭>0 // do things
@@ -323,7 +555,7 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
 
     fn compile_while(&mut self, while_stmt: &WhileStmt) -> CResult {
         /*
-        ╭─>0 PushVal (true)
+        ╭─>0 PushTrue
         │╭─1 JmpFalse (2)
         ││ 2 // body
         ╰│─3 Jmp (-3)
@@ -353,6 +585,210 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         Ok(())
     }
 
+    /// Compiles a `match`. As an expression, the value of the taken arm replaces the matched value
+    /// on the stack, or `null` if no arm matches.
+    fn compile_match(&mut self, match_stmt: &MatchStmt, is_expr: bool) -> CResult {
+        /*
+           0 // the value, which stays on the stack as a hidden local
+           1 PushNull             │ a slot for every nested value of the pattern
+         ╭─2 JmpFalse             │ the tests of the pattern, and the guard
+         │ 3 // the body
+         │ 4 ShrinkStack          │ the slots and the locals of the body
+        ╭│─5 Jmp
+        │╰>6 ShrinkStack          │ the slots, only reached if the arm doesn't match
+        │  7 // the next arms
+        ╰─>8 ShrinkStack (1)      │ the value, unless it is replaced by the value of the arm
+         */
+
+        self.compile_expr(&match_stmt.scrutinee)?;
+        let value = self.current_stack_top();
+        let arm_stack_size = self.current_stack_size();
+
+        let mut end_jmps = std::vec::Vec::new();
+
+        for arm in match_stmt.arms.iter() {
+            let slots = pattern_slots(&arm.pattern);
+            for _ in 0..slots {
+                self.push_value(Value::Null, arm.pattern.span());
+            }
+
+            let debug_locals_start = self.blocks[self.current_block_idx].locals.len();
+            let arm_env = Env::new_inner(self.env.clone(), OuterEnvKind::Block, debug_locals_start);
+            self.env = arm_env;
+
+            let mut next_slot = arm_stack_size;
+            let mut fails = std::vec::Vec::new();
+            self.compile_pattern(&arm.pattern, value, &mut next_slot, &mut fails);
+
+            if let Some(guard) = &arm.guard {
+                self.compile_expr(guard)?;
+                let guard_jmp = self.push_instr(Instr::JmpFalse(0), StackChange::Shrink, arm.span);
+                fails.push(guard_jmp);
+            }
+
+            if is_expr {
+                self.compile_block_expr(&arm.body)?;
+                self.push_instr(Instr::Store(value), StackChange::Shrink, arm.span);
+            } else {
+                self.compile_block(&arm.body)?;
+            }
+
+            self.close_scope_locals();
+            let outer = self.env.borrow().outer.clone().expect("outer env got lost");
+            self.env = outer;
+
+            self.shrink_stack(arm_stack_size, arm.span);
+            end_jmps.push(self.push_instr(Instr::Jmp(0), StackChange::None, arm.span));
+
+            // the tests fail before the slots are popped
+            self.set_jmp_target_stack_size(arm_stack_size + slots);
+            for fail in fails {
+                let offset = self.forward_jmp_offset(fail as isize);
+                self.change_instr(fail, Instr::JmpFalse(offset));
+            }
+            self.shrink_stack(arm_stack_size, arm.span);
+        }
+
+        if is_expr {
+            // no arm matched
+            self.push_value(Value::Null, match_stmt.span);
+            self.push_instr(Instr::Store(value), StackChange::Shrink, match_stmt.span);
+        }
+
+        for jmp in end_jmps {
+            let offset = self.forward_jmp_offset(jmp as isize);
+            self.change_instr(jmp, Instr::Jmp(offset));
+        }
+
+        if !is_expr {
+            self.shrink_stack(arm_stack_size - 1, match_stmt.span);
+        }
+
+        Ok(())
+    }
+
+    /// Tests whether the value at the offset `src` matches the pattern. Every failing test is a
+    /// `JmpFalse` in `fails`, which still has to be patched. Nested values are stored in the slots
+    /// starting at `next_slot`, and bindings become locals of the current env.
+    fn compile_pattern(
+        &mut self,
+        pattern: &Pattern,
+        src: usize,
+        next_slot: &mut usize,
+        fails: &mut std::vec::Vec<usize>,
+    ) {
+        let span = pattern.span();
+        match pattern {
+            Pattern::Wildcard(_) => {}
+            Pattern::Binding(name) => self.declare_local(name.sym, src),
+            Pattern::Literal(literal) => {
+                let index = self.blocks[self.current_block_idx].add_const(literal_value(literal));
+                self.push_instr(Instr::Load(src), StackChange::Grow, span);
+                self.push_instr(Instr::MatchConst(index), StackChange::None, span);
+                fails.push(self.push_instr(Instr::JmpFalse(0), StackChange::Shrink, span));
+            }
+            Pattern::Array(elements, rest, _) => {
+                let len = elements.len() as u32;
+                let test = match rest {
+                    Some(_) => Instr::MatchArrayMin(len),
+                    None => Instr::MatchArray(len),
+                };
+                self.push_instr(Instr::Load(src), StackChange::Grow, span);
+                self.push_instr(test, StackChange::None, span);
+                fails.push(self.push_instr(Instr::JmpFalse(0), StackChange::Shrink, span));
+
+                for (index, element) in elements.iter().enumerate() {
+                    if let Pattern::Wildcard(_) = element {
+                        continue;
+                    }
+                    let slot = take_slot(next_slot);
+                    self.push_instr(Instr::Load(src), StackChange::Grow, span);
+                    self.push_instr(
+                        Instr::GetElement(index as u32),
+                        StackChange::None,
+                        element.span(),
+                    );
+                    self.push_instr(Instr::Store(slot), StackChange::Shrink, element.span());
+                    self.compile_pattern(element, slot, next_slot, fails);
+                }
+
+                if let Some(rest) = rest.filter(|rest| !matches!(rest, Pattern::Wildcard(_))) {
+                    let slot = take_slot(next_slot);
+                    self.push_instr(Instr::Load(src), StackChange::Grow, span);
+                    self.push_instr(Instr::SliceArray(len), StackChange::None, rest.span());
+                    self.push_instr(Instr::Store(slot), StackChange::Shrink, rest.span());
+                    self.compile_pattern(rest, slot, next_slot, fails);
+                }
+            }
+            Pattern::Object(fields, _) => {
+                for field in fields.iter() {
+                    let site = self.blocks[self.current_block_idx].add_field_site(field.name.sym);
+                    self.push_instr(Instr::Load(src), StackChange::Grow, span);
+                    self.push_instr(Instr::HasField(site), StackChange::None, field.name.span);
+                    fails.push(self.push_instr(Instr::JmpFalse(0), StackChange::Shrink, span));
+
+                    if let Pattern::Wildcard(_) = field.pattern {
+                        continue;
+                    }
+                    let slot = take_slot(next_slot);
+                    let site = self.blocks[self.current_block_idx].add_field_site(field.name.sym);
+                    self.push_instr(Instr::Load(src), StackChange::Grow, span);
+                    self.push_instr(Instr::GetField(site), StackChange::None, field.name.span);
+                    self.push_instr(Instr::Store(slot), StackChange::Shrink, field.name.span);
+                    self.compile_pattern(&field.pattern, slot, next_slot, fails);
+                }
+            }
+            Pattern::Or(alternatives, _) => {
+                let (last, alternatives) = alternatives.split_last().expect("empty `|` pattern");
+                let mut matched_jmps = std::vec::Vec::new();
+
+                // a failing alternative continues with the next one
+                for alternative in alternatives {
+                    let mut alternative_fails = std::vec::Vec::new();
+                    self.compile_pattern(alternative, src, next_slot, &mut alternative_fails);
+                    matched_jmps.push(self.push_instr(Instr::Jmp(0), StackChange::None, span));
+                    for fail in alternative_fails {
+                        let offset = self.forward_jmp_offset(fail as isize);
+                        self.change_instr(fail, Instr::JmpFalse(offset));
+                    }
+                }
+                self.compile_pattern(last, src, next_slot, fails);
+
+                for jmp in matched_jmps {
+                    let offset = self.forward_jmp_offset(jmp as isize);
+                    self.change_instr(jmp, Instr::Jmp(offset));
+                }
+            }
+        }
+    }
+
+    /// Destructures the value at the offset `src` into the bindings of the pattern, which become
+    /// locals of the current env. The nested values stay on the stack as hidden locals, and a value
+    /// that doesn't match the pattern is a runtime error.
+    fn compile_destructure(&mut self, pattern: &Pattern, src: usize) {
+        let span = pattern.span();
+        let slots = pattern_slots(pattern);
+        for _ in 0..slots {
+            self.push_value(Value::Null, span);
+        }
+
+        let mut next_slot = self.current_stack_size() - slots;
+        let mut fails = std::vec::Vec::new();
+        self.compile_pattern(pattern, src, &mut next_slot, &mut fails);
+
+        if !fails.is_empty() {
+            let matched_jmp = self.push_instr(Instr::Jmp(0), StackChange::None, span);
+            for fail in fails {
+                let offset = self.forward_jmp_offset(fail as isize);
+                self.change_instr(fail, Instr::JmpFalse(offset));
+            }
+            self.push_instr(Instr::MatchFailed, StackChange::None, span);
+
+            let offset = self.forward_jmp_offset(matched_jmp as isize);
+            self.change_instr(matched_jmp, Instr::Jmp(offset));
+        }
+    }
+
     fn compile_break(&mut self, span: Span) -> CResult {
         let break_idx = self.push_instr(Instr::Jmp(0), StackChange::None, span);
         self.breaks
@@ -362,14 +798,40 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         Ok(())
     }
 
-    fn compile_return(&mut self, expr: &Option<Expr>, span: Span) -> CResult {
-        if let Some(expr) = expr {
-            self.compile_expr(expr)?;
-        } else {
-            self.push_instr(Instr::PushVal(Value::Null), StackChange::Grow, span);
+    fn compile_return(&mut self, expr: Option<&Expr>, span: Span) -> CResult {
+        match expr {
+            // the global script has no stack frame that could be reused
+            // method calls look up the method in the call itself, so they are never tail calls
+            // a `?.` may skip the call, so the result has to be produced here
+            Some(Expr::Call(
+                call @ Call {
+                    kind: CallKind::Fn(args),
+                    ..
+                },
+            )) if self.current_block_idx != 0
+                && call.method_call().is_none()
+                && !has_null_safe_link(call)
+                && !has_spread(args) =>
+            {
+                let mut null_jumps = std::vec::Vec::new();
+                let args = self.compile_call_operands(call, args, &mut null_jumps)?;
+                debug_assert!(null_jumps.is_empty());
+                self.push_instr(
+                    Instr::TailCall(args),
+                    StackChange::ShrinkN(args as usize + 1),
+                    span,
+                );
+                return Ok(());
+            }
+            Some(expr) => self.compile_expr(expr)?,
+            None => {
+                self.push_value(Value::Null, span);
+            }
         }
 
-        self.push_instr(Instr::Return, StackChange::None, span);
+        // the code after the return is never reached, but it must continue with the stack size
+        // from before the return statement, or the offsets of later locals would be wrong
+        self.push_instr(Instr::Return, StackChange::Shrink, span);
 
         Ok(())
     }
@@ -382,12 +844,57 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         Ok(())
     }
 
+    /// Compiles a block whose value is not used, and pops its locals
     fn compile_block(&mut self, block: &Block) -> CResult {
-        let next_env = Env::new_inner(self.env.clone(), OuterEnvKind::Block);
+        let block_stack_size = self.current_stack_size();
+
+        self.compile_block_scope(block, false)?;
+
+        self.shrink_stack(block_stack_size, block.span);
+        Ok(())
+    }
+
+    /// Compiles a block and leaves its value on the stack, in place of its locals
+    fn compile_block_expr(&mut self, block: &Block) -> CResult {
+        let block_stack_size = self.current_stack_size();
+
+        self.compile_block_scope(block, true)?;
+
+        if self.current_stack_size() > block_stack_size + 1 {
+            // move the value into the first local, and pop the rest
+            self.push_instr(
+                Instr::Store(block_stack_size),
+                StackChange::Shrink,
+                block.span,
+            );
+            self.shrink_stack(block_stack_size + 1, block.span);
+        }
+        Ok(())
+    }
+
+    /// Compiles the statements and the value of the block in a new scope. The value is pushed
+    /// last, and it is `null` if the block has none and `needs_value` is set.
+    fn compile_block_scope(&mut self, block: &Block, needs_value: bool) -> CResult {
+        let debug_locals_start = self.blocks[self.current_block_idx].locals.len();
+        let next_env = Env::new_inner(self.env.clone(), OuterEnvKind::Block, debug_locals_start);
         self.env = next_env;
 
         self.compile_stmts(block.stmts)?;
+        match &block.value {
+            // a trailing `if`, `match` or block doesn't need to produce a value nobody uses
+            Some(Expr::If(if_stmt)) if !needs_value => self.compile_if(if_stmt)?,
+            Some(Expr::Match(match_stmt)) if !needs_value => {
+                self.compile_match(match_stmt, false)?
+            }
+            Some(Expr::Block(block)) if !needs_value => self.compile_block(block)?,
+            Some(value) => self.compile_expr(value)?,
+            None if needs_value => {
+                self.push_value(Value::Null, block.span);
+            }
+            None => {}
+        }
 
+        self.close_scope_locals();
         let outer = self.env.borrow().outer.clone().expect("outer env got lost");
         self.env = outer;
         Ok(())
@@ -400,12 +907,33 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
             Expr::UnaryOp(inner) => self.compile_expr_unary(inner),
             Expr::BinaryOp(inner) => self.compile_expr_binary(inner),
             Expr::Call(inner) => self.compile_expr_call(inner),
+            Expr::If(inner) => self.compile_if_expr(inner),
+            Expr::Block(inner) => self.compile_block_expr(inner),
+            Expr::Match(inner) => self.compile_match(inner, true),
+            Expr::Spread(_) => unreachable!("spreads only appear in the arguments of calls"),
         }
     }
 
     fn compile_expr_ident(&mut self, name: &Ident) -> CResult {
-        let offset = self.env.borrow().lookup_local(name)?;
-        self.push_instr(Instr::Load(offset), StackChange::Grow, name.span);
+        let binding = self.env.borrow().lookup(name);
+
+        match binding {
+            Ok(Binding::Local(offset)) => {
+                self.push_instr(Instr::Load(offset), StackChange::Grow, name.span);
+            }
+            Ok(Binding::Function(function)) => {
+                self.push_value(Value::Function(function), name.span);
+            }
+            Ok(Binding::Const(value)) => {
+                self.push_value(value, name.span);
+            }
+            Err(err) => {
+                // locals shadow the native functions
+                let native = native::lookup(name.sym.as_str()).ok_or(err)?;
+                self.push_value(Value::NativeFunction(native), name.span);
+            }
+        }
+
         Ok(())
     }
 
@@ -413,19 +941,33 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         let value = match lit {
             Literal::String(str, _) => Value::String(*str),
             Literal::Number(num, _) => Value::Num(*num),
-            Literal::Array(vec, _) => {
-                if vec.is_empty() {
-                    Value::Array
-                } else {
-                    todo!()
+            Literal::Int(int, _) => Value::Int(*int),
+            Literal::Array([], _) => Value::Array(self.rt.alloc_array(std::vec::Vec::new())),
+            Literal::Array(elements, span) => {
+                for element in elements.iter() {
+                    self.compile_expr(element)?;
                 }
+                let len = elements
+                    .len()
+                    .try_into()
+                    .map_err(|_| CompilerError::new(*span, "Too many elements.".to_string()))?;
+                self.push_instr(
+                    Instr::NewArray(len),
+                    StackChange::ShrinkN(elements.len() - 1),
+                    *span,
+                );
+                return Ok(());
+            }
+            Literal::Object(span) => {
+                // objects are mutable, so every evaluation must create a new one
+                self.push_instr(Instr::NewObject, StackChange::Grow, *span);
+                return Ok(());
             }
-            Literal::Object(_) => Value::Object(self.rt.alloc_obj(HashMap::default())),
             Literal::Boolean(bool, _) => Value::Bool(*bool),
             Literal::Null(_) => Value::Null,
         };
 
-        self.push_instr(Instr::PushVal(value), StackChange::Grow, lit.span());
+        self.push_value(value, lit.span());
 
         Ok(())
     }
@@ -440,53 +982,107 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
     }
 
     fn compile_expr_binary(&mut self, binary: &BinaryOp) -> CResult {
+        if let BinaryOpKind::Coalesce = binary.kind {
+            return self.compile_coalesce(&binary.lhs, &binary.rhs, binary.span);
+        }
+
         self.compile_expr(&binary.lhs)?;
         self.compile_expr(&binary.rhs)?;
 
-        let instruction = match binary.kind {
-            BinaryOpKind::Add => Instr::BinAdd,
-            BinaryOpKind::And => Instr::BinAnd,
-            BinaryOpKind::Or => Instr::BinOr,
-            BinaryOpKind::Equal => Instr::CmpEq,
-            BinaryOpKind::GreaterEqual => Instr::CmpGreaterEq,
-            BinaryOpKind::Greater => Instr::CmpGreater,
-            BinaryOpKind::LessEqual => Instr::CmpLessEq,
-            BinaryOpKind::Less => Instr::CmpLess,
-            BinaryOpKind::NotEqual => Instr::CmpNotEq,
-            BinaryOpKind::Sub => Instr::BinSub,
-            BinaryOpKind::Mul => Instr::BinMul,
-            BinaryOpKind::Div => Instr::BinDiv,
-            BinaryOpKind::Mod => Instr::BinMod,
-        };
+        self.push_instr(binary_instr(binary.kind), StackChange::Shrink, binary.span);
+
+        Ok(())
+    }
+
+    /// ```text
+    /// lhs                 ╭─ lhs
+    /// JmpNull ─╮          │
+    /// Jmp ─────┼─╮        │ the value of lhs is the result
+    /// ShrinkStack <╯ │    │ pop the null
+    /// rhs            │    ╰─ rhs
+    ///              <─╯
+    /// ```
+    fn compile_coalesce(&mut self, lhs: &Expr, rhs: &Expr, span: Span) -> CResult {
+        self.compile_expr(lhs)?;
+        self.compile_default(rhs, span)
+    }
+
+    /// Replaces the value on top of the stack with `rhs` if it is `null`, `rhs` is only evaluated
+    /// then
+    fn compile_default(&mut self, rhs: &Expr, span: Span) -> CResult {
+        let result_size = self.current_stack_size();
+
+        let null_jmp = self.push_instr(Instr::JmpNull(0), StackChange::None, span);
+        let end_jmp = self.push_instr(Instr::Jmp(0), StackChange::None, span);
 
-        self.push_instr(instruction, StackChange::Shrink, binary.span);
+        let offset = self.forward_jmp_offset(null_jmp as isize);
+        self.change_instr(null_jmp, Instr::JmpNull(offset));
+        self.shrink_stack(result_size - 1, span);
+        self.compile_expr(rhs)?;
+
+        let offset = self.forward_jmp_offset(end_jmp as isize);
+        self.change_instr(end_jmp, Instr::Jmp(offset));
 
         Ok(())
     }
 
     fn compile_expr_call(&mut self, call: &Call) -> CResult {
-        let params = match &call.kind {
-            CallKind::Fn(params) => params,
-            _ => todo!(),
-        };
+        let mut null_jumps = std::vec::Vec::new();
+        self.compile_call_link(call, &mut null_jumps)?;
+        self.end_null_chain(null_jumps, call.span);
+        Ok(())
+    }
 
-        let name = match &call.callee {
-            Expr::Ident(ident) => ident,
-            _ => todo!(),
+    /// Compiles one call or field of a chain like `a?.b.c()`. Every `?.` in the chain pushes a
+    /// `JmpNull` to the end of the whole chain into `null_jumps`, together with the stack size at
+    /// the jump.
+    fn compile_call_link(
+        &mut self,
+        call: &Call,
+        null_jumps: &mut std::vec::Vec<(usize, usize)>,
+    ) -> CResult {
+        let params = match call.kind {
+            CallKind::Field(field) => {
+                self.compile_chain(&call.callee, null_jumps)?;
+                self.push_null_check(call, null_jumps);
+
+                let site = self.blocks[self.current_block_idx].add_field_site(field.sym);
+                self.push_instr(Instr::GetField(site), StackChange::None, call.span);
+                return Ok(());
+            }
+            CallKind::Fn(params) => params,
         };
 
-        let offset = self.env.borrow().lookup_local(name)?;
+        if let Some((_, method, params)) = call.method_call() {
+            if call.null_safe {
+                return Err(CompilerError::with_note(
+                    call.callee.span().extend(call.span),
+                    "methods can't be called with `?.()`".to_string(),
+                    "use `object?.method()` to skip the call if the object is `null`".to_string(),
+                ));
+            }
+            let Expr::Call(link) = call.callee else {
+                unreachable!("method calls are called on a field")
+            };
+            return self.compile_method_call(link, method, params, call.span, null_jumps);
+        }
 
-        for param in params.iter() {
-            self.compile_expr(param)?;
+        if has_spread(params) {
+            self.compile_spread_args(0, params, call.span)?;
+            self.compile_chain(&call.callee, null_jumps)?;
+            self.push_null_check(call, null_jumps);
+            // the function and the array are replaced by the return value
+            self.push_instr(Instr::CallSpread, StackChange::Shrink, call.span);
+            return Ok(());
         }
 
-        self.push_instr(Instr::Load(offset), StackChange::Grow, call.span);
+        let args = self.compile_call_operands(call, params, null_jumps)?;
+
         // The callee gets rid of the params. We also pushed the load for the function above,
         // but the callee also leaves behind a return value.
-        let expected_stack_shrink = params.len();
+        let expected_stack_shrink = args as usize;
         self.push_instr(
-            Instr::Call,
+            Instr::Call(args),
             StackChange::ShrinkN(expected_stack_shrink),
             call.span,
         );
@@ -494,6 +1090,179 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         Ok(())
     }
 
+    /// Compiles the callee of a link, which continues the chain if it is a call itself
+    fn compile_chain(
+        &mut self,
+        expr: &Expr,
+        null_jumps: &mut std::vec::Vec<(usize, usize)>,
+    ) -> CResult {
+        match expr {
+            Expr::Call(call) => self.compile_call_link(call, null_jumps),
+            _ => self.compile_expr(expr),
+        }
+    }
+
+    /// For a `?.`, skips the rest of the chain if the value on top of the stack is null
+    fn push_null_check(&mut self, call: &Call, null_jumps: &mut std::vec::Vec<(usize, usize)>) {
+        if call.null_safe {
+            let idx = self.push_instr(Instr::JmpNull(0), StackChange::None, call.span);
+            null_jumps.push((idx, self.current_stack_size()));
+        }
+    }
+
+    /// Patches the `JmpNull`s of a chain to its end, with the null as the result. Jumps from
+    /// deeper in the stack, like the callee of a call after its arguments, land on a pad that
+    /// moves the null into the place of the result and pops the rest.
+    fn end_null_chain(&mut self, null_jumps: std::vec::Vec<(usize, usize)>, span: Span) {
+        if null_jumps.is_empty() {
+            return;
+        }
+
+        let result_size = self.current_stack_size();
+        let (to_end, mut deeper): (std::vec::Vec<_>, std::vec::Vec<_>) = null_jumps
+            .into_iter()
+            .partition(|&(_, size)| size == result_size);
+        deeper.sort_by_key(|&(_, size)| size);
+
+        let mut end_jmps = std::vec::Vec::new();
+        if !deeper.is_empty() {
+            end_jmps.push(self.push_instr(Instr::Jmp(0), StackChange::None, span));
+        }
+        for pad in deeper.chunk_by(|(_, a), (_, b)| a == b) {
+            let size = pad[0].1;
+            self.set_jmp_target_stack_size(size);
+            for &(jmp, _) in pad {
+                let offset = self.forward_jmp_offset(jmp as isize);
+                self.change_instr(jmp, Instr::JmpNull(offset));
+            }
+            self.push_instr(Instr::Store(result_size - 1), StackChange::Shrink, span);
+            self.shrink_stack(result_size, span);
+            end_jmps.push(self.push_instr(Instr::Jmp(0), StackChange::None, span));
+        }
+
+        for (jmp, _) in to_end {
+            let offset = self.forward_jmp_offset(jmp as isize);
+            self.change_instr(jmp, Instr::JmpNull(offset));
+        }
+        for jmp in end_jmps {
+            let offset = self.forward_jmp_offset(jmp as isize);
+            self.change_instr(jmp, Instr::Jmp(offset));
+        }
+    }
+
+    /// Pushes the receiver and then the arguments, the method is looked up by the call itself
+    fn compile_method_call(
+        &mut self,
+        link: &Call,
+        method: Ident,
+        params: &[Expr],
+        span: Span,
+        null_jumps: &mut std::vec::Vec<(usize, usize)>,
+    ) -> CResult {
+        self.compile_chain(&link.callee, null_jumps)?;
+        self.push_null_check(link, null_jumps);
+
+        if has_spread(params) {
+            // the receiver is the first element of the arguments
+            self.compile_spread_args(1, params, span)?;
+            let site = self.blocks[self.current_block_idx].add_field_site(method.sym);
+            self.push_instr(Instr::CallMethodSpread(site), StackChange::None, span);
+            return Ok(());
+        }
+
+        for param in params.iter() {
+            self.compile_expr(param)?;
+        }
+
+        let args = params
+            .len()
+            .try_into()
+            .map_err(|_| CompilerError::new(span, "Too many arguments.".to_string()))?;
+
+        let site = self.blocks[self.current_block_idx].add_field_site(method.sym);
+        // the receiver and the arguments are replaced by the return value
+        self.push_instr(
+            Instr::CallMethod { site, args },
+            StackChange::ShrinkN(args as usize),
+            span,
+        );
+
+        Ok(())
+    }
+
+    /// Collects the arguments of a call with a spread into a single array. The `pushed` values on
+    /// top of the stack are its first elements.
+    ///
+    /// ```text
+    /// f(a, b, ...c, d)
+    ///
+    /// a b NewArray(2)
+    /// c ExtendArray
+    /// d NewArray(1) ExtendArray
+    /// ```
+    fn compile_spread_args(&mut self, pushed: usize, params: &[Expr], span: Span) -> CResult {
+        let mut pending = pushed;
+        let mut has_array = false;
+
+        for param in params {
+            if let Expr::Spread(spread) = param {
+                self.collect_args(pending, has_array, span)?;
+                pending = 0;
+                has_array = true;
+
+                self.compile_expr(&spread.expr)?;
+                self.push_instr(Instr::ExtendArray, StackChange::Shrink, spread.span);
+            } else {
+                self.compile_expr(param)?;
+                pending += 1;
+            }
+        }
+
+        if pending > 0 {
+            self.collect_args(pending, has_array, span)?;
+        }
+
+        Ok(())
+    }
+
+    /// Puts the `pending` values on top of the stack into an array, and appends it to the array
+    /// below them if there is one
+    fn collect_args(&mut self, pending: usize, has_array: bool, span: Span) -> CResult {
+        let len = pending
+            .try_into()
+            .map_err(|_| CompilerError::new(span, "Too many arguments.".to_string()))?;
+        let stack_change = match pending {
+            0 => StackChange::Grow,
+            _ => StackChange::ShrinkN(pending - 1),
+        };
+        self.push_instr(Instr::NewArray(len), stack_change, span);
+
+        if has_array {
+            self.push_instr(Instr::ExtendArray, StackChange::Shrink, span);
+        }
+        Ok(())
+    }
+
+    /// Pushes the arguments and then the function, and returns the amount of arguments
+    fn compile_call_operands(
+        &mut self,
+        call: &Call,
+        params: &[Expr],
+        null_jumps: &mut std::vec::Vec<(usize, usize)>,
+    ) -> CResult<u32> {
+        for param in params.iter() {
+            self.compile_expr(param)?;
+        }
+
+        self.compile_chain(&call.callee, null_jumps)?;
+        self.push_null_check(call, null_jumps);
+
+        params
+            .len()
+            .try_into()
+            .map_err(|_| CompilerError::new(call.span, "Too many arguments.".to_string()))
+    }
+
     fn shrink_stack(&mut self, jmp_target_stack_size: usize, span: Span) {
         let amount = self.current_stack_size() - jmp_target_stack_size;
 
@@ -519,6 +1288,42 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         self.loop_nesting -= 1;
     }
 
+    /// Declares a new local in the current env, and emits debuginfo for it
+    fn declare_local(&mut self, name: Symbol, stack_pos: usize) {
+        self.env.borrow_mut().locals.insert(name, stack_pos);
+        // a new variable with the same name is not constant
+        self.env.borrow_mut().constants.remove(&name);
+
+        let start = self.code_len() as usize;
+        let block = &mut self.blocks[self.current_block_idx];
+        block.locals.push(LocalVar {
+            name,
+            offset: stack_pos,
+            start,
+            end: OPEN_SCOPE,
+        });
+    }
+
+    /// Ends the scope of all locals of the current env in the debuginfo
+    fn close_scope_locals(&mut self) {
+        let start = self.env.borrow().debug_locals_start;
+        let end = self.code_len() as usize;
+        let block = &mut self.blocks[self.current_block_idx];
+        for local in &mut block.locals[start..] {
+            if local.end == OPEN_SCOPE {
+                local.end = end;
+            }
+        }
+    }
+
+    /// The code after an unconditional jump is only reached by other jumps, which can happen with a
+    /// different stack size than the one of the code before
+    fn set_jmp_target_stack_size(&mut self, size: usize) {
+        let block = &mut self.blocks[self.current_block_idx];
+        debug_assert!(matches!(block.code.last(), Some(Instr::Jmp(_))));
+        *block.stack_sizes.last_mut().expect("empty stack") = size;
+    }
+
     fn current_stack_top(&self) -> usize {
         let block = &self.blocks[self.current_block_idx];
         // we want the stack position, not the size, so the `- 1`
@@ -552,6 +1357,12 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
         block.code[index] = instr;
     }
 
+    /// Pushes an instruction that pushes the value onto the stack
+    fn push_value(&mut self, value: Value, span: Span) -> usize {
+        let instr = self.blocks[self.current_block_idx].push_value_instr(value);
+        self.push_instr(instr, StackChange::Grow, span)
+    }
+
     /// Pushes an instruction and returns the index of the new instruction
     fn push_instr(&mut self, instr: Instr, stack_change: StackChange, span: Span) -> usize {
         let block = &mut self.blocks[self.current_block_idx];
@@ -571,6 +1382,136 @@ impl<'bc, 'gc> Compiler<'bc, 'gc> {
     }
 }
 
+fn binary_instr(kind: BinaryOpKind) -> Instr {
+    match kind {
+        BinaryOpKind::Add => Instr::BinAdd,
+        BinaryOpKind::And => Instr::BinAnd,
+        BinaryOpKind::Or => Instr::BinOr,
+        BinaryOpKind::Equal => Instr::CmpEq,
+        BinaryOpKind::GreaterEqual => Instr::CmpGreaterEq,
+        BinaryOpKind::Greater => Instr::CmpGreater,
+        BinaryOpKind::LessEqual => Instr::CmpLessEq,
+        BinaryOpKind::Less => Instr::CmpLess,
+        BinaryOpKind::NotEqual => Instr::CmpNotEq,
+        BinaryOpKind::Sub => Instr::BinSub,
+        BinaryOpKind::Mul => Instr::BinMul,
+        BinaryOpKind::Div => Instr::BinDiv,
+        BinaryOpKind::IntDiv => Instr::BinIntDiv,
+        BinaryOpKind::Mod => Instr::BinMod,
+        BinaryOpKind::BitAnd => Instr::BinBitAnd,
+        BinaryOpKind::BitOr => Instr::BinBitOr,
+        BinaryOpKind::BitXor => Instr::BinBitXor,
+        BinaryOpKind::Shl => Instr::BinShl,
+        BinaryOpKind::Shr => Instr::BinShr,
+        BinaryOpKind::Coalesce => unreachable!("`??` short-circuits"),
+    }
+}
+
+/// How many arguments a function needs at least, the parameters after them have a default or
+/// collect the rest
+fn required_args(params: &[Param]) -> u32 {
+    params
+        .iter()
+        .take_while(|param| param.default.is_none() && !param.rest)
+        .count() as u32
+}
+
+/// Whether one of the arguments is spread, which needs the arguments in an array
+fn has_spread(args: &[Expr]) -> bool {
+    args.iter().any(|arg| matches!(arg, Expr::Spread(_)))
+}
+
+/// Whether a `?.` in the chain of calls and fields can skip this call
+fn has_null_safe_link(call: &Call) -> bool {
+    call.null_safe || matches!(call.callee, Expr::Call(callee) if has_null_safe_link(callee))
+}
+
+/// Only variables and fields can be assigned to, arrays can't be indexed
+fn invalid_assignment_target(target: &Expr) -> CompilerError {
+    let span = match target {
+        // the span of a call only covers its arguments
+        Expr::Call(call) => call.callee.span().extend(call.span),
+        target => target.span(),
+    };
+    CompilerError::with_note(
+        span,
+        "invalid assignment target".to_string(),
+        "only variables and fields can be assigned to".to_string(),
+    )
+}
+
+fn null_safe_assignment(target: &Expr) -> CompilerError {
+    CompilerError::with_note(
+        target.span(),
+        "can't assign to a field after `?.`".to_string(),
+        "the object might be `null`, check it with an `if` first".to_string(),
+    )
+}
+
+/// The name and value of a top-level constant that is inlined, since its initializer is a literal
+fn inlined_constant<'a>(declaration: &'a Declaration, env: &Env) -> Option<(&'a Ident, Value)> {
+    let Pattern::Binding(name) = &declaration.pattern else {
+        return None;
+    };
+    if !declaration.constant || !env.is_top_level() {
+        return None;
+    }
+
+    let value = match &declaration.init {
+        Expr::Literal(Literal::Array(..) | Literal::Object(_)) => return None,
+        Expr::Literal(literal) => literal_value(literal),
+        Expr::UnaryOp(unary) => match (&unary.kind, &unary.expr) {
+            (UnaryOpKind::Neg, Expr::Literal(Literal::Number(num, _))) => Value::Num(-num),
+            (UnaryOpKind::Neg, Expr::Literal(Literal::Int(int, _))) => Value::Int(-int),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((name, value))
+}
+
+/// The value of a literal that is not an array or object, like the ones in patterns
+fn literal_value(literal: &Literal) -> Value {
+    match literal {
+        Literal::String(str, _) => Value::String(*str),
+        Literal::Number(num, _) => Value::Num(*num),
+        Literal::Int(int, _) => Value::Int(*int),
+        Literal::Boolean(bool, _) => Value::Bool(*bool),
+        Literal::Null(_) => Value::Null,
+        Literal::Array(..) | Literal::Object(_) => {
+            unreachable!("array or object literal in pattern")
+        }
+    }
+}
+
+/// The amount of nested values of the pattern that must be stored while it is tested
+fn pattern_slots(pattern: &Pattern) -> usize {
+    let nested = |pattern: &Pattern| match pattern {
+        Pattern::Wildcard(_) => 0,
+        pattern => 1 + pattern_slots(pattern),
+    };
+    match pattern {
+        Pattern::Array(elements, rest, _) => elements.iter().chain(*rest).map(nested).sum(),
+        Pattern::Object(fields, _) => fields.iter().map(|field| nested(&field.pattern)).sum(),
+        Pattern::Or(alternatives, _) => alternatives.iter().map(pattern_slots).sum(),
+        Pattern::Literal(_) | Pattern::Binding(_) | Pattern::Wildcard(_) => 0,
+    }
+}
+
+/// Returns the offset of the next free slot of a pattern
+fn take_slot(next_slot: &mut usize) -> usize {
+    let slot = *next_slot;
+    *next_slot += 1;
+    slot
+}
+
+fn not_found(name: &Ident) -> CompilerError {
+    CompilerError::new(
+        name.span,
+        format!("variable {} not found", name.sym.as_str()),
+    )
+}
+
 #[derive(Debug, Copy, Clone)]
 enum StackChange {
     Shrink,
@@ -0,0 +1,205 @@
+//! The optimization pass over the compiled bytecode, enabled with `-O`
+//!
+//! This runs over the code of every `FnBlock` after compilation and
+//! * folds operations on constants, so `1 + 2 * 3` becomes a single `PushConst`
+//! * removes `Nop`s and jumps to the next instruction
+//! * merges consecutive `ShrinkStack`s
+//!
+//! Removed instructions are first replaced with `Nop`s, so that the indices stay the same, and
+//! then removed all at once, fixing up the relative jump offsets, spans and debuginfo.
+//!
+//! Operations that would fail at runtime are not folded, so the program still fails with the same
+//! error. Instructions are only combined if no jump lands between them.
+
+use crate::{
+    runtime::{
+        bytecode::{FnBlock, Instr},
+        vm::{self, Value},
+    },
+    HashSet,
+};
+
+pub fn optimize(block: &mut FnBlock<'_>) {
+    fold_constants(block);
+    while remove_nops(block) {}
+}
+
+/// The indices of all instructions that are the target of a jump
+fn jump_targets(code: &[Instr]) -> HashSet<usize> {
+    code.iter()
+        .enumerate()
+        .filter_map(|(index, instr)| match instr {
+            Instr::Jmp(offset) | Instr::JmpFalse(offset) | Instr::JmpNull(offset) => {
+                Some((index as isize + 1 + offset) as usize)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The index of the closest instruction before `index` that is not a `Nop`
+fn previous(code: &[Instr], index: usize) -> Option<usize> {
+    code[..index]
+        .iter()
+        .rposition(|instr| !matches!(instr, Instr::Nop))
+}
+
+fn fold_constants(block: &mut FnBlock<'_>) {
+    let targets = jump_targets(&block.code);
+    // the folded instructions must run after each other, no jump may skip parts of them
+    let is_straight = |from: usize, to: usize| !(from + 1..=to).any(|i| targets.contains(&i));
+
+    for index in 0..block.code.len() {
+        let instr = block.code[index];
+
+        if let Instr::Neg = instr {
+            let operand = match previous(&block.code, index) {
+                Some(operand) if is_straight(operand, index) => operand,
+                _ => continue,
+            };
+            let result = block.pushed_value(block.code[operand]).and_then(fold_neg);
+            if let Some(result) = result {
+                block.code[operand] = Instr::Nop;
+                block.code[index] = block.push_value_instr(result);
+            }
+        } else {
+            let rhs = match previous(&block.code, index) {
+                Some(rhs) => rhs,
+                None => continue,
+            };
+            let lhs = match previous(&block.code, rhs) {
+                Some(lhs) if is_straight(lhs, index) => lhs,
+                _ => continue,
+            };
+            let lhs_value = block.pushed_value(block.code[lhs]);
+            let rhs_value = block.pushed_value(block.code[rhs]);
+            if let (Some(lhs_value), Some(rhs_value)) = (lhs_value, rhs_value) {
+                if let Some(result) = fold_binary(instr, lhs_value, rhs_value) {
+                    block.code[lhs] = Instr::Nop;
+                    block.code[rhs] = Instr::Nop;
+                    block.code[index] = block.push_value_instr(result);
+                }
+            }
+        }
+    }
+}
+
+/// `Neg` on a constant, this is `-` and `not`
+fn fold_neg(value: Value) -> Option<Value> {
+    match value {
+        Value::Bool(_) | Value::Num(_) | Value::Int(_) => vm::neg(value).ok(),
+        _ => None,
+    }
+}
+
+/// A binary operation on two constants, `None` if `instr` isn't a binary operation or if it
+/// would fail at runtime. It uses the operators of the VM, so the result is always the same
+fn fold_binary(instr: Instr, lhs: Value, rhs: Value) -> Option<Value> {
+    let op: vm::BinaryOp = match instr {
+        Instr::BinAdd => vm::add,
+        Instr::BinSub => vm::sub,
+        Instr::BinMul => vm::mul,
+        Instr::BinDiv => vm::div,
+        Instr::BinIntDiv => vm::int_div,
+        Instr::BinMod => vm::rem,
+        Instr::BinAnd => vm::and,
+        Instr::BinOr => vm::or,
+        Instr::BinBitAnd => vm::bit_and,
+        Instr::BinBitOr => vm::bit_or,
+        Instr::BinBitXor => vm::bit_xor,
+        Instr::BinShl => vm::shl,
+        Instr::BinShr => vm::shr,
+        Instr::CmpGreater => vm::greater,
+        Instr::CmpGreaterEq => vm::greater_eq,
+        Instr::CmpLess => vm::less,
+        Instr::CmpLessEq => vm::less_eq,
+        Instr::CmpEq => vm::eq,
+        Instr::CmpNotEq => vm::not_eq,
+        _ => return None,
+    };
+    // arrays and objects have an identity, so they are never folded
+    let is_foldable = |value: Value| {
+        matches!(
+            value,
+            Value::Null | Value::Bool(_) | Value::Num(_) | Value::Int(_) | Value::String(_)
+        )
+    };
+    if !is_foldable(lhs) || !is_foldable(rhs) {
+        return None;
+    }
+    op(lhs, rhs).ok()
+}
+
+/// Turns useless instructions into `Nop`s and then removes all of them. Returns whether anything
+/// was removed, because that can create new jumps to the next instruction.
+fn remove_nops(block: &mut FnBlock<'_>) -> bool {
+    let targets = jump_targets(&block.code);
+    let code = &mut block.code;
+
+    for index in 0..code.len() {
+        match code[index] {
+            Instr::Jmp(0) | Instr::ShrinkStack(0) => code[index] = Instr::Nop,
+            Instr::ShrinkStack(amount) if !targets.contains(&(index + 1)) => {
+                if let Some(Instr::ShrinkStack(next)) = code.get(index + 1) {
+                    code[index + 1] = Instr::ShrinkStack(amount + next);
+                    code[index] = Instr::Nop;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the index of every instruction after the removal. removed instructions get the index of
+    // the next instruction that is kept, so jumps to them still work
+    let mut new_indices = Vec::with_capacity(code.len() + 1);
+    let mut kept = 0;
+    for instr in code.iter() {
+        new_indices.push(kept);
+        if !matches!(instr, Instr::Nop) {
+            kept += 1;
+        }
+    }
+    new_indices.push(kept);
+
+    if kept == code.len() {
+        return false;
+    }
+
+    let old_len = code.len();
+    for old in 0..old_len {
+        let instr = block.code[old];
+        if let Instr::Nop = instr {
+            continue;
+        }
+        let new = new_indices[old];
+
+        block.code[new] = match instr {
+            Instr::Jmp(offset) => Instr::Jmp(new_offset(&new_indices, old, offset)),
+            Instr::JmpFalse(offset) => Instr::JmpFalse(new_offset(&new_indices, old, offset)),
+            Instr::JmpNull(offset) => Instr::JmpNull(new_offset(&new_indices, old, offset)),
+            other => other,
+        };
+        block.spans[new] = block.spans[old];
+        // bytecode loaded from a file doesn't have the stack sizes
+        if let Some(&size) = block.stack_sizes.get(old) {
+            block.stack_sizes[new] = size;
+        }
+    }
+    block.code.truncate(kept);
+    block.spans.truncate(kept);
+    block.stack_sizes.truncate(kept);
+
+    for local in block.locals.iter_mut() {
+        // scopes that are still open are past the end of the code
+        local.start = new_indices.get(local.start).copied().unwrap_or(local.start);
+        local.end = new_indices.get(local.end).copied().unwrap_or(local.end);
+    }
+
+    true
+}
+
+/// The offset of a jump after the instructions have been moved
+fn new_offset(new_indices: &[usize], old: usize, offset: isize) -> isize {
+    let old_target = (old as isize + 1 + offset) as usize;
+    new_indices[old_target] as isize - new_indices[old] as isize - 1
+}
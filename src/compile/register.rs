@@ -0,0 +1,1176 @@
+//! The compiler for the register based bytecode, see
+//! [`register`](crate::runtime::bytecode::register)
+//!
+//! The scoping rules are the same as for the stack based bytecode and use the same [`Env`], but
+//! locals map to registers instead of stack offsets. Registers are handed out like a stack: every
+//! local and every temporary value takes the next free register, and they are free again at the end
+//! of their block or expression.
+
+use std::{cell::RefCell, rc::Rc};
+
+use bumpalo::{collections::Vec, Bump};
+
+use super::{
+    has_null_safe_link, has_spread, inlined_constant, invalid_assignment_target, literal_value,
+    null_safe_assignment, required_args, Binding, CResult, Env, OuterEnvKind,
+};
+use crate::{
+    errors::{CompilerError, Span},
+    runtime::{
+        bytecode::register::{Reg, RegBlock, RegInstr},
+        gc::{RtAlloc, Symbol},
+        native,
+        vm::Value,
+    },
+    syntax::ast::{
+        Assignment, BinaryOp, BinaryOpKind, Block, Call, CallKind, Declaration, ElsePart, Expr,
+        FnDecl, Ident, IfStmt, Literal, MatchStmt, Pattern, Program, Stmt, UnaryOp, WhileStmt,
+    },
+    HashMap,
+};
+
+struct Compiler<'bc, 'gc> {
+    blocks: Vec<'bc, RegBlock<'bc>>,
+    current_block_idx: usize,
+    bump: &'bc Bump,
+    env: Rc<RefCell<Env>>,
+    rt: &'gc mut RtAlloc,
+    /// The first free register of the current function
+    next_reg: usize,
+
+    /// How nested the current loop is, required for break offsets
+    loop_nesting: usize,
+    /// All break instructions currently in need of an offset. K=loop_nesting, V=break_indices
+    breaks: HashMap<usize, std::vec::Vec<usize>>,
+}
+
+/// Compiles the program to register based bytecode, the first block is the global script
+pub fn compile<'bc>(
+    ast: &Program,
+    bytecode_bump: &'bc Bump,
+    rt: &mut RtAlloc,
+) -> Result<&'bc [RegBlock<'bc>], CompilerError> {
+    let mut compiler = Compiler {
+        blocks: Vec::new_in(bytecode_bump),
+        current_block_idx: 0,
+        bump: bytecode_bump,
+        env: Rc::new(RefCell::new(Env {
+            locals: HashMap::default(),
+            functions: HashMap::default(),
+            constants: HashMap::default(),
+            outer: None,
+            outer_kind: OuterEnvKind::Block,
+            debug_locals_start: 0,
+        })),
+        rt,
+        next_reg: 0,
+        loop_nesting: 0,
+        breaks: HashMap::default(),
+    };
+
+    compiler.compile(ast)?;
+
+    Ok(compiler.blocks.into_bump_slice())
+}
+
+impl<'bc, 'gc> Compiler<'bc, 'gc> {
+    fn compile(&mut self, ast: &Program) -> CResult {
+        let name = self.rt.intern_string("<main>");
+        let global_block = self.new_block(name, 0);
+        self.blocks.push(global_block);
+        self.current_block_idx = self.blocks.len() - 1;
+
+        self.compile_stmts(ast.stmts)?;
+        // there is no caller to return to
+        self.push_instr(RegInstr::Exit, Span::dummy());
+        Ok(())
+    }
+
+    fn new_block(&self, name: Symbol, arity: u32) -> RegBlock<'bc> {
+        RegBlock {
+            name,
+            code: Vec::new_in(self.bump),
+            consts: Vec::new_in(self.bump),
+            field_sites: Vec::new_in(self.bump),
+            spans: Vec::new_in(self.bump),
+            arity,
+            required: arity,
+            variadic: false,
+            is_method: false,
+            registers: 0,
+        }
+    }
+
+    fn compile_stmts(&mut self, stmts: &[Stmt]) -> CResult {
+        for stmt in stmts {
+            let temporaries_start = self.next_reg;
+            match stmt {
+                Stmt::Declaration(inner) => self.compile_declaration(inner),
+                Stmt::Assignment(inner) => self.compile_assignment(inner),
+                Stmt::FnDecl(inner) => self.compile_fn_decl(inner),
+                Stmt::If(inner) => self.compile_if(inner),
+                Stmt::Loop(block, span) => self.compile_loop(block, *span),
+                Stmt::While(inner) => self.compile_while(inner),
+                Stmt::Match(inner) => self.compile_match(inner, None),
+                Stmt::Break(span) => self.compile_break(*span),
+                Stmt::Return(expr, span) => self.compile_return(expr.as_ref(), *span),
+                Stmt::Print(expr, span) => self.compile_print(expr, *span),
+                Stmt::Block(inner) => self.compile_block(inner),
+                Stmt::Expr(inner) => self.compile_expr(inner).map(drop),
+            }?;
+            // temporaries never outlive their statement, only declarations keep their register
+            if !matches!(stmt, Stmt::Declaration(_) | Stmt::FnDecl(_)) {
+                self.next_reg = temporaries_start;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile_declaration(&mut self, declaration: &Declaration) -> CResult {
+        let inlined = inlined_constant(declaration, &self.env.borrow());
+        if let Some((name, value)) = inlined {
+            self.env.borrow_mut().declare_constant(name, Some(value));
+            return Ok(());
+        }
+
+        let reg = self.alloc_reg(declaration.span)?;
+        self.compile_expr_into(&declaration.init, reg)?;
+
+        // the variables are only in scope after the initializer
+        match &declaration.pattern {
+            Pattern::Binding(name) => self.declare_local(name.sym, reg),
+            pattern => self.compile_destructure(pattern, reg)?,
+        }
+
+        // the variables shadow functions with the same name
+        let mut bindings = std::vec::Vec::new();
+        declaration.pattern.bindings(&mut bindings);
+        for binding in bindings {
+            let mut env = self.env.borrow_mut();
+            env.functions.remove(&binding.sym);
+            if declaration.constant {
+                env.declare_constant(&binding, None);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_assignment(&mut self, assignment: &Assignment) -> CResult {
+        let local = match &assignment.lhs {
+            Expr::Ident(ident) => ident,
+            Expr::Call(
+                call @ Call {
+                    callee,
+                    kind: CallKind::Field(field),
+                    ..
+                },
+            ) => {
+                if has_null_safe_link(call) {
+                    return Err(null_safe_assignment(&assignment.lhs));
+                }
+
+                let object = self.compile_expr(callee)?;
+
+                let src = if let Some(op) = assignment.op {
+                    let value = self.alloc_reg(assignment.span)?;
+                    let site = self.blocks[self.current_block_idx].add_field_site(field.sym);
+                    self.push_instr(
+                        RegInstr::GetField {
+                            dst: value,
+                            object,
+                            site,
+                        },
+                        assignment.span,
+                    );
+                    let rhs = self.compile_expr(&assignment.rhs)?;
+                    self.push_instr(binary_instr(op, value, value, rhs), assignment.span);
+                    value
+                } else {
+                    self.compile_expr(&assignment.rhs)?
+                };
+
+                let site = self.blocks[self.current_block_idx].add_field_site(field.sym);
+                self.push_instr(RegInstr::SetField { object, src, site }, assignment.span);
+                return Ok(());
+            }
+            _ => return Err(invalid_assignment_target(&assignment.lhs)),
+        };
+
+        let reg = self.env.borrow().lookup_local(local)? as Reg;
+
+        if let Some(op) = assignment.op {
+            let rhs = self.compile_expr(&assignment.rhs)?;
+            self.push_instr(binary_instr(op, reg, reg, rhs), assignment.span);
+            return Ok(());
+        }
+
+        // only the last instruction of an expression writes to its destination, so the variable
+        // can be used on the right side
+        self.compile_expr_into(&assignment.rhs, reg)
+    }
+
+    fn compile_fn_decl(&mut self, decl: &FnDecl) -> CResult {
+        let arity = decl.params.len().try_into().map_err(|_| {
+            CompilerError::new(
+                decl.params[u8::MAX as usize]
+                    .span
+                    .extend(decl.params.last().unwrap().span),
+                "Too many parameters. How the fuck did you do this.".to_string(),
+            )
+        })?;
+        let mut block = self.new_block(decl.name.sym, arity);
+        block.required = required_args(decl.params);
+        block.variadic = decl.params.last().is_some_and(|param| param.rest);
+        block.is_method = decl.object.is_some();
+
+        // set the new block as the current block
+        let new_block_idx = self.blocks.len();
+        self.blocks.push(block);
+        let old_block_idx = self.current_block_idx;
+        self.current_block_idx = new_block_idx;
+        let old_next_reg = self.next_reg;
+        self.next_reg = 0;
+
+        // the function can call itself, methods are only reachable through their object
+        if decl.object.is_none() {
+            self.env
+                .borrow_mut()
+                .functions
+                .insert(decl.name.sym, new_block_idx);
+        }
+
+        self.env = Env::new_inner(self.env.clone(), OuterEnvKind::Closure, 0);
+
+        // the arguments are the first registers, destructured params are declared after their
+        // defaults
+        for param in decl.params {
+            let reg = self.alloc_reg(param.span)?;
+            if let Pattern::Binding(name) = param.pattern {
+                self.declare_local(name.sym, reg);
+            }
+        }
+
+        // missing arguments are `null`, so a default is just `param = param ?? default`
+        for (reg, param) in decl.params.iter().enumerate() {
+            let reg = reg as Reg;
+            if let Some(default) = &param.default {
+                self.compile_default(reg, default, reg, param.span)?;
+            }
+            if !matches!(param.pattern, Pattern::Binding(_)) {
+                self.compile_destructure(&param.pattern, reg)?;
+            }
+        }
+
+        self.compile_stmts(decl.body.stmts)?;
+
+        // the value of the body is returned, just like with `return`
+        self.compile_return(decl.body.value.as_ref(), decl.span)?;
+
+        let outer = self.env.borrow().outer.clone().expect("outer env got lost");
+        self.env = outer;
+        self.current_block_idx = old_block_idx;
+        self.next_reg = old_next_reg;
+
+        if let Some(object) = decl.object {
+            // store the method in its object, which needs no register afterwards
+            let object = self.compile_expr(&Expr::Ident(object))?;
+            let src = self.alloc_reg(decl.span)?;
+            self.load_value(src, Value::Function(new_block_idx), decl.span);
+
+            let site = self.blocks[self.current_block_idx].add_field_site(decl.name.sym);
+            self.push_instr(RegInstr::SetField { object, src, site }, decl.span);
+            self.next_reg = old_next_reg;
+            return Ok(());
+        }
+
+        // save the function as a local variable
+        let reg = self.alloc_reg(decl.span)?;
+        self.load_value(reg, Value::Function(new_block_idx), decl.span);
+        self.declare_local(decl.name.sym, reg);
+
+        Ok(())
+    }
+
+    fn compile_if(&mut self, if_stmt: &IfStmt) -> CResult {
+        let cond = self.compile_expr(&if_stmt.cond)?;
+
+        // the offset will be fixed later
+        let jmp_idx = self.push_instr(RegInstr::JmpFalse { cond, offset: 0 }, if_stmt.span);
+
+        self.compile_block(&if_stmt.body)?;
+
+        if let Some(else_part) = if_stmt.else_part {
+            let else_skip_jmp_idx = self.push_instr(RegInstr::Jmp { offset: 0 }, if_stmt.span);
+
+            let offset = self.forward_jmp_offset(jmp_idx);
+            self.change_instr(jmp_idx, RegInstr::JmpFalse { cond, offset });
+
+            match else_part {
+                ElsePart::Else(block, _) => self.compile_block(block)?,
+                ElsePart::ElseIf(if_stmt, _) => self.compile_if(if_stmt)?,
+            }
+
+            let offset = self.forward_jmp_offset(else_skip_jmp_idx);
+            self.change_instr(else_skip_jmp_idx, RegInstr::Jmp { offset });
+        } else {
+            let offset = self.forward_jmp_offset(jmp_idx);
+            self.change_instr(jmp_idx, RegInstr::JmpFalse { cond, offset });
+        }
+
+        Ok(())
+    }
+
+    /// Like [`compile_if`](Self::compile_if), but writes the value of the taken branch to `dst`
+    fn compile_if_into(&mut self, if_stmt: &IfStmt, dst: Reg) -> CResult {
+        let cond = self.compile_expr(&if_stmt.cond)?;
+
+        // the offset will be fixed later
+        let jmp_idx = self.push_instr(RegInstr::JmpFalse { cond, offset: 0 }, if_stmt.span);
+
+        self.compile_block_into(&if_stmt.body, dst)?;
+
+        let else_skip_jmp_idx = self.push_instr(RegInstr::Jmp { offset: 0 }, if_stmt.span);
+
+        let offset = self.forward_jmp_offset(jmp_idx);
+        self.change_instr(jmp_idx, RegInstr::JmpFalse { cond, offset });
+
+        match if_stmt.else_part {
+            Some(ElsePart::Else(block, _)) => self.compile_block_into(block, dst)?,
+            Some(ElsePart::ElseIf(if_stmt, _)) => self.compile_if_into(if_stmt, dst)?,
+            None => self.load_value(dst, Value::Null, if_stmt.span),
+        }
+
+        let offset = self.forward_jmp_offset(else_skip_jmp_idx);
+        self.change_instr(else_skip_jmp_idx, RegInstr::Jmp { offset });
+
+        Ok(())
+    }
+
+    fn compile_loop(&mut self, ast_block: &Block, span: Span) -> CResult {
+        let first_stmt_idx = self.code_len();
+        self.loop_nesting += 1;
+
+        self.compile_block(ast_block)?;
+
+        let offset = self.back_jmp_offset(first_stmt_idx);
+        self.push_instr(RegInstr::Jmp { offset }, span);
+
+        self.end_loop();
+
+        Ok(())
+    }
+
+    fn compile_while(&mut self, while_stmt: &WhileStmt) -> CResult {
+        let cond_idx = self.code_len();
+        self.loop_nesting += 1;
+
+        let cond = self.compile_expr(&while_stmt.cond)?;
+        let jmp_false_idx =
+            self.push_instr(RegInstr::JmpFalse { cond, offset: 0 }, while_stmt.span);
+
+        self.compile_block(&while_stmt.body)?;
+
+        let offset = self.back_jmp_offset(cond_idx);
+        self.push_instr(RegInstr::Jmp { offset }, while_stmt.span);
+
+        let offset = self.forward_jmp_offset(jmp_false_idx);
+        self.change_instr(jmp_false_idx, RegInstr::JmpFalse { cond, offset });
+
+        self.end_loop();
+
+        Ok(())
+    }
+
+    /// Compiles a `match`, as an expression the value of the taken arm is written to `dst`, or
+    /// `null` if no arm matches
+    fn compile_match(&mut self, match_stmt: &MatchStmt, dst: Option<Reg>) -> CResult {
+        // the value is copied, so that assigning to a binding doesn't change the matched variable
+        let value = self.alloc_reg(match_stmt.span)?;
+        self.compile_expr_into(&match_stmt.scrutinee, value)?;
+        let arm_start_reg = self.next_reg;
+
+        let mut end_jmps = std::vec::Vec::new();
+
+        for arm in match_stmt.arms.iter() {
+            let arm_env = Env::new_inner(self.env.clone(), OuterEnvKind::Block, 0);
+            self.env = arm_env;
+
+            let mut fails = std::vec::Vec::new();
+            self.compile_pattern(&arm.pattern, value, &mut fails)?;
+
+            if let Some(guard) = &arm.guard {
+                let cond = self.compile_expr(guard)?;
+                let guard_jmp = self.push_instr(RegInstr::JmpFalse { cond, offset: 0 }, arm.span);
+                fails.push((guard_jmp, cond));
+            }
+
+            match dst {
+                Some(dst) => self.compile_block_into(&arm.body, dst)?,
+                None => self.compile_block(&arm.body)?,
+            }
+
+            // the bindings and the nested values of the pattern are gone
+            self.next_reg = arm_start_reg;
+            let outer = self.env.borrow().outer.clone().expect("outer env got lost");
+            self.env = outer;
+
+            end_jmps.push(self.push_instr(RegInstr::Jmp { offset: 0 }, arm.span));
+
+            for (fail, cond) in fails {
+                let offset = self.forward_jmp_offset(fail);
+                self.change_instr(fail, RegInstr::JmpFalse { cond, offset });
+            }
+        }
+
+        if let Some(dst) = dst {
+            // no arm matched
+            self.load_value(dst, Value::Null, match_stmt.span);
+        }
+
+        for jmp in end_jmps {
+            let offset = self.forward_jmp_offset(jmp);
+            self.change_instr(jmp, RegInstr::Jmp { offset });
+        }
+
+        Ok(())
+    }
+
+    /// Tests whether the value in `src` matches the pattern. Every failing test is a `JmpFalse` in
+    /// `fails` together with its condition, which still has to be patched. Bindings become locals
+    /// of the current env.
+    fn compile_pattern(
+        &mut self,
+        pattern: &Pattern,
+        src: Reg,
+        fails: &mut std::vec::Vec<(usize, Reg)>,
+    ) -> CResult {
+        let span = pattern.span();
+        match pattern {
+            Pattern::Wildcard(_) => {}
+            Pattern::Binding(name) => self.declare_local(name.sym, src),
+            Pattern::Literal(literal) => {
+                let index = self.blocks[self.current_block_idx].add_const(literal_value(literal));
+                let cond = self.alloc_reg(span)?;
+                self.push_instr(
+                    RegInstr::MatchConst {
+                        dst: cond,
+                        src,
+                        index,
+                    },
+                    span,
+                );
+                fails.push((
+                    self.push_instr(RegInstr::JmpFalse { cond, offset: 0 }, span),
+                    cond,
+                ));
+            }
+            Pattern::Array(elements, rest, _) => {
+                let len = elements.len() as u32;
+                let cond = self.alloc_reg(span)?;
+                let test = match rest {
+                    Some(_) => RegInstr::MatchArrayMin {
+                        dst: cond,
+                        src,
+                        len,
+                    },
+                    None => RegInstr::MatchArray {
+                        dst: cond,
+                        src,
+                        len,
+                    },
+                };
+                self.push_instr(test, span);
+                fails.push((
+                    self.push_instr(RegInstr::JmpFalse { cond, offset: 0 }, span),
+                    cond,
+                ));
+
+                for (index, element) in elements.iter().enumerate() {
+                    if let Pattern::Wildcard(_) = element {
+                        continue;
+                    }
+                    let dst = self.alloc_reg(element.span())?;
+                    let index = index as u32;
+                    let instr = RegInstr::GetElement {
+                        dst,
+                        array: src,
+                        index,
+                    };
+                    self.push_instr(instr, element.span());
+                    self.compile_pattern(element, dst, fails)?;
+                }
+
+                if let Some(rest) = rest.filter(|rest| !matches!(rest, Pattern::Wildcard(_))) {
+                    let dst = self.alloc_reg(rest.span())?;
+                    let instr = RegInstr::SliceArray {
+                        dst,
+                        array: src,
+                        start: len,
+                    };
+                    self.push_instr(instr, rest.span());
+                    self.compile_pattern(rest, dst, fails)?;
+                }
+            }
+            Pattern::Object(fields, _) => {
+                for field in fields.iter() {
+                    let site = self.blocks[self.current_block_idx].add_field_site(field.name.sym);
+                    let cond = self.alloc_reg(span)?;
+                    let instr = RegInstr::HasField {
+                        dst: cond,
+                        object: src,
+                        site,
+                    };
+                    self.push_instr(instr, field.name.span);
+                    let fail = self.push_instr(RegInstr::JmpFalse { cond, offset: 0 }, span);
+                    fails.push((fail, cond));
+
+                    if let Pattern::Wildcard(_) = field.pattern {
+                        continue;
+                    }
+                    let dst = self.alloc_reg(field.name.span)?;
+                    let site = self.blocks[self.current_block_idx].add_field_site(field.name.sym);
+                    let instr = RegInstr::GetField {
+                        dst,
+                        object: src,
+                        site,
+                    };
+                    self.push_instr(instr, field.name.span);
+                    self.compile_pattern(&field.pattern, dst, fails)?;
+                }
+            }
+            Pattern::Or(alternatives, _) => {
+                let (last, alternatives) = alternatives.split_last().expect("empty `|` pattern");
+                let mut matched_jmps = std::vec::Vec::new();
+
+                // a failing alternative continues with the next one
+                for alternative in alternatives {
+                    let mut alternative_fails = std::vec::Vec::new();
+                    self.compile_pattern(alternative, src, &mut alternative_fails)?;
+                    matched_jmps.push(self.push_instr(RegInstr::Jmp { offset: 0 }, span));
+                    for (fail, cond) in alternative_fails {
+                        let offset = self.forward_jmp_offset(fail);
+                        self.change_instr(fail, RegInstr::JmpFalse { cond, offset });
+                    }
+                }
+                self.compile_pattern(last, src, fails)?;
+
+                for jmp in matched_jmps {
+                    let offset = self.forward_jmp_offset(jmp);
+                    self.change_instr(jmp, RegInstr::Jmp { offset });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Destructures the value in `src` into the bindings of the pattern, which become locals of
+    /// the current env. A value that doesn't match the pattern is a runtime error.
+    fn compile_destructure(&mut self, pattern: &Pattern, src: Reg) -> CResult {
+        let span = pattern.span();
+        let mut fails = std::vec::Vec::new();
+        self.compile_pattern(pattern, src, &mut fails)?;
+
+        if !fails.is_empty() {
+            let matched_jmp = self.push_instr(RegInstr::Jmp { offset: 0 }, span);
+            for (fail, cond) in fails {
+                let offset = self.forward_jmp_offset(fail);
+                self.change_instr(fail, RegInstr::JmpFalse { cond, offset });
+            }
+            self.push_instr(RegInstr::MatchFailed, span);
+
+            let offset = self.forward_jmp_offset(matched_jmp);
+            self.change_instr(matched_jmp, RegInstr::Jmp { offset });
+        }
+
+        Ok(())
+    }
+
+    fn compile_break(&mut self, span: Span) -> CResult {
+        let break_idx = self.push_instr(RegInstr::Jmp { offset: 0 }, span);
+        self.breaks
+            .entry(self.loop_nesting)
+            .or_default()
+            .push(break_idx);
+        Ok(())
+    }
+
+    fn compile_return(&mut self, expr: Option<&Expr>, span: Span) -> CResult {
+        let src = match expr {
+            // the global script can't return, so it has no registers that could be reused
+            // method calls look up the method in the call itself, so they are never tail calls
+            // a `?.` may skip the call, so the result has to be produced here
+            Some(Expr::Call(
+                call @ Call {
+                    kind: CallKind::Fn(args),
+                    ..
+                },
+            )) if self.current_block_idx != 0
+                && call.method_call().is_none()
+                && !has_null_safe_link(call)
+                && !has_spread(args) =>
+            {
+                let mut null_jumps = std::vec::Vec::new();
+                let (callee, args, count) =
+                    self.compile_call_operands(call, args, &mut null_jumps)?;
+                debug_assert!(null_jumps.is_empty());
+                self.push_instr(
+                    RegInstr::TailCall {
+                        callee,
+                        args,
+                        count,
+                    },
+                    span,
+                );
+                return Ok(());
+            }
+            Some(expr) => self.compile_expr(expr)?,
+            None => {
+                let reg = self.alloc_reg(span)?;
+                self.load_value(reg, Value::Null, span);
+                reg
+            }
+        };
+
+        self.push_instr(RegInstr::Return { src }, span);
+
+        Ok(())
+    }
+
+    fn compile_print(&mut self, expr: &Expr, span: Span) -> CResult {
+        let src = self.compile_expr(expr)?;
+        self.push_instr(RegInstr::Print { src }, span);
+        Ok(())
+    }
+
+    /// Compiles a block whose value is not used
+    fn compile_block(&mut self, block: &Block) -> CResult {
+        let next_env = Env::new_inner(self.env.clone(), OuterEnvKind::Block, 0);
+        self.env = next_env;
+        let block_start_reg = self.next_reg;
+
+        self.compile_stmts(block.stmts)?;
+        match &block.value {
+            // a trailing `if`, `match` or block doesn't need to produce a value nobody uses
+            Some(Expr::If(if_stmt)) => self.compile_if(if_stmt)?,
+            Some(Expr::Match(match_stmt)) => self.compile_match(match_stmt, None)?,
+            Some(Expr::Block(block)) => self.compile_block(block)?,
+            Some(value) => drop(self.compile_expr(value)?),
+            None => {}
+        }
+
+        // the locals of the block are gone
+        self.next_reg = block_start_reg;
+        let outer = self.env.borrow().outer.clone().expect("outer env got lost");
+        self.env = outer;
+        Ok(())
+    }
+
+    /// Compiles a block and writes its value to `dst`
+    fn compile_block_into(&mut self, block: &Block, dst: Reg) -> CResult {
+        let next_env = Env::new_inner(self.env.clone(), OuterEnvKind::Block, 0);
+        self.env = next_env;
+        let block_start_reg = self.next_reg;
+
+        self.compile_stmts(block.stmts)?;
+        match &block.value {
+            Some(value) => self.compile_expr_into(value, dst)?,
+            None => self.load_value(dst, Value::Null, block.span),
+        }
+
+        // the locals of the block are gone
+        self.next_reg = block_start_reg;
+        let outer = self.env.borrow().outer.clone().expect("outer env got lost");
+        self.env = outer;
+        Ok(())
+    }
+
+    /// Compiles the expression and returns the register that contains the value. Local variables
+    /// are used directly, everything else is computed into a new temporary register.
+    fn compile_expr(&mut self, expr: &Expr) -> CResult<Reg> {
+        if let Expr::Ident(name) = expr {
+            if let Ok(Binding::Local(reg)) = self.env.borrow().lookup(name) {
+                return Ok(reg as Reg);
+            }
+        }
+
+        let dst = self.alloc_reg(expr.span())?;
+        self.compile_expr_into(expr, dst)?;
+        Ok(dst)
+    }
+
+    /// Compiles the expression and writes the value to `dst`
+    fn compile_expr_into(&mut self, expr: &Expr, dst: Reg) -> CResult {
+        let temporaries_start = self.next_reg;
+
+        match expr {
+            Expr::Ident(inner) => self.compile_expr_ident(inner, dst),
+            Expr::Literal(inner) => self.compile_expr_literal(inner, dst),
+            Expr::UnaryOp(inner) => self.compile_expr_unary(inner, dst),
+            Expr::BinaryOp(inner) => self.compile_expr_binary(inner, dst),
+            Expr::Call(inner) => self.compile_expr_call(inner, dst),
+            Expr::If(inner) => self.compile_if_into(inner, dst),
+            Expr::Block(inner) => self.compile_block_into(inner, dst),
+            Expr::Match(inner) => self.compile_match(inner, Some(dst)),
+            Expr::Spread(_) => unreachable!("spreads only appear in the arguments of calls"),
+        }?;
+
+        self.next_reg = temporaries_start;
+        Ok(())
+    }
+
+    fn compile_expr_ident(&mut self, name: &Ident, dst: Reg) -> CResult {
+        let binding = self.env.borrow().lookup(name);
+
+        match binding {
+            Ok(Binding::Local(src)) => {
+                let src = src as Reg;
+                if src != dst {
+                    self.push_instr(RegInstr::Move { dst, src }, name.span);
+                }
+            }
+            Ok(Binding::Function(function)) => {
+                self.load_value(dst, Value::Function(function), name.span);
+            }
+            Ok(Binding::Const(value)) => self.load_value(dst, value, name.span),
+            Err(err) => {
+                // locals shadow the native functions
+                let native = native::lookup(name.sym.as_str()).ok_or(err)?;
+                self.load_value(dst, Value::NativeFunction(native), name.span);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile_expr_literal(&mut self, lit: &Literal, dst: Reg) -> CResult {
+        let value = match lit {
+            Literal::String(str, _) => Value::String(*str),
+            Literal::Number(num, _) => Value::Num(*num),
+            Literal::Int(int, _) => Value::Int(*int),
+            Literal::Array([], _) => Value::Array(self.rt.alloc_array(std::vec::Vec::new())),
+            Literal::Array(elements, span) => {
+                let temporaries_start = self.next_reg;
+                let args = self.next_reg as Reg;
+                for element in elements.iter() {
+                    let reg = self.alloc_reg(element.span())?;
+                    self.compile_expr_into(element, reg)?;
+                }
+                let count = elements
+                    .len()
+                    .try_into()
+                    .map_err(|_| CompilerError::new(*span, "Too many elements.".to_string()))?;
+                self.push_instr(RegInstr::NewArray { dst, args, count }, *span);
+                self.next_reg = temporaries_start;
+                return Ok(());
+            }
+            Literal::Object(span) => {
+                // objects are mutable, so every evaluation must create a new one
+                self.push_instr(RegInstr::NewObject { dst }, *span);
+                return Ok(());
+            }
+            Literal::Boolean(bool, _) => Value::Bool(*bool),
+            Literal::Null(_) => Value::Null,
+        };
+
+        self.load_value(dst, value, lit.span());
+
+        Ok(())
+    }
+
+    fn compile_expr_unary(&mut self, unary: &UnaryOp, dst: Reg) -> CResult {
+        let src = self.compile_expr(&unary.expr)?;
+
+        // not and neg compile to the same instruction
+        self.push_instr(RegInstr::Neg { dst, src }, unary.span);
+
+        Ok(())
+    }
+
+    fn compile_expr_binary(&mut self, binary: &BinaryOp, dst: Reg) -> CResult {
+        if let BinaryOpKind::Coalesce = binary.kind {
+            return self.compile_coalesce(&binary.lhs, &binary.rhs, dst, binary.span);
+        }
+
+        let lhs = self.compile_expr(&binary.lhs)?;
+        let rhs = self.compile_expr(&binary.rhs)?;
+
+        self.push_instr(binary_instr(binary.kind, dst, lhs, rhs), binary.span);
+
+        Ok(())
+    }
+
+    fn compile_coalesce(&mut self, lhs: &Expr, rhs: &Expr, dst: Reg, span: Span) -> CResult {
+        let lhs = self.compile_expr(lhs)?;
+        self.compile_default(lhs, rhs, dst, span)
+    }
+
+    /// Writes `lhs` to `dst`, or `rhs` if it is `null`, which is only evaluated then
+    fn compile_default(&mut self, lhs: Reg, rhs: &Expr, dst: Reg, span: Span) -> CResult {
+        let null_jmp = self.push_instr(
+            RegInstr::JmpNull {
+                src: lhs,
+                offset: 0,
+            },
+            span,
+        );
+        if lhs != dst {
+            self.push_instr(RegInstr::Move { dst, src: lhs }, span);
+        }
+        let end_jmp = self.push_instr(RegInstr::Jmp { offset: 0 }, span);
+
+        let offset = self.forward_jmp_offset(null_jmp);
+        self.change_instr(null_jmp, RegInstr::JmpNull { src: lhs, offset });
+        self.compile_expr_into(rhs, dst)?;
+
+        let offset = self.forward_jmp_offset(end_jmp);
+        self.change_instr(end_jmp, RegInstr::Jmp { offset });
+
+        Ok(())
+    }
+
+    fn compile_expr_call(&mut self, call: &Call, dst: Reg) -> CResult {
+        let mut null_jumps = std::vec::Vec::new();
+        self.compile_call_link(call, dst, &mut null_jumps)?;
+
+        if !null_jumps.is_empty() {
+            // every `?.` that found a null lands on the `null` result
+            let skip = self.push_instr(RegInstr::Jmp { offset: 0 }, call.span);
+            for (jmp, src) in null_jumps {
+                let offset = self.forward_jmp_offset(jmp);
+                self.change_instr(jmp, RegInstr::JmpNull { src, offset });
+            }
+            self.load_value(dst, Value::Null, call.span);
+            let offset = self.forward_jmp_offset(skip);
+            self.change_instr(skip, RegInstr::Jmp { offset });
+        }
+
+        Ok(())
+    }
+
+    /// Compiles one call or field of a chain like `a?.b.c()`. Every `?.` in the chain pushes a
+    /// `JmpNull` to the end of the whole chain into `null_jumps`, together with the register it
+    /// checks.
+    fn compile_call_link(
+        &mut self,
+        call: &Call,
+        dst: Reg,
+        null_jumps: &mut std::vec::Vec<(usize, Reg)>,
+    ) -> CResult {
+        let params = match call.kind {
+            CallKind::Field(field) => {
+                let object = self.compile_chain(&call.callee, null_jumps)?;
+                self.push_null_check(call, object, null_jumps);
+
+                let site = self.blocks[self.current_block_idx].add_field_site(field.sym);
+                self.push_instr(RegInstr::GetField { dst, object, site }, call.span);
+                return Ok(());
+            }
+            CallKind::Fn(params) => params,
+        };
+
+        if let Some((_, method, params)) = call.method_call() {
+            if call.null_safe {
+                return Err(CompilerError::with_note(
+                    call.callee.span().extend(call.span),
+                    "methods can't be called with `?.()`".to_string(),
+                    "use `object?.method()` to skip the call if the object is `null`".to_string(),
+                ));
+            }
+            let Expr::Call(link) = call.callee else {
+                unreachable!("method calls are called on a field")
+            };
+            return self.compile_method_call(link, method, params, dst, call.span, null_jumps);
+        }
+
+        if has_spread(params) {
+            let args = self.alloc_reg(call.span)?;
+            self.compile_spread_args(args, 0, params, call.span)?;
+            let callee = self.compile_chain(&call.callee, null_jumps)?;
+            self.push_null_check(call, callee, null_jumps);
+            self.push_instr(RegInstr::CallSpread { dst, callee, args }, call.span);
+            return Ok(());
+        }
+
+        let (callee, args, count) = self.compile_call_operands(call, params, null_jumps)?;
+
+        self.push_instr(
+            RegInstr::Call {
+                dst,
+                callee,
+                args,
+                count,
+            },
+            call.span,
+        );
+
+        Ok(())
+    }
+
+    /// Compiles the callee of a link, which continues the chain if it is a call itself
+    fn compile_chain(
+        &mut self,
+        expr: &Expr,
+        null_jumps: &mut std::vec::Vec<(usize, Reg)>,
+    ) -> CResult<Reg> {
+        match expr {
+            Expr::Call(_) => {
+                let dst = self.alloc_reg(expr.span())?;
+                self.compile_chain_into(expr, dst, null_jumps)?;
+                Ok(dst)
+            }
+            _ => self.compile_expr(expr),
+        }
+    }
+
+    /// Same as [`Self::compile_chain`], but writes the value to `dst`
+    fn compile_chain_into(
+        &mut self,
+        expr: &Expr,
+        dst: Reg,
+        null_jumps: &mut std::vec::Vec<(usize, Reg)>,
+    ) -> CResult {
+        let Expr::Call(call) = expr else {
+            return self.compile_expr_into(expr, dst);
+        };
+
+        let temporaries_start = self.next_reg;
+        self.compile_call_link(call, dst, null_jumps)?;
+        self.next_reg = temporaries_start;
+        Ok(())
+    }
+
+    /// For a `?.`, skips the rest of the chain if `src` is null
+    fn push_null_check(
+        &mut self,
+        call: &Call,
+        src: Reg,
+        null_jumps: &mut std::vec::Vec<(usize, Reg)>,
+    ) {
+        if call.null_safe {
+            let jmp = self.push_instr(RegInstr::JmpNull { src, offset: 0 }, call.span);
+            null_jumps.push((jmp, src));
+        }
+    }
+
+    /// Compiles the receiver and then the arguments into consecutive registers, the method is looked
+    /// up by the call itself
+    fn compile_method_call(
+        &mut self,
+        link: &Call,
+        method: Ident,
+        params: &[Expr],
+        dst: Reg,
+        span: Span,
+        null_jumps: &mut std::vec::Vec<(usize, Reg)>,
+    ) -> CResult {
+        if has_spread(params) {
+            // the receiver is the first element of the arguments
+            let args = self.alloc_reg(span)?;
+            let receiver = self.alloc_reg(link.callee.span())?;
+            self.compile_chain_into(&link.callee, receiver, null_jumps)?;
+            self.push_null_check(link, receiver, null_jumps);
+            self.compile_spread_args(args, 1, params, span)?;
+
+            let site = self.blocks[self.current_block_idx].add_field_site(method.sym);
+            self.push_instr(RegInstr::CallMethodSpread { dst, args, site }, span);
+            return Ok(());
+        }
+
+        // the receiver is the first argument
+        let count = (params.len() + 1)
+            .try_into()
+            .map_err(|_| CompilerError::new(span, "Too many arguments.".to_string()))?;
+
+        let args = self.next_reg as Reg;
+        let reg = self.alloc_reg(link.callee.span())?;
+        self.compile_chain_into(&link.callee, reg, null_jumps)?;
+        self.push_null_check(link, reg, null_jumps);
+        for param in params.iter() {
+            let reg = self.alloc_reg(param.span())?;
+            self.compile_expr_into(param, reg)?;
+        }
+
+        let site = self.blocks[self.current_block_idx].add_field_site(method.sym);
+        self.push_instr(
+            RegInstr::CallMethod {
+                dst,
+                args,
+                count,
+                site,
+            },
+            span,
+        );
+
+        Ok(())
+    }
+
+    /// Collects the arguments of a call with a spread into a new array in `array`, which must be
+    /// the last register in use. The `pushed` registers after it already hold its first elements.
+    fn compile_spread_args(
+        &mut self,
+        array: Reg,
+        pushed: u16,
+        params: &[Expr],
+        span: Span,
+    ) -> CResult {
+        let mut pending = pushed;
+        let mut has_array = false;
+
+        for param in params {
+            if let Expr::Spread(spread) = param {
+                self.collect_args(array, pending, has_array, span);
+                pending = 0;
+                has_array = true;
+
+                let src = self.compile_expr(&spread.expr)?;
+                self.push_instr(RegInstr::ExtendArray { array, src }, spread.span);
+                self.next_reg = array as usize + 1;
+            } else {
+                let reg = self.alloc_reg(param.span())?;
+                self.compile_expr_into(param, reg)?;
+                pending += 1;
+            }
+        }
+
+        if pending > 0 {
+            self.collect_args(array, pending, has_array, span);
+        }
+
+        Ok(())
+    }
+
+    /// Puts the `pending` values in the registers after `array` into an array, and appends it to
+    /// the one in `array` if there is one already
+    fn collect_args(&mut self, array: Reg, pending: u16, has_array: bool, span: Span) {
+        let args = array + 1;
+        let dst = if has_array { args } else { array };
+        self.push_instr(
+            RegInstr::NewArray {
+                dst,
+                args,
+                count: pending,
+            },
+            span,
+        );
+        if has_array {
+            self.push_instr(RegInstr::ExtendArray { array, src: args }, span);
+        }
+        self.next_reg = array as usize + 1;
+    }
+
+    /// Compiles the arguments into consecutive registers and the function, and returns the
+    /// register of the function, the first argument and the amount of arguments
+    fn compile_call_operands(
+        &mut self,
+        call: &Call,
+        params: &[Expr],
+        null_jumps: &mut std::vec::Vec<(usize, Reg)>,
+    ) -> CResult<(Reg, Reg, u16)> {
+        let count = params
+            .len()
+            .try_into()
+            .map_err(|_| CompilerError::new(call.span, "Too many arguments.".to_string()))?;
+
+        // the arguments must be next to each other at the top, they become the callee's registers
+        let args = self.next_reg as Reg;
+        for param in params.iter() {
+            let reg = self.alloc_reg(param.span())?;
+            self.compile_expr_into(param, reg)?;
+        }
+
+        let callee = self.compile_chain(&call.callee, null_jumps)?;
+        self.push_null_check(call, callee, null_jumps);
+
+        Ok((callee, args, count))
+    }
+
+    fn end_loop(&mut self) {
+        let breaks = self.breaks.remove(&self.loop_nesting);
+        if let Some(breaks) = breaks {
+            for brk in breaks {
+                let offset = self.forward_jmp_offset(brk);
+                self.change_instr(brk, RegInstr::Jmp { offset });
+            }
+        }
+        self.loop_nesting -= 1;
+    }
+
+    fn declare_local(&mut self, name: Symbol, reg: Reg) {
+        self.env.borrow_mut().locals.insert(name, reg as usize);
+        // a new variable with the same name is not constant
+        self.env.borrow_mut().constants.remove(&name);
+    }
+
+    /// Takes the next free register
+    fn alloc_reg(&mut self, span: Span) -> CResult<Reg> {
+        let reg = Reg::try_from(self.next_reg).map_err(|_| {
+            CompilerError::new(span, "Too many variables in a single function.".to_string())
+        })?;
+        self.next_reg += 1;
+
+        let block = &mut self.blocks[self.current_block_idx];
+        block.registers = block.registers.max(self.next_reg);
+
+        Ok(reg)
+    }
+
+    fn load_value(&mut self, dst: Reg, value: Value, span: Span) {
+        let index = self.blocks[self.current_block_idx].add_const(value);
+        self.push_instr(RegInstr::LoadConst { dst, index }, span);
+    }
+
+    /// target is implicitly: self.code_len()
+    fn back_jmp_offset(&self, target: usize) -> i32 {
+        let source = self.code_len();
+        -((source - target + 1) as i32)
+    }
+
+    /// target is implicitly: self.code_len()
+    fn forward_jmp_offset(&self, source: usize) -> i32 {
+        let target = self.code_len();
+        (target - source - 1) as i32
+    }
+
+    fn code_len(&self) -> usize {
+        self.blocks[self.current_block_idx].code.len()
+    }
+
+    fn change_instr(&mut self, index: usize, instr: RegInstr) {
+        self.blocks[self.current_block_idx].code[index] = instr;
+    }
+
+    /// Pushes an instruction and returns the index of the new instruction
+    fn push_instr(&mut self, instr: RegInstr, span: Span) -> usize {
+        let block = &mut self.blocks[self.current_block_idx];
+        block.code.push(instr);
+        block.spans.push(span);
+        block.code.len() - 1
+    }
+}
+
+fn binary_instr(kind: BinaryOpKind, dst: Reg, lhs: Reg, rhs: Reg) -> RegInstr {
+    match kind {
+        BinaryOpKind::Add => RegInstr::Add { dst, lhs, rhs },
+        BinaryOpKind::And => RegInstr::And { dst, lhs, rhs },
+        BinaryOpKind::Or => RegInstr::Or { dst, lhs, rhs },
+        BinaryOpKind::Equal => RegInstr::Eq { dst, lhs, rhs },
+        BinaryOpKind::GreaterEqual => RegInstr::GreaterEq { dst, lhs, rhs },
+        BinaryOpKind::Greater => RegInstr::Greater { dst, lhs, rhs },
+        BinaryOpKind::LessEqual => RegInstr::LessEq { dst, lhs, rhs },
+        BinaryOpKind::Less => RegInstr::Less { dst, lhs, rhs },
+        BinaryOpKind::NotEqual => RegInstr::NotEq { dst, lhs, rhs },
+        BinaryOpKind::Sub => RegInstr::Sub { dst, lhs, rhs },
+        BinaryOpKind::Mul => RegInstr::Mul { dst, lhs, rhs },
+        BinaryOpKind::Div => RegInstr::Div { dst, lhs, rhs },
+        BinaryOpKind::IntDiv => RegInstr::IntDiv { dst, lhs, rhs },
+        BinaryOpKind::Mod => RegInstr::Mod { dst, lhs, rhs },
+        BinaryOpKind::BitAnd => RegInstr::BitAnd { dst, lhs, rhs },
+        BinaryOpKind::BitOr => RegInstr::BitOr { dst, lhs, rhs },
+        BinaryOpKind::BitXor => RegInstr::BitXor { dst, lhs, rhs },
+        BinaryOpKind::Shl => RegInstr::Shl { dst, lhs, rhs },
+        BinaryOpKind::Shr => RegInstr::Shr { dst, lhs, rhs },
+        BinaryOpKind::Coalesce => unreachable!("`??` short-circuits"),
+    }
+}
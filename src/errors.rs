@@ -4,11 +4,11 @@
 //! The `span` submodule handles Spans, which are used for tracking locations in the source code.
 //!
 //! There is a single type `CompilerError` that can be created from anywhere, and reported using
-//! functions from here.
+//! functions from here. Warnings use the same type, but they don't stop the compilation.
 
-use std::fmt::Debug;
+use std::{fmt::Debug, io::Write};
 
-pub use span::Span;
+pub use span::{LineIndex, Span};
 
 mod span {
     use std::fmt::{Debug, Formatter};
@@ -75,6 +75,31 @@ mod span {
         }
     }
 
+    /// Maps byte offsets in the source code to line numbers
+    #[derive(Debug, Clone)]
+    pub struct LineIndex {
+        line_starts: Vec<usize>,
+    }
+
+    impl LineIndex {
+        pub fn new(source: &str) -> Self {
+            let line_starts = std::iter::once(0)
+                .chain(source.match_indices('\n').map(|(idx, _)| idx + 1))
+                .collect();
+            Self { line_starts }
+        }
+
+        /// The 1-based line number of the byte offset
+        pub fn line(&self, offset: usize) -> usize {
+            self.line_starts.partition_point(|&start| start <= offset)
+        }
+
+        /// The amount of lines in the source code
+        pub fn line_count(&self) -> usize {
+            self.line_starts.len()
+        }
+    }
+
     #[cfg(feature = "_debug")]
     impl dbg_pls::DebugPls for Span {
         fn fmt(&self, f: dbg_pls::Formatter<'_>) {
@@ -91,6 +116,8 @@ pub struct CompilerError {
     pub span: Span,
     pub message: String,
     pub note: Option<String>,
+    /// The code that the note is about, which is shown below it
+    pub note_span: Option<Span>,
 }
 
 impl CompilerError {
@@ -99,6 +126,7 @@ impl CompilerError {
             span,
             message,
             note: None,
+            note_span: None,
         }
     }
 
@@ -107,41 +135,92 @@ impl CompilerError {
             span,
             message,
             note: Some(note),
+            note_span: None,
+        }
+    }
+
+    /// Points the note at other code, like the declaration that the error is about
+    pub fn note_at(self, note_span: Span) -> Self {
+        Self {
+            note_span: Some(note_span),
+            ..self
         }
     }
 }
 
-pub fn display_error(source: &str, error: CompilerError) {
-    let span = error.span;
+pub fn display_error(source: &str, error: CompilerError, w: &mut dyn Write) {
+    // if we can't even write the error, there's nothing left to report it to
+    let _ = write_diagnostic(source, "error", RED, error, w);
+}
+
+pub fn display_warning(source: &str, warning: CompilerError, w: &mut dyn Write) {
+    let _ = write_diagnostic(source, "warning", YELLOW, warning, w);
+}
 
+fn write_diagnostic(
+    source: &str,
+    level: &str,
+    color: &str,
+    error: CompilerError,
+    w: &mut dyn Write,
+) -> std::io::Result<()> {
+    let Some(snippet) = find_line(source, error.span) else {
+        return Ok(());
+    };
+
+    writeln!(w, "{}{}: {}{}", color, level, error.message, RESET)?;
+    writeln!(w, "      {}|{}", CYAN, RESET)?;
+    write_snippet(snippet, error.span, color, w)?;
+    if let Some(note) = error.note {
+        writeln!(w, "      {}|{}", CYAN, RESET)?;
+        writeln!(
+            w,
+            "      {}|{}   {}note: {}{}",
+            CYAN, RESET, GREEN, note, RESET
+        )?;
+        if let Some((span, snippet)) = error
+            .note_span
+            .and_then(|span| Some((span, find_line(source, span)?)))
+        {
+            writeln!(w, "      {}|{}", CYAN, RESET)?;
+            write_snippet(snippet, span, GREEN, w)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The line that contains the start of the span, with its index and the offset of the line
+fn find_line(source: &str, span: Span) -> Option<(usize, &str, usize)> {
     let mut chars = 0;
-    let lines = source.split_inclusive('\n').enumerate();
-    for (idx, line) in lines {
+    for (idx, line) in source.split_inclusive('\n').enumerate() {
         if chars + line.len() > span.start {
-            let offset_on_line = span.start - chars;
-
-            eprintln!("{}error: {}{}", RED, error.message, RESET);
-            eprintln!("      {}|{}", CYAN, RESET);
-            eprintln!("{}{:>5} |{} {}", CYAN, idx + 1, RESET, line);
-            eprint!("      {}|{} ", CYAN, RESET);
-            eprintln!(
-                "{}{}{}{}",
-                " ".repeat(offset_on_line),
-                RED,
-                "^".repeat(span.len()),
-                RESET,
-            );
-            if let Some(note) = error.note {
-                eprintln!("      {}|{}", CYAN, RESET);
-                eprintln!(
-                    "      {}|{}   {}note: {}{}",
-                    CYAN, RESET, GREEN, note, RESET
-                );
-            }
-            break;
+            return Some((idx, line, chars));
         }
         chars += line.len();
     }
+    None
+}
+
+/// Writes the line and marks the span on it
+fn write_snippet(
+    (idx, line, line_start): (usize, &str, usize),
+    span: Span,
+    color: &str,
+    w: &mut dyn Write,
+) -> std::io::Result<()> {
+    let offset_on_line = span.start - line_start;
+
+    writeln!(w, "{}{:>5} |{} {}", CYAN, idx + 1, RESET, line)?;
+    write!(w, "      {}|{} ", CYAN, RESET)?;
+    writeln!(
+        w,
+        "{}{}{}{}",
+        " ".repeat(offset_on_line),
+        color,
+        "^".repeat(span.len()),
+        RESET,
+    )
 }
 
 macro_rules! color {
@@ -154,3 +233,4 @@ color!(RED: "0;31m");
 color!(RESET: "0m");
 color!(CYAN: "0;36m");
 color!(GREEN: "0;32m");
+color!(YELLOW: "0;33m");
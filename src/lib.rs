@@ -3,19 +3,25 @@
 mod compile;
 mod errors;
 mod runtime;
+#[cfg(feature = "serde")]
+pub mod serde;
 mod syntax;
 mod util;
 
-use std::io::Write;
+use std::io::{BufRead, Write};
 
 pub use bumpalo::Bump;
 
 use crate::{
     errors::CompilerError,
+    runtime::bytecode::{serialize, FnBlock},
     syntax::{ast::Program, lex, parse},
 };
 pub use crate::{
-    runtime::gc::RtAlloc,
+    runtime::{
+        gc::{Array, Object, RtAlloc, Symbol},
+        vm::Value,
+    },
     syntax::{lex::*, parse::*},
 };
 
@@ -34,15 +40,39 @@ type HashSet<T> = std::collections::HashSet<T>;
 type HashSet<T> = rustc_hash::FxHashSet<T>;
 
 pub struct Config<'io> {
+    /// The name of the script, used in reports
+    pub filename: &'io str,
     pub debug: bool,
+    /// Run the program in the interactive debugger
     pub step: bool,
+    /// Print a report about where the program spent its time to stderr
+    pub profile: bool,
+    /// If profiling, also write the folded call stacks for flamegraphs here
+    pub profile_folded: Option<&'io mut dyn Write>,
+    /// Print a summary of the executed lines to stderr
+    pub coverage: bool,
+    /// If collecting coverage, also write an lcov report here
+    pub coverage_lcov: Option<&'io mut dyn Write>,
     pub parse_only: bool,
+    /// Optimize the bytecode after compiling it
+    pub optimize: bool,
+    /// Run the program with the register based VM instead of the stack based VM. It doesn't
+    /// support the debugger, profiler, coverage, optimizations or bytecode files.
+    pub register: bool,
     pub stdout: &'io mut dyn Write,
+    /// Where `input()` and the step debugger read from
+    pub stdin: &'io mut dyn BufRead,
+    /// Where errors and debug output are written to
+    pub stderr: &'io mut dyn Write,
 }
 
 pub fn run_program(program: &str, cfg: &mut Config) {
     if cfg.debug {
-        eprintln!("Config: debug: {}, step: {}", cfg.debug, cfg.step);
+        let _ = writeln!(
+            cfg.stderr,
+            "Config: debug: {}, step: {}",
+            cfg.debug, cfg.step
+        );
     }
 
     let ast_alloc = Bump::new();
@@ -51,7 +81,9 @@ pub fn run_program(program: &str, cfg: &mut Config) {
     let mut runtime = unsafe { RtAlloc::new() };
 
     let lexer = Lexer::new(program, &mut runtime);
-    let ast = parse::parse(lexer, &ast_alloc);
+    let mut warnings = Vec::new();
+    let ast = parse::parse(lexer, &ast_alloc, &mut warnings);
+    display_warnings(program, warnings, cfg);
 
     match ast {
         Ok(ast) => {
@@ -60,31 +92,145 @@ pub fn run_program(program: &str, cfg: &mut Config) {
             }
             process_ast(program, &ast, runtime, cfg)
         }
-        Err(err) => errors::display_error(program, err),
+        Err(err) => errors::display_error(program, err, cfg.stderr),
+    }
+}
+
+fn display_warnings(program: &str, warnings: Vec<CompilerError>, cfg: &mut Config<'_>) {
+    for warning in warnings {
+        errors::display_warning(program, warning, cfg.stderr);
     }
 }
 
 fn process_ast(program: &str, ast: &Program, mut runtime: RtAlloc, cfg: &mut Config<'_>) {
     if cfg.debug {
-        util::dbg("AST:\n", ast);
+        util::dbg(cfg.stderr, "AST:\n", ast);
     }
 
     let bytecode_alloc = Bump::new();
 
-    let bytecode = compile::compile(ast, &bytecode_alloc, &mut runtime);
+    if cfg.register {
+        return process_ast_register(program, ast, runtime, &bytecode_alloc, cfg);
+    }
+
+    let bytecode = compile::compile(ast, &bytecode_alloc, &mut runtime, cfg.optimize);
 
     match bytecode {
         Ok(code) => {
             if cfg.debug {
-                util::dbg("Bytecode:\n", code);
+                util::dbg(cfg.stderr, "Bytecode:\n", code);
             }
 
-            let result = runtime::vm::execute(code, runtime, cfg);
-            if let Err(msg) = result {
-                eprintln!("error: {msg}");
+            execute(code, runtime, program, cfg);
+        }
+        Err(err) => errors::display_error(program, err, cfg.stderr),
+    }
+}
+
+fn process_ast_register(
+    program: &str,
+    ast: &Program,
+    mut runtime: RtAlloc,
+    bytecode_alloc: &Bump,
+    cfg: &mut Config<'_>,
+) {
+    if cfg.step || cfg.profile || cfg.coverage {
+        let _ = writeln!(
+            cfg.stderr,
+            "error: the register VM doesn't support the debugger, profiler or coverage"
+        );
+        return;
+    }
+
+    match compile::register::compile(ast, bytecode_alloc, &mut runtime) {
+        Ok(code) => {
+            if cfg.debug {
+                util::dbg(cfg.stderr, "Bytecode:\n", code);
+            }
+
+            if let Err(msg) = runtime::register_vm::execute(code, runtime, cfg) {
+                let _ = writeln!(cfg.stderr, "error: {msg}");
             }
         }
-        Err(err) => errors::display_error(program, err),
+        Err(err) => errors::display_error(program, err, cfg.stderr),
+    }
+}
+
+/// Compiles the program and serializes the bytecode, so that it can be run with [`run_bytecode`]
+/// later. Errors are written to `cfg.stderr`.
+pub fn compile_program(program: &str, cfg: &mut Config) -> Option<Vec<u8>> {
+    let ast_alloc = Bump::new();
+
+    // SAFETY: Just this scope
+    let mut runtime = unsafe { RtAlloc::new() };
+
+    let lexer = Lexer::new(program, &mut runtime);
+    let mut warnings = Vec::new();
+    let ast = parse::parse(lexer, &ast_alloc, &mut warnings);
+    display_warnings(program, warnings, cfg);
+    let ast = match ast {
+        Ok(ast) => ast,
+        Err(err) => {
+            errors::display_error(program, err, cfg.stderr);
+            return None;
+        }
+    };
+
+    let bytecode_alloc = Bump::new();
+    match compile::compile(&ast, &bytecode_alloc, &mut runtime, cfg.optimize) {
+        Ok(code) => Some(serialize::serialize(code, program)),
+        Err(err) => {
+            errors::display_error(program, err, cfg.stderr);
+            None
+        }
+    }
+}
+
+/// Whether the file contains bytecode from [`compile_program`] instead of source code
+pub fn is_bytecode(file: &[u8]) -> bool {
+    serialize::is_bytecode(file)
+}
+
+/// Runs bytecode that was created by [`compile_program`]
+pub fn run_bytecode(bytecode: &[u8], cfg: &mut Config) {
+    if cfg.register {
+        let _ = writeln!(
+            cfg.stderr,
+            "error: bytecode files can only be run by the stack VM"
+        );
+        return;
+    }
+
+    let bytecode_alloc = Bump::new();
+
+    // SAFETY: I will try to 🥺
+    let mut runtime = unsafe { RtAlloc::new() };
+
+    let loaded = match serialize::deserialize(bytecode, &bytecode_alloc, &mut runtime) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            let _ = writeln!(cfg.stderr, "error: {err}");
+            return;
+        }
+    };
+
+    if cfg.debug {
+        util::dbg(cfg.stderr, "Bytecode:\n", loaded.blocks);
+    }
+
+    execute(loaded.blocks, runtime, &loaded.source, cfg);
+}
+
+/// Verifies the bytecode and runs it
+fn execute<'bc>(code: &'bc [FnBlock<'bc>], runtime: RtAlloc, source: &str, cfg: &mut Config) {
+    if let Err(err) = runtime::verify::verify(code) {
+        let _ = writeln!(cfg.stderr, "error: {err}");
+        return;
+    }
+
+    let result = runtime::vm::execute(code, runtime, source, cfg);
+    if let Err(msg) = result {
+        let _ = writeln!(cfg.stderr, "error: {msg}");
     }
 }
 
@@ -97,11 +243,11 @@ pub fn _fuzz_compile(program: &str) {
     let ast_alloc = Bump::new();
 
     let lexer = lex::Lexer::new(program, &mut runtime);
-    let ast = parse::parse(lexer, &ast_alloc);
+    let ast = parse::parse(lexer, &ast_alloc, &mut Vec::new());
 
     if let Ok(ast) = ast {
         let bytecode_alloc = Bump::new();
-        let _bytecode = compile::compile(&ast, &bytecode_alloc, &mut runtime);
+        let _bytecode = compile::compile(&ast, &bytecode_alloc, &mut runtime, true);
     }
 }
 
@@ -112,7 +258,7 @@ pub fn _fuzz_parse(program: &str) {
     let ast_alloc = Bump::new();
 
     let lexer = lex::Lexer::new(program, &mut runtime);
-    let _ast = parse::parse(lexer, &ast_alloc);
+    let _ast = parse::parse(lexer, &ast_alloc, &mut Vec::new());
 }
 
 #[doc(hidden)]
@@ -130,5 +276,5 @@ pub fn _parse<'ast>(
     rt_alloc: &mut RtAlloc,
 ) -> Result<Program<'ast>, CompilerError> {
     let lex = Lexer::new(program, rt_alloc);
-    parse(lex, alloc)
+    parse(lex, alloc, &mut Vec::new())
 }
@@ -1,4 +1,7 @@
-use std::io;
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+};
 
 use dilaria::Config;
 
@@ -6,33 +9,165 @@ fn main() {
     let mut args = std::env::args();
 
     if let Some(filename) = args.nth(1) {
+        if filename == "compile" {
+            compile(args);
+            return;
+        }
+
         let mut stdout = io::stdout();
+        let mut stdin = io::stdin().lock();
+        let mut stderr = io::stderr();
 
-        let mut cfg = Config {
-            debug: false,
-            step: false,
-            parse_only: false,
-            stdout: &mut stdout,
-        };
+        let mut debug = false;
+        let mut step = false;
+        let mut profile = false;
+        let mut coverage = false;
+        let mut parse_only = false;
+        let mut optimize = false;
+        let mut register = false;
+        let mut profile_folded = None;
+        let mut coverage_lcov = None;
 
-        for arg in args {
+        while let Some(arg) = args.next() {
             match &*arg {
-                "--debug" => cfg.debug = true,
-                "--step" => cfg.step = true,
-                "--parse-only" => cfg.parse_only = true,
+                "--debug" => debug = true,
+                "--step" => step = true,
+                "--profile" => profile = true,
+                "--profile-folded" => {
+                    match create_output(&arg, args.next()) {
+                        Some(file) => profile_folded = Some(file),
+                        None => return,
+                    }
+                    profile = true;
+                }
+                "--coverage" => coverage = true,
+                "--coverage-lcov" => {
+                    match create_output(&arg, args.next()) {
+                        Some(file) => coverage_lcov = Some(file),
+                        None => return,
+                    }
+                    coverage = true;
+                }
+                "--parse-only" => parse_only = true,
+                "-O" => optimize = true,
+                "--register" => register = true,
                 _ => {}
             }
         }
 
-        match std::fs::read_to_string(filename) {
-            Ok(contents) => {
-                dilaria::run_program(&contents, &mut cfg);
+        let mut cfg = Config {
+            filename: &filename,
+            debug,
+            step,
+            profile,
+            profile_folded: profile_folded
+                .as_mut()
+                .map(|file| file as &mut dyn io::Write),
+            coverage,
+            coverage_lcov: coverage_lcov
+                .as_mut()
+                .map(|file| file as &mut dyn io::Write),
+            parse_only,
+            optimize,
+            register,
+            stdout: &mut stdout,
+            stdin: &mut stdin,
+            stderr: &mut stderr,
+        };
+
+        match std::fs::read(&filename) {
+            Ok(contents) if dilaria::is_bytecode(&contents) => {
+                dilaria::run_bytecode(&contents, &mut cfg);
             }
+            Ok(contents) => match String::from_utf8(contents) {
+                Ok(contents) => dilaria::run_program(&contents, &mut cfg),
+                Err(_) => eprintln!("{}: file is not valid UTF-8", filename),
+            },
             Err(err) => {
                 eprintln!("{}", err);
             }
         }
     } else {
         eprintln!("Usage: <filename>");
+        eprintln!("       compile <filename> [-O] [-o <output>]");
+    }
+}
+
+/// `compile foo.dil -O -o foo.dilc`, the output defaults to the input with a `.dilc` extension
+fn compile(mut args: impl Iterator<Item = String>) {
+    let filename = match args.next() {
+        Some(filename) => filename,
+        None => {
+            eprintln!("Usage: compile <filename> [-O] [-o <output>]");
+            return;
+        }
+    };
+
+    let mut output = None;
+    let mut optimize = false;
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "-o" => output = args.next(),
+            "-O" => optimize = true,
+            _ => {}
+        }
+    }
+    let output = output.unwrap_or_else(|| {
+        std::path::Path::new(&filename)
+            .with_extension("dilc")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    let contents = match std::fs::read_to_string(&filename) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+
+    let mut stdout = io::stdout();
+    let mut stdin = io::empty();
+    let mut stderr = io::stderr();
+    let mut cfg = Config {
+        filename: &filename,
+        debug: false,
+        step: false,
+        profile: false,
+        profile_folded: None,
+        coverage: false,
+        coverage_lcov: None,
+        parse_only: false,
+        optimize,
+        register: false,
+        stdout: &mut stdout,
+        stdin: &mut stdin,
+        stderr: &mut stderr,
+    };
+
+    if let Some(bytecode) = dilaria::compile_program(&contents, &mut cfg) {
+        if let Err(err) = std::fs::write(&output, bytecode) {
+            eprintln!("{}: {}", output, err);
+        }
+    }
+}
+
+/// Creates the file that is passed after an option like `--profile-folded`
+fn create_output(option: &str, path: Option<String>) -> Option<BufWriter<File>> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: {} <filename>", option);
+            return None;
+        }
+    };
+
+    match File::create(&path) {
+        Ok(file) => Some(BufWriter::new(file)),
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            None
+        }
     }
 }
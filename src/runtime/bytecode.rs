@@ -10,6 +10,17 @@
 //! Note: Because of closures, function blocks have more required inputs than just the parameters,
 //! but the compiler should handle that correctly.
 //!
+//! ## Constants
+//! Values are not stored in the instructions, since that would make every instruction as big as a
+//! `Value`. Every block has its own constant pool instead, and `PushConst` refers to an index
+//! into it. `null`, `true` and `false` have their own instructions.
+//!
+//! ## Field sites
+//! `GetField`, `SetField`, `CallMethod` and `HasField` refer to a [`FieldSite`] of the block,
+//! which holds the name of the field and the inline cache of that instruction. Every field
+//! instruction has its own site, since different places in the code see differently shaped
+//! objects.
+//!
 //! ## Local offsets
 //! Variables offsets are calculated as `local offsets`. Local offsets are calculated relative to
 //! the start of the space of the stack required by that function. The interpreter must keep track
@@ -20,18 +31,36 @@
 //! to the length before the call. This means the interpreter has to do some bookkeeping, but it has
 //! to do that anyways.
 //!
-//! It is the compilers job to generate the correct loading of the arguments and assure that the arity
-//! is correct before the `Call` instruction.
+//! It is the compilers job to generate the correct loading of the arguments before the `Call`
+//! instruction. The VM checks the amount of arguments, fills missing ones with `null` and collects
+//! the rest into an array, so the stack frame always has `arity` parameters. Defaults are filled in
+//! by the function itself.
 //!
 //! See [`stack_frame`](`super::stack_frame`) for mode details
+//!
+//! # Serialization
+//! Compiled bytecode can be written to a file and loaded again, see [`serialize`]
+//!
+//! # Registers
+//! There is a second, register based instruction set for comparison, see [`register`]
 
-use std::fmt::{Debug, Formatter};
+pub mod register;
+pub mod serialize;
+
+use std::{
+    cell::Cell,
+    fmt::{Debug, Formatter},
+};
 
 use bumpalo::collections::Vec;
 
 use crate::{
     errors::Span,
-    runtime::{gc::Symbol, vm::Value},
+    runtime::{
+        gc::{shape::FieldCache, Symbol},
+        vm::Value,
+    },
+    util,
 };
 
 /// This struct contains all data for a function.
@@ -39,15 +68,101 @@ pub struct FnBlock<'bc> {
     pub name: Symbol,
     /// The bytecode of the function
     pub code: Vec<'bc, Instr>,
+    /// The constant pool of the function, see `PushConst`
+    pub consts: Vec<'bc, Value>,
+    /// The fields accessed by `GetField` and `SetField`
+    pub field_sites: Vec<'bc, FieldSite>,
     /// The sizes of the stack required by the function after the instruction at the same index.
     /// This is only used during compilation to calculate local variable offsets.
     pub stack_sizes: Vec<'bc, usize>,
     /// The corresponding source code location of each instruction. This is debuginfo and only
-    /// used if there are errors.
+    /// used if there are errors or for debugging.
     pub spans: Vec<'bc, Span>,
-    /// How many parameters the function accepts.
+    /// All local variables of the function. This is debuginfo and only used for debugging.
+    pub locals: Vec<'bc, LocalVar>,
+    /// How many parameters the function accepts, including the rest parameter.
     /// Yes, it supports 4294967295 parameters. I dare you to overflow that.
     pub arity: u32,
+    /// How many arguments a call needs at least, the parameters after these have a default
+    pub required: u32,
+    /// Whether the last parameter collects the remaining arguments into an array
+    pub variadic: bool,
+    /// Whether the function was declared as a method `fn X.m(self)`, only methods get the receiver
+    /// of `CallMethod` as their first argument
+    pub is_method: bool,
+}
+
+impl FnBlock<'_> {
+    /// The instruction that pushes the value, which adds it to the constant pool if needed
+    pub fn push_value_instr(&mut self, value: Value) -> Instr {
+        match value {
+            Value::Null => Instr::PushNull,
+            Value::Bool(true) => Instr::PushTrue,
+            Value::Bool(false) => Instr::PushFalse,
+            value => Instr::PushConst(self.add_const(value)),
+        }
+    }
+
+    /// Adds the value to the constant pool and returns its index, see [`add_const`]
+    pub fn add_const(&mut self, value: Value) -> u32 {
+        add_const(&mut self.consts, value)
+    }
+
+    /// Adds a new field site and returns its index, see [`add_field_site`]
+    pub fn add_field_site(&mut self, name: Symbol) -> u32 {
+        add_field_site(&mut self.field_sites, name)
+    }
+
+    /// The value that the instruction pushes, if it pushes a constant
+    pub fn pushed_value(&self, instr: Instr) -> Option<Value> {
+        match instr {
+            Instr::PushConst(index) => Some(self.consts[index as usize]),
+            Instr::PushNull => Some(Value::Null),
+            Instr::PushTrue => Some(Value::Bool(true)),
+            Instr::PushFalse => Some(Value::Bool(false)),
+            _ => None,
+        }
+    }
+}
+
+/// Adds the value to the constant pool and returns its index. Constants that are the same are
+/// only stored once, arrays and objects are always added since they have an identity.
+pub fn add_const(consts: &mut Vec<'_, Value>, value: Value) -> u32 {
+    let existing = consts.iter().position(|existing| match (existing, value) {
+        (Value::Num(a), Value::Num(b)) => a.to_bits() == b.to_bits(),
+        (Value::Int(a), Value::Int(b)) => *a == b,
+        (Value::String(a), Value::String(b)) => *a == b,
+        (Value::Function(a), Value::Function(b)) => *a == b,
+        (Value::NativeFunction(a), Value::NativeFunction(b)) => *a == b,
+        (Value::Null, Value::Null) => true,
+        (Value::Bool(a), Value::Bool(b)) => *a == b,
+        _ => false,
+    });
+
+    let index = existing.unwrap_or_else(|| {
+        consts.push(value);
+        consts.len() - 1
+    });
+    index.try_into().expect("more than u32::MAX constants")
+}
+
+/// A field access in the code, see the [module docs](self)
+#[derive(Debug)]
+pub struct FieldSite {
+    pub name: Symbol,
+    /// The inline cache, it is empty until the instruction runs
+    pub cache: Cell<Option<FieldCache>>,
+}
+
+/// Adds a new field site with an empty cache and returns its index
+pub fn add_field_site(field_sites: &mut Vec<'_, FieldSite>, name: Symbol) -> u32 {
+    field_sites.push(FieldSite {
+        name,
+        cache: Cell::new(None),
+    });
+    (field_sites.len() - 1)
+        .try_into()
+        .expect("more than u32::MAX field sites")
 }
 
 impl Debug for FnBlock<'_> {
@@ -56,6 +171,26 @@ impl Debug for FnBlock<'_> {
     }
 }
 
+/// Debuginfo about a local variable of a function
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub struct LocalVar {
+    pub name: Symbol,
+    /// The local offset of the variable
+    pub offset: usize,
+    /// The index of the first instruction where the variable is in scope
+    pub start: usize,
+    /// The index of the first instruction where the variable is out of scope again
+    pub end: usize,
+}
+
+impl LocalVar {
+    /// Whether the variable is in scope for the instruction at `index`
+    pub fn is_live(&self, index: usize) -> bool {
+        (self.start..self.end).contains(&index)
+    }
+}
+
 /// Index into the block list
 pub type Function = usize;
 
@@ -71,19 +206,70 @@ pub enum Instr {
     Store(usize),
     /// Load the variable value from the local offset `usize` onto the stack
     Load(usize),
-    /// Push a value onto the stack
-    PushVal(Value),
+    /// Push the constant with the index `u32` from the constant pool of the function
+    PushConst(u32),
+    /// Push `null`
+    PushNull,
+    /// Push `true`
+    PushTrue,
+    /// Push `false`
+    PushFalse,
+    /// Push the value on top of the stack again
+    Dup,
     /// Negate the top value on the stack. Only works with numbers and booleans
     Neg,
+    /// Push a new empty object
+    NewObject,
+    /// Replace the `u32` values on top of the stack with an array of them, the first one is the
+    /// lowest on the stack
+    NewArray(u32),
+    /// Replace the two arrays on top of the stack with a new array of the elements of both. Errors
+    /// if the value on top is not an array
+    ExtendArray,
+    /// Replace the object on top of the stack with the value of its field from the field site with
+    /// the index `u32`
+    GetField(u32),
+    /// Set the field from the field site with the index `u32` on the object below the top of the
+    /// stack to the value on top of the stack, popping both
+    SetField(u32),
 
-    // The binary operations. The `rhs` is on top of the stack, and `lhs` is below it
+    // The instructions for `match`. The tests never fail, values of the wrong type just don't match
+    /// Replace the value on top of the stack with whether it is equal to the constant with the
+    /// index `u32`. Values of different types are not equal.
+    MatchConst(u32),
+    /// Replace the value on top of the stack with whether it is an array with exactly `u32`
+    /// elements
+    MatchArray(u32),
+    /// Replace the value on top of the stack with whether it is an array with at least `u32`
+    /// elements
+    MatchArrayMin(u32),
+    /// Replace the value on top of the stack with whether it is an object that has the field from
+    /// the field site with the index `u32`, either itself or through its prototype
+    HasField(u32),
+    /// Replace the array on top of the stack with its element at the index `u32`
+    GetElement(u32),
+    /// Replace the array on top of the stack with a new array of its elements from the index `u32`
+    /// on
+    SliceArray(u32),
+    /// Error because a value doesn't match the pattern of `let` or a parameter, which is reached
+    /// when one of its tests fails
+    MatchFailed,
+
+    // The binary operations. The `rhs` is on top of the stack, and `lhs` is below it. If one of
+    // them is a float and the other an int, the int is converted to a float
     BinAdd,
     BinSub,
     BinMul,
     BinDiv,
+    BinIntDiv,
     BinMod,
     BinAnd,
     BinOr,
+    BinBitAnd,
+    BinBitOr,
+    BinBitXor,
+    BinShl,
+    BinShr,
     CmpGreater,
     CmpGreaterEq,
     CmpLess,
@@ -98,11 +284,30 @@ pub enum Instr {
     JmpFalse(isize),
     /// Same as `JmpFalse`, but unconditional
     Jmp(isize),
+    /// If the current stack value is null, skip `usize` instructions. The value stays on the stack
+    JmpNull(isize),
 
-    /// Calls the function at the top of the stack, after the parameters
-    Call,
+    /// Calls the function at the top of the stack, after the `u32` arguments
+    Call(u32),
+    /// Calls the function at the top of the stack with the elements of the array below it as the
+    /// arguments
+    CallSpread,
+    /// Same as `CallMethod`, but with the elements of the array on top of the stack as the
+    /// arguments. The first element is the receiver
+    CallMethodSpread(u32),
+    /// Calls the method from the field site `site` on the object below the `args` arguments. The
+    /// object is passed as the first argument `self` if the function is a method, other functions
+    /// are called without it
+    CallMethod {
+        site: u32,
+        args: u32,
+    },
     /// Returns from the function, removing that stack frame
     Return,
+    /// Calls the function at the top of the stack, after the `u32` arguments, and returns its
+    /// return value. The stack frame of the current function is reused for the callee, see
+    /// [`stack_frame`](crate::runtime::stack_frame)
+    TailCall(u32),
 
     /// Stop the program
     Exit,
@@ -111,6 +316,8 @@ pub enum Instr {
     ShrinkStack(usize),
 }
 
+util::assert_size!(Instr <= 16);
+
 #[cfg(feature = "_debug")]
 impl dbg_pls::DebugPls for FnBlock<'_> {
     fn fmt(&self, f: dbg_pls::Formatter<'_>) {
@@ -118,6 +325,7 @@ impl dbg_pls::DebugPls for FnBlock<'_> {
             .field("name", &self.name)
             .field("arity", &self.arity)
             .field("code", &self.code.as_slice())
+            .field("consts", &self.consts.as_slice())
             .field("stack_sizes", &self.stack_sizes.as_slice())
             .finish_non_exhaustive()
     }
@@ -0,0 +1,341 @@
+//! The register based bytecode, enabled with `--register`
+//!
+//! Instead of pushing and popping values on a stack, every instruction names the registers it
+//! reads and writes, like `Add { dst, lhs, rhs }`. Registers are the slots of the stack frame of
+//! the current function. The compiler assigns every local variable a fixed register, so reading a
+//! variable doesn't need an instruction at all, and temporary values get the registers above the
+//! locals.
+//!
+//! # Function calls
+//! The arguments of a call are written to consecutive registers at the top of the frame of the
+//! caller. The frame of the callee starts at the first argument, so the arguments become its first
+//! registers without being copied. The return value is written to the `dst` register of the
+//! caller, and the VM keeps the return addresses separately, not in the registers.
+//!
+//! `return f(x);` is compiled to a `TailCall`, which doesn't save a return address at all and
+//! lets the callee take over the registers of the current function, so deep tail recursion needs
+//! no additional memory.
+//!
+//! Jumps are relative to the next instruction, just like in the stack based bytecode.
+
+use std::fmt::{Debug, Formatter};
+
+use bumpalo::collections::Vec;
+
+use crate::{
+    errors::Span,
+    runtime::{bytecode::FieldSite, gc::Symbol, vm::Value},
+    util,
+};
+
+/// The index of a register in the stack frame of the current function
+pub type Reg = u16;
+
+/// A function compiled to register based bytecode
+pub struct RegBlock<'bc> {
+    pub name: Symbol,
+    pub code: Vec<'bc, RegInstr>,
+    /// The constant pool of the function, see `LoadConst`
+    pub consts: Vec<'bc, Value>,
+    /// The fields accessed by `GetField` and `SetField`, see [`FieldSite`]
+    pub field_sites: Vec<'bc, FieldSite>,
+    /// The corresponding source code location of each instruction
+    pub spans: Vec<'bc, Span>,
+    /// How many parameters the function accepts, including the rest parameter
+    pub arity: u32,
+    /// How many arguments a call needs at least, the parameters after these have a default
+    pub required: u32,
+    /// Whether the last parameter collects the remaining arguments into an array
+    pub variadic: bool,
+    /// Whether the function was declared as a method, see [`FnBlock`](super::FnBlock)
+    pub is_method: bool,
+    /// How many registers the function needs, including the parameters
+    pub registers: usize,
+}
+
+impl RegBlock<'_> {
+    /// Adds the value to the constant pool and returns its index, see [`add_const`](super::add_const)
+    pub fn add_const(&mut self, value: Value) -> u32 {
+        super::add_const(&mut self.consts, value)
+    }
+
+    /// Adds a new field site and returns its index, see [`add_field_site`](super::add_field_site)
+    pub fn add_field_site(&mut self, name: Symbol) -> u32 {
+        super::add_field_site(&mut self.field_sites, name)
+    }
+}
+
+impl Debug for RegBlock<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.code.fmt(f)
+    }
+}
+
+#[cfg(feature = "_debug")]
+impl dbg_pls::DebugPls for RegBlock<'_> {
+    fn fmt(&self, f: dbg_pls::Formatter<'_>) {
+        f.debug_struct("RegBlock")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("registers", &self.registers)
+            .field("code", &self.code.as_slice())
+            .field("consts", &self.consts.as_slice())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A register based instruction, see the [module docs](self)
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub enum RegInstr {
+    /// Copy the value of `src` into `dst`
+    Move {
+        dst: Reg,
+        src: Reg,
+    },
+    /// Load the constant with the `index` from the constant pool into `dst`
+    LoadConst {
+        dst: Reg,
+        index: u32,
+    },
+    /// Negate a number or boolean
+    Neg {
+        dst: Reg,
+        src: Reg,
+    },
+    /// Write a new empty object to `dst`
+    NewObject {
+        dst: Reg,
+    },
+    /// Write a new array with the values of the `count` registers starting at `args` to `dst`
+    NewArray {
+        dst: Reg,
+        args: Reg,
+        count: u16,
+    },
+    /// Write a new array with the elements of the array in `array` and then the ones in `src` to
+    /// `array`. Errors if `src` is not an array
+    ExtendArray {
+        array: Reg,
+        src: Reg,
+    },
+    /// Read the field of the field site with the index `site` from the object in `object`
+    GetField {
+        dst: Reg,
+        object: Reg,
+        site: u32,
+    },
+    /// Set the field of the field site with the index `site` on the object in `object`
+    SetField {
+        object: Reg,
+        src: Reg,
+        site: u32,
+    },
+
+    // The tests of `match`, which write a boolean to `dst` and never fail
+    /// Whether `src` is equal to the constant with the `index`
+    MatchConst {
+        dst: Reg,
+        src: Reg,
+        index: u32,
+    },
+    /// Whether `src` is an array with exactly `len` elements
+    MatchArray {
+        dst: Reg,
+        src: Reg,
+        len: u32,
+    },
+    /// Whether `src` is an array with at least `len` elements
+    MatchArrayMin {
+        dst: Reg,
+        src: Reg,
+        len: u32,
+    },
+    /// Whether `object` is an object that has the field of the field site with the index `site`
+    HasField {
+        dst: Reg,
+        object: Reg,
+        site: u32,
+    },
+    /// Read the element at the `index` of the array in `array`
+    GetElement {
+        dst: Reg,
+        array: Reg,
+        index: u32,
+    },
+    /// A new array of the elements of the array in `array` from `start` on
+    SliceArray {
+        dst: Reg,
+        array: Reg,
+        start: u32,
+    },
+    /// Error because a value doesn't match the pattern of `let` or a parameter
+    MatchFailed,
+
+    // The binary operations, `dst = lhs op rhs`
+    Add {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Sub {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Mul {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Div {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    IntDiv {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Mod {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    And {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Or {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    BitAnd {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    BitOr {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    BitXor {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Shl {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Shr {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Greater {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    GreaterEq {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Less {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    LessEq {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    Eq {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+    NotEq {
+        dst: Reg,
+        lhs: Reg,
+        rhs: Reg,
+    },
+
+    /// Println the value of `src`
+    Print {
+        src: Reg,
+    },
+
+    /// If `cond` is false, skip `offset` instructions
+    JmpFalse {
+        cond: Reg,
+        offset: i32,
+    },
+    /// Skip `offset` instructions
+    Jmp {
+        offset: i32,
+    },
+    /// If `src` is null, skip `offset` instructions
+    JmpNull {
+        src: Reg,
+        offset: i32,
+    },
+
+    /// Call the function in `callee` with the `count` arguments starting at register `args`, and
+    /// write the return value to `dst`
+    Call {
+        dst: Reg,
+        callee: Reg,
+        args: Reg,
+        count: u16,
+    },
+    /// Call the method of the field site with the index `site` on the receiver in register `args`,
+    /// with the receiver as its first argument if the function is a method, and write the return
+    /// value to `dst`. `count` includes the receiver.
+    CallMethod {
+        dst: Reg,
+        args: Reg,
+        count: u16,
+        site: u32,
+    },
+    /// Call the function in `callee` like `Call`, with the elements of the array in `args` as the
+    /// arguments. They are written to the registers starting at `args`, which must be the last
+    /// register in use
+    CallSpread {
+        dst: Reg,
+        callee: Reg,
+        args: Reg,
+    },
+    /// Call the method like `CallMethod`, with the elements of the array in `args` as the
+    /// arguments, the first one is the receiver. They are written to the registers starting at
+    /// `args`, which must be the last register in use
+    CallMethodSpread {
+        dst: Reg,
+        args: Reg,
+        site: u32,
+    },
+    /// Return the value of `src` from the function
+    Return {
+        src: Reg,
+    },
+    /// Call the function in `callee` like `Call` and return its return value. The arguments are
+    /// moved to the first registers of the current function, which the callee then reuses.
+    TailCall {
+        callee: Reg,
+        args: Reg,
+        count: u16,
+    },
+
+    /// Stop the program
+    Exit,
+}
+
+util::assert_size!(RegInstr <= 12);
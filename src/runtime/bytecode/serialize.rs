@@ -0,0 +1,689 @@
+//! The binary format of compiled bytecode, used for `.dilc` files
+//!
+//! All integers are little endian. `usize` and `isize` values are stored as 64 bit integers,
+//! lengths and indices as 32 bit integers. Strings are stored once in the string table and
+//! referred to by their index.
+//!
+//! ```text
+//! file      = magic:"DILC" version:u32 strings source:str functions
+//! strings   = len:u32 (len:u32 utf8-bytes)*
+//! str       = index:u32 into the string table
+//! functions = len:u32 (name:str params consts sites len:u32 instr* (start:u64 end:u64)* locals)*
+//! params    = arity:u32 required:u32 variadic:u8 method:u8
+//! consts    = len:u32 value*
+//! sites     = len:u32 name:str*
+//! locals    = len:u32 (name:str offset:u64 start:u64 end:u64)*
+//! ```
+//!
+//! `source` is the source code that the spans point into, so that the debugger and the reports
+//! still work without the original file.
+//!
+//! Instructions and values are a tag byte followed by their operands. Loading validates the whole
+//! file, everything that does not fit the format exactly is rejected. Whether the code itself makes
+//! sense is checked by the [`verifier`](`crate::runtime::verify`) before it is run.
+
+use std::fmt::{Display, Formatter};
+
+use bumpalo::{collections::Vec, Bump};
+
+use crate::{
+    errors::Span,
+    runtime::{
+        bytecode::{add_field_site, FnBlock, Instr, LocalVar},
+        gc::RtAlloc,
+        native,
+        vm::Value,
+    },
+    HashMap,
+};
+
+pub const MAGIC: &[u8; 4] = b"DILC";
+
+/// Must be incremented for every change to the format or the instruction set
+pub const VERSION: u32 = 12;
+
+/// Arrays and objects can only be nested this deep
+const MAX_DEPTH: usize = 64;
+
+/// Whether the file looks like serialized bytecode instead of source code
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Bytecode that was loaded from a file
+pub struct Loaded<'bc> {
+    pub blocks: &'bc [FnBlock<'bc>],
+    /// The source code that the spans point into
+    pub source: String,
+}
+
+/// Loading a bytecode file failed because it is invalid
+#[derive(Debug)]
+pub struct LoadError {
+    /// The byte offset in the file where the problem was found
+    offset: usize,
+    message: String,
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid bytecode file at byte {}: {}",
+            self.offset, self.message
+        )
+    }
+}
+
+type LResult<T> = Result<T, LoadError>;
+
+mod tag {
+    pub const NULL: u8 = 0;
+    pub const BOOL: u8 = 1;
+    pub const NUM: u8 = 2;
+    pub const STRING: u8 = 3;
+    pub const ARRAY: u8 = 4;
+    pub const OBJECT: u8 = 5;
+    pub const FUNCTION: u8 = 6;
+    pub const NATIVE_FUNCTION: u8 = 7;
+    pub const INT: u8 = 8;
+}
+
+/// The instructions without operands, their tag is their index in this list
+const SIMPLE_INSTRS: &[Instr] = &[
+    Instr::Nop,
+    Instr::Neg,
+    Instr::BinAdd,
+    Instr::BinSub,
+    Instr::BinMul,
+    Instr::BinDiv,
+    Instr::BinMod,
+    Instr::BinAnd,
+    Instr::BinOr,
+    Instr::CmpGreater,
+    Instr::CmpGreaterEq,
+    Instr::CmpLess,
+    Instr::CmpLessEq,
+    Instr::CmpEq,
+    Instr::CmpNotEq,
+    Instr::Print,
+    Instr::Return,
+    Instr::Exit,
+    Instr::PushNull,
+    Instr::PushTrue,
+    Instr::PushFalse,
+    Instr::NewObject,
+    Instr::Dup,
+    Instr::ExtendArray,
+    Instr::CallSpread,
+    Instr::MatchFailed,
+    Instr::BinIntDiv,
+    Instr::BinBitAnd,
+    Instr::BinBitOr,
+    Instr::BinBitXor,
+    Instr::BinShl,
+    Instr::BinShr,
+];
+
+mod instr_tag {
+    // the tags before are taken by `SIMPLE_INSTRS`
+    pub const STORE: u8 = 32;
+    pub const LOAD: u8 = 33;
+    pub const PUSH_CONST: u8 = 34;
+    pub const JMP_FALSE: u8 = 35;
+    pub const JMP: u8 = 36;
+    pub const CALL: u8 = 37;
+    pub const SHRINK_STACK: u8 = 38;
+    pub const TAIL_CALL: u8 = 39;
+    pub const GET_FIELD: u8 = 40;
+    pub const SET_FIELD: u8 = 41;
+    pub const CALL_METHOD: u8 = 42;
+    pub const MATCH_CONST: u8 = 43;
+    pub const MATCH_ARRAY: u8 = 44;
+    pub const HAS_FIELD: u8 = 45;
+    pub const GET_ELEMENT: u8 = 46;
+    pub const JMP_NULL: u8 = 47;
+    pub const NEW_ARRAY: u8 = 48;
+    pub const CALL_METHOD_SPREAD: u8 = 49;
+    pub const MATCH_ARRAY_MIN: u8 = 50;
+    pub const SLICE_ARRAY: u8 = 51;
+}
+
+pub fn serialize(blocks: &[FnBlock<'_>], source: &str) -> std::vec::Vec<u8> {
+    let mut writer = Writer {
+        out: std::vec::Vec::new(),
+        strings: std::vec::Vec::new(),
+        string_ids: HashMap::default(),
+    };
+
+    let source = writer.string_id(source);
+    writer.u32(blocks.len() as u32);
+    for block in blocks {
+        writer.block(block);
+    }
+
+    let mut file = std::vec::Vec::new();
+    file.extend_from_slice(MAGIC);
+    file.extend_from_slice(&VERSION.to_le_bytes());
+    file.extend_from_slice(&(writer.strings.len() as u32).to_le_bytes());
+    for string in &writer.strings {
+        file.extend_from_slice(&(string.len() as u32).to_le_bytes());
+        file.extend_from_slice(string.as_bytes());
+    }
+    file.extend_from_slice(&source.to_le_bytes());
+    file.extend_from_slice(&writer.out);
+    file
+}
+
+struct Writer {
+    out: std::vec::Vec<u8>,
+    strings: std::vec::Vec<String>,
+    string_ids: HashMap<String, u32>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.out.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string_id(&mut self, string: &str) -> u32 {
+        if let Some(id) = self.string_ids.get(string) {
+            return *id;
+        }
+        let id = self.strings.len() as u32;
+        self.strings.push(string.to_owned());
+        self.string_ids.insert(string.to_owned(), id);
+        id
+    }
+
+    fn string(&mut self, string: &str) {
+        let id = self.string_id(string);
+        self.u32(id);
+    }
+
+    fn block(&mut self, block: &FnBlock<'_>) {
+        self.string(block.name.as_str());
+        self.u32(block.arity);
+        self.u32(block.required);
+        self.u8(block.variadic as u8);
+        self.u8(block.is_method as u8);
+
+        self.u32(block.consts.len() as u32);
+        for value in &block.consts {
+            self.value(*value);
+        }
+
+        // the caches are filled at runtime, only the names are stored
+        self.u32(block.field_sites.len() as u32);
+        for site in &block.field_sites {
+            self.string(site.name.as_str());
+        }
+
+        self.u32(block.code.len() as u32);
+        for instr in &block.code {
+            self.instr(*instr);
+        }
+        for span in &block.spans {
+            self.u64(span.start as u64);
+            self.u64(span.end as u64);
+        }
+
+        self.u32(block.locals.len() as u32);
+        for local in &block.locals {
+            self.string(local.name.as_str());
+            self.u64(local.offset as u64);
+            self.u64(local.start as u64);
+            self.u64(local.end as u64);
+        }
+    }
+
+    fn instr(&mut self, instr: Instr) {
+        match instr {
+            Instr::Store(offset) => {
+                self.u8(instr_tag::STORE);
+                self.u64(offset as u64);
+            }
+            Instr::Load(offset) => {
+                self.u8(instr_tag::LOAD);
+                self.u64(offset as u64);
+            }
+            Instr::PushConst(index) => {
+                self.u8(instr_tag::PUSH_CONST);
+                self.u32(index);
+            }
+            Instr::JmpFalse(offset) => {
+                self.u8(instr_tag::JMP_FALSE);
+                self.u64(offset as u64);
+            }
+            Instr::Jmp(offset) => {
+                self.u8(instr_tag::JMP);
+                self.u64(offset as u64);
+            }
+            Instr::JmpNull(offset) => {
+                self.u8(instr_tag::JMP_NULL);
+                self.u64(offset as u64);
+            }
+            Instr::Call(args) => {
+                self.u8(instr_tag::CALL);
+                self.u32(args);
+            }
+            Instr::MatchConst(index) => {
+                self.u8(instr_tag::MATCH_CONST);
+                self.u32(index);
+            }
+            Instr::MatchArray(len) => {
+                self.u8(instr_tag::MATCH_ARRAY);
+                self.u32(len);
+            }
+            Instr::MatchArrayMin(len) => {
+                self.u8(instr_tag::MATCH_ARRAY_MIN);
+                self.u32(len);
+            }
+            Instr::HasField(site) => {
+                self.u8(instr_tag::HAS_FIELD);
+                self.u32(site);
+            }
+            Instr::GetElement(index) => {
+                self.u8(instr_tag::GET_ELEMENT);
+                self.u32(index);
+            }
+            Instr::SliceArray(start) => {
+                self.u8(instr_tag::SLICE_ARRAY);
+                self.u32(start);
+            }
+            Instr::CallMethod { site, args } => {
+                self.u8(instr_tag::CALL_METHOD);
+                self.u32(site);
+                self.u32(args);
+            }
+            Instr::TailCall(args) => {
+                self.u8(instr_tag::TAIL_CALL);
+                self.u32(args);
+            }
+            Instr::GetField(site) => {
+                self.u8(instr_tag::GET_FIELD);
+                self.u32(site);
+            }
+            Instr::SetField(site) => {
+                self.u8(instr_tag::SET_FIELD);
+                self.u32(site);
+            }
+            Instr::NewArray(len) => {
+                self.u8(instr_tag::NEW_ARRAY);
+                self.u32(len);
+            }
+            Instr::CallMethodSpread(site) => {
+                self.u8(instr_tag::CALL_METHOD_SPREAD);
+                self.u32(site);
+            }
+            Instr::ShrinkStack(amount) => {
+                self.u8(instr_tag::SHRINK_STACK);
+                self.u64(amount as u64);
+            }
+            simple => {
+                let tag = SIMPLE_INSTRS
+                    .iter()
+                    .position(|instr| {
+                        std::mem::discriminant(instr) == std::mem::discriminant(&simple)
+                    })
+                    .expect("all other instructions are simple");
+                self.u8(tag as u8);
+            }
+        }
+    }
+
+    fn value(&mut self, value: Value) {
+        match value {
+            Value::Null => self.u8(tag::NULL),
+            Value::Bool(bool) => {
+                self.u8(tag::BOOL);
+                self.u8(bool as u8);
+            }
+            Value::Num(num) => {
+                self.u8(tag::NUM);
+                self.u64(num.to_bits());
+            }
+            Value::Int(int) => {
+                self.u8(tag::INT);
+                self.u64(int as u64);
+            }
+            Value::String(sym) => {
+                self.u8(tag::STRING);
+                self.string(sym.as_str());
+            }
+            Value::Array(array) => {
+                self.u8(tag::ARRAY);
+                self.u32(array.len() as u32);
+                for element in array.iter() {
+                    self.value(*element);
+                }
+            }
+            Value::Object(object) => {
+                self.u8(tag::OBJECT);
+                let mut fields = object.entries();
+                fields.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+                self.u32(fields.len() as u32);
+                for (key, value) in fields {
+                    self.string(key.as_str());
+                    self.value(value);
+                }
+            }
+            Value::Function(function) => {
+                self.u8(tag::FUNCTION);
+                self.u32(function as u32);
+            }
+            Value::NativeFunction(native) => {
+                self.u8(tag::NATIVE_FUNCTION);
+                self.string(native::name(native));
+            }
+            Value::NativeU(_) => unreachable!("bookkeeping values are never part of the bytecode"),
+        }
+    }
+}
+
+pub fn deserialize<'bc>(bytes: &[u8], bump: &'bc Bump, rt: &mut RtAlloc) -> LResult<Loaded<'bc>> {
+    let mut reader = Reader {
+        bytes,
+        pos: 0,
+        strings: std::vec::Vec::new(),
+        rt,
+    };
+
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(reader.error_at(0, "not a bytecode file"));
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(reader.error_at(
+            MAGIC.len(),
+            format!("unsupported version {version}, expected {VERSION}"),
+        ));
+    }
+
+    let string_count = reader.len(4)?;
+    for _ in 0..string_count {
+        let len = reader.len(1)?;
+        let start = reader.pos;
+        let string = std::str::from_utf8(reader.bytes(len)?)
+            .map_err(|_| reader.error_at(start, "string is not valid UTF-8"))?;
+        reader.strings.push(string);
+    }
+
+    let source = reader.string()?.to_owned();
+
+    let block_count = reader.len(8)?;
+    if block_count == 0 {
+        return Err(reader.error("there must be at least the main function"));
+    }
+
+    let mut blocks = Vec::with_capacity_in(block_count, bump);
+    for _ in 0..block_count {
+        blocks.push(reader.block(bump, source.len())?);
+    }
+
+    if blocks[0].arity != 0 {
+        return Err(reader.error("the main function must not have parameters"));
+    }
+
+    if reader.pos != reader.bytes.len() {
+        return Err(reader.error("trailing bytes after the last function"));
+    }
+
+    Ok(Loaded {
+        blocks: blocks.into_bump_slice(),
+        source,
+    })
+}
+
+struct Reader<'a, 'rt> {
+    bytes: &'a [u8],
+    pos: usize,
+    strings: std::vec::Vec<&'a str>,
+    rt: &'rt mut RtAlloc,
+}
+
+impl<'a> Reader<'a, '_> {
+    fn error(&self, message: impl Into<String>) -> LoadError {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, offset: usize, message: impl Into<String>) -> LoadError {
+        LoadError {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> LResult<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> LResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> LResult<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> LResult<u64> {
+        let bytes = self.bytes(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn usize(&mut self) -> LResult<usize> {
+        let start = self.pos;
+        let value = self.u64()?;
+        usize::try_from(value).map_err(|_| self.error_at(start, "number is too big"))
+    }
+
+    /// Reads the length of a list, and checks that the file is big enough to contain that many
+    /// items of at least `min_item_size` bytes, to not allocate huge amounts for broken files
+    fn len(&mut self, min_item_size: usize) -> LResult<usize> {
+        let start = self.pos;
+        let len = self.u32()? as usize;
+        let remaining = self.bytes.len() - self.pos;
+        if len.saturating_mul(min_item_size) > remaining {
+            return Err(self.error_at(start, format!("length {len} exceeds the file size")));
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> LResult<&'a str> {
+        let start = self.pos;
+        let index = self.u32()? as usize;
+        self.strings
+            .get(index)
+            .copied()
+            .ok_or_else(|| self.error_at(start, format!("string index {index} is out of bounds")))
+    }
+
+    fn block<'bc>(&mut self, bump: &'bc Bump, source_len: usize) -> LResult<FnBlock<'bc>> {
+        let name = self.string()?;
+        let name = self.rt.intern_string(name);
+        let arity = self.u32()?;
+        let required_start = self.pos;
+        let required = self.u32()?;
+        if required > arity {
+            return Err(self.error_at(required_start, "more required arguments than parameters"));
+        }
+        let variadic = match self.u8()? {
+            0 => false,
+            1 if arity > 0 => true,
+            1 => return Err(self.error_at(self.pos - 1, "variadic function without parameters")),
+            other => return Err(self.error_at(self.pos - 1, format!("invalid bool {other}"))),
+        };
+        let is_method = match self.u8()? {
+            0 => false,
+            1 if arity > 0 => true,
+            1 => return Err(self.error_at(self.pos - 1, "method without `self` parameter")),
+            other => return Err(self.error_at(self.pos - 1, format!("invalid bool {other}"))),
+        };
+
+        let consts_len = self.len(1)?;
+        let mut consts = Vec::with_capacity_in(consts_len, bump);
+        for _ in 0..consts_len {
+            consts.push(self.value(0)?);
+        }
+
+        let field_sites_len = self.len(4)?;
+        let mut field_sites = Vec::with_capacity_in(field_sites_len, bump);
+        for _ in 0..field_sites_len {
+            let name = self.string()?;
+            let name = self.rt.intern_string(name);
+            add_field_site(&mut field_sites, name);
+        }
+
+        // every instruction has at least a tag and a span
+        let code_len = self.len(1 + 16)?;
+        let mut code = Vec::with_capacity_in(code_len, bump);
+        for _ in 0..code_len {
+            code.push(self.instr()?);
+        }
+
+        let mut spans = Vec::with_capacity_in(code_len, bump);
+        for _ in 0..code_len {
+            let start_pos = self.pos;
+            let start = self.usize()?;
+            let end = self.usize()?;
+            if start > end || end > source_len {
+                return Err(self.error_at(start_pos, "span is outside of the source code"));
+            }
+            spans.push(Span::start_end(start, end));
+        }
+
+        let locals_len = self.len(4 + 24)?;
+        let mut locals = Vec::with_capacity_in(locals_len, bump);
+        for _ in 0..locals_len {
+            let name = self.string()?;
+            let name = self.rt.intern_string(name);
+            let offset = self.usize()?;
+            let start = self.usize()?;
+            let end = self.usize()?;
+            locals.push(LocalVar {
+                name,
+                offset,
+                start,
+                end,
+            });
+        }
+
+        Ok(FnBlock {
+            name,
+            code,
+            consts,
+            field_sites,
+            // only needed while compiling
+            stack_sizes: Vec::new_in(bump),
+            spans,
+            locals,
+            arity,
+            required,
+            variadic,
+            is_method,
+        })
+    }
+
+    fn instr(&mut self) -> LResult<Instr> {
+        let start = self.pos;
+        let tag = self.u8()?;
+        let instr = match tag {
+            instr_tag::STORE => Instr::Store(self.usize()?),
+            instr_tag::LOAD => Instr::Load(self.usize()?),
+            instr_tag::PUSH_CONST => Instr::PushConst(self.u32()?),
+            instr_tag::JMP_FALSE => Instr::JmpFalse(self.u64()? as isize),
+            instr_tag::JMP => Instr::Jmp(self.u64()? as isize),
+            instr_tag::CALL => Instr::Call(self.u32()?),
+            instr_tag::SHRINK_STACK => Instr::ShrinkStack(self.usize()?),
+            instr_tag::TAIL_CALL => Instr::TailCall(self.u32()?),
+            instr_tag::GET_FIELD => Instr::GetField(self.u32()?),
+            instr_tag::SET_FIELD => Instr::SetField(self.u32()?),
+            instr_tag::MATCH_CONST => Instr::MatchConst(self.u32()?),
+            instr_tag::MATCH_ARRAY => Instr::MatchArray(self.u32()?),
+            instr_tag::HAS_FIELD => Instr::HasField(self.u32()?),
+            instr_tag::GET_ELEMENT => Instr::GetElement(self.u32()?),
+            instr_tag::JMP_NULL => Instr::JmpNull(self.u64()? as isize),
+            instr_tag::NEW_ARRAY => Instr::NewArray(self.u32()?),
+            instr_tag::CALL_METHOD_SPREAD => Instr::CallMethodSpread(self.u32()?),
+            instr_tag::MATCH_ARRAY_MIN => Instr::MatchArrayMin(self.u32()?),
+            instr_tag::SLICE_ARRAY => Instr::SliceArray(self.u32()?),
+            instr_tag::CALL_METHOD => Instr::CallMethod {
+                site: self.u32()?,
+                args: self.u32()?,
+            },
+            _ => match SIMPLE_INSTRS.get(tag as usize) {
+                Some(instr) => *instr,
+                None => return Err(self.error_at(start, format!("unknown instruction {tag}"))),
+            },
+        };
+        Ok(instr)
+    }
+
+    fn value(&mut self, depth: usize) -> LResult<Value> {
+        let start = self.pos;
+        if depth > MAX_DEPTH {
+            return Err(self.error_at(start, "values are nested too deeply"));
+        }
+        let tag = self.u8()?;
+        let value = match tag {
+            tag::NULL => Value::Null,
+            tag::BOOL => match self.u8()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                other => return Err(self.error_at(start + 1, format!("invalid bool {other}"))),
+            },
+            tag::NUM => Value::Num(f64::from_bits(self.u64()?)),
+            tag::INT => Value::Int(self.u64()? as i64),
+            tag::STRING => {
+                let str = self.string()?;
+                Value::String(self.rt.intern_string(str))
+            }
+            tag::ARRAY => {
+                let len = self.len(1)?;
+                let elements = (0..len)
+                    .map(|_| self.value(depth + 1))
+                    .collect::<LResult<_>>()?;
+                Value::Array(self.rt.alloc_array(elements))
+            }
+            tag::OBJECT => {
+                let len = self.len(4 + 1)?;
+                let mut fields = HashMap::default();
+                for _ in 0..len {
+                    let key_start = self.pos;
+                    let key = self.string()?;
+                    let key = self.rt.intern_string(key);
+                    let value = self.value(depth + 1)?;
+                    if fields.insert(key, value).is_some() {
+                        return Err(self.error_at(key_start, "duplicate object key"));
+                    }
+                }
+                Value::Object(self.rt.alloc_obj(fields))
+            }
+            tag::FUNCTION => Value::Function(self.u32()? as usize),
+            tag::NATIVE_FUNCTION => {
+                let name = self.string()?;
+                let native = native::lookup(name).ok_or_else(|| {
+                    self.error_at(start, format!("native function `{name}` does not exist"))
+                })?;
+                Value::NativeFunction(native)
+            }
+            _ => return Err(self.error_at(start, format!("unknown value {tag}"))),
+        };
+        Ok(value)
+    }
+}
@@ -0,0 +1,195 @@
+//! Line coverage, enabled with `--coverage`
+//!
+//! The VM counts how often every instruction of every `FnBlock` is executed. After the program has
+//! stopped, the instructions are mapped to source lines through the `spans`. A line counts as
+//! executed as often as its most executed instruction, so a loop condition is counted once for
+//! every check, not once for every instruction it consists of.
+
+use std::io::{self, Write};
+
+use crate::{
+    errors::LineIndex,
+    runtime::bytecode::{FnBlock, Function},
+};
+
+pub(super) struct Coverage {
+    lines: LineIndex,
+    /// How often every instruction was executed, indexed by block and then instruction
+    instr_hits: Vec<Vec<u64>>,
+    /// How often every function was called, indexed by block
+    calls: Vec<u64>,
+}
+
+/// The coverage of a single source line, `None` for lines without any code
+type LineHits = Option<u64>;
+
+impl Coverage {
+    pub fn new(source: &str, blocks: &[FnBlock<'_>]) -> Self {
+        let mut calls = vec![0; blocks.len()];
+        // the global script is always the first block and runs once
+        if let Some(main) = calls.first_mut() {
+            *main = 1;
+        }
+
+        Self {
+            lines: LineIndex::new(source),
+            instr_hits: blocks
+                .iter()
+                .map(|block| vec![0; block.code.len()])
+                .collect(),
+            calls,
+        }
+    }
+
+    /// Called before every instruction
+    pub fn instr(&mut self, block: Function, index: usize) {
+        self.instr_hits[block][index] += 1;
+    }
+
+    /// Called after a new stack frame for `block` has been created
+    pub fn enter(&mut self, block: Function) {
+        self.calls[block] += 1;
+    }
+
+    /// Maps the instruction hits to lines, the returned list is indexed by the 0-based line number
+    fn line_hits(&self, blocks: &[FnBlock<'_>]) -> Vec<LineHits> {
+        let mut lines = vec![None; self.lines.line_count()];
+
+        for (block, hits) in blocks.iter().zip(&self.instr_hits) {
+            for (span, hits) in block.spans.iter().zip(hits) {
+                // instructions inserted by the compiler don't belong to any line
+                if span.len() == 0 {
+                    continue;
+                }
+                let line = &mut lines[self.lines.line(span.start) - 1];
+                *line = Some(line.unwrap_or(0).max(*hits));
+            }
+        }
+
+        lines
+    }
+
+    /// The first line of every function except the global script
+    fn function_lines<'a>(
+        &'a self,
+        blocks: &'a [FnBlock<'a>],
+    ) -> impl Iterator<Item = (usize, &'a str, u64)> + 'a {
+        blocks
+            .iter()
+            .zip(&self.calls)
+            .skip(1)
+            .filter_map(|(block, calls)| {
+                let start = block
+                    .spans
+                    .iter()
+                    .filter(|span| span.len() != 0)
+                    .map(|span| span.start)
+                    .min()?;
+                Some((self.lines.line(start), block.name.as_str(), *calls))
+            })
+    }
+
+    /// Writes a short summary with the percentage and all lines that were never executed
+    pub fn write_summary(&self, blocks: &[FnBlock<'_>], w: &mut dyn Write) -> io::Result<()> {
+        let lines = self.line_hits(blocks);
+        let found = lines.iter().flatten().count();
+        let hit = lines.iter().flatten().filter(|hits| **hits > 0).count();
+
+        let functions_found = self.function_lines(blocks).count();
+        let functions_hit = self
+            .function_lines(blocks)
+            .filter(|(_, _, calls)| *calls > 0)
+            .count();
+
+        let percent = if found == 0 {
+            100.0
+        } else {
+            hit as f64 / found as f64 * 100.0
+        };
+
+        writeln!(w, "coverage: {hit} of {found} lines ({percent:.2}%)")?;
+        writeln!(w, "functions: {functions_hit} of {functions_found}")?;
+
+        let missed = missed_ranges(&lines);
+        if !missed.is_empty() {
+            writeln!(w, "not covered: {}", missed.join(", "))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the report in the lcov tracefile format, for a single source file
+    pub fn write_lcov(
+        &self,
+        blocks: &[FnBlock<'_>],
+        filename: &str,
+        w: &mut dyn Write,
+    ) -> io::Result<()> {
+        writeln!(w, "TN:")?;
+        writeln!(w, "SF:{filename}")?;
+
+        let functions = self.function_lines(blocks).collect::<Vec<_>>();
+        for (line, name, _) in &functions {
+            writeln!(w, "FN:{line},{name}")?;
+        }
+        for (_, name, calls) in &functions {
+            writeln!(w, "FNDA:{calls},{name}")?;
+        }
+        writeln!(w, "FNF:{}", functions.len())?;
+        writeln!(
+            w,
+            "FNH:{}",
+            functions.iter().filter(|(_, _, calls)| *calls > 0).count()
+        )?;
+
+        let lines = self.line_hits(blocks);
+        for (line, hits) in lines.iter().enumerate() {
+            if let Some(hits) = hits {
+                writeln!(w, "DA:{},{hits}", line + 1)?;
+            }
+        }
+        writeln!(w, "LF:{}", lines.iter().flatten().count())?;
+        writeln!(
+            w,
+            "LH:{}",
+            lines.iter().flatten().filter(|hits| **hits > 0).count()
+        )?;
+
+        writeln!(w, "end_of_record")
+    }
+}
+
+/// Groups the lines with code that were never executed into ranges like `3-5`. Lines without code
+/// in between don't interrupt a range.
+fn missed_ranges(lines: &[LineHits]) -> Vec<String> {
+    let mut ranges = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+
+    for (line, hits) in lines.iter().enumerate() {
+        let line = line + 1;
+        match hits {
+            Some(0) => {
+                current = Some(match current {
+                    Some((start, _)) => (start, line),
+                    None => (line, line),
+                })
+            }
+            Some(_) => {
+                ranges.extend(current.take());
+            }
+            None => {}
+        }
+    }
+    ranges.extend(current);
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect()
+}
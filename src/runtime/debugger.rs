@@ -0,0 +1,341 @@
+//! The interactive debugger, enabled with `--step`
+//!
+//! Before every instruction, the VM hands control to the debugger, which decides whether to pause.
+//! It only ever pauses when execution reaches a new source line, which is found through the
+//! `spans` of the `FnBlock`. While paused, commands are read from the configured stdin, and all
+//! output goes to the configured stderr.
+
+use crate::{
+    errors::LineIndex,
+    runtime::{
+        bytecode::Function,
+        stack_frame::Frame,
+        vm::{Value, Vm, VmErrorInner, VmResult},
+    },
+};
+
+const HELP: &str = "\
+commands:
+  s, step           step to the next line, entering functions
+  n, next           step to the next line in this function or its callers
+  o, out            step out of the current function
+  c, continue       run until the next breakpoint
+  b, break <line>   set a breakpoint on a line
+  d, delete <line>  remove a breakpoint
+  bt, backtrace     print the call stack
+  l, locals [frame] print the local variables of a frame
+  p, print <name>   print a local variable of the current function
+  i, instr          print the next instruction and the raw stack
+  q, quit           stop the program
+  h, help           print this help
+an empty line repeats the last command";
+
+pub(super) struct Debugger {
+    lines: LineIndex,
+    source_lines: Vec<String>,
+    breakpoints: Vec<usize>,
+    mode: StepMode,
+    last_command: String,
+    /// Where the previous instruction was
+    last_location: Option<Location>,
+    /// Where the debugger paused last
+    paused_at: Option<Location>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepMode {
+    Into,
+    /// Step until a line with at most this call depth is reached
+    Over(usize),
+    /// Step until a line with less than this call depth is reached
+    Out(usize),
+    Continue,
+    /// The input has ended, never pause again
+    Detached,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    block: Function,
+    line: usize,
+    depth: usize,
+}
+
+/// A function that is currently being executed
+struct FrameInfo {
+    block: Function,
+    /// The index of the instruction that is being executed, for callers the `Call`
+    instr: usize,
+    stack_frame_offset: usize,
+}
+
+impl Debugger {
+    pub fn new(source: &str) -> Self {
+        Self {
+            lines: LineIndex::new(source),
+            source_lines: source.lines().map(ToOwned::to_owned).collect(),
+            breakpoints: Vec::new(),
+            mode: StepMode::Into,
+            last_command: "step".to_string(),
+            last_location: None,
+            paused_at: None,
+        }
+    }
+
+    /// Called before every instruction
+    pub fn before_instr(&mut self, vm: &mut Vm<'_, '_>) -> VmResult {
+        if self.mode == StepMode::Detached {
+            return Ok(());
+        }
+
+        // at this point, we've always incremented the pc already
+        let span = vm.current.spans[vm.pc - 1];
+        if span.len() == 0 {
+            // instructions inserted by the compiler don't belong to any line
+            return Ok(());
+        }
+
+        let location = Location {
+            block: vm.current_block_index,
+            line: self.lines.line(span.start),
+            depth: vm.call_depth,
+        };
+
+        let new_line = self.last_location != Some(location);
+        self.last_location = Some(location);
+        if !new_line {
+            return Ok(());
+        }
+
+        let step_done = self.paused_at != Some(location)
+            && match self.mode {
+                StepMode::Into => true,
+                StepMode::Over(depth) => location.depth <= depth,
+                StepMode::Out(depth) => location.depth < depth,
+                StepMode::Continue | StepMode::Detached => false,
+            };
+
+        if step_done || self.breakpoints.contains(&location.line) {
+            self.paused_at = Some(location);
+            self.pause(vm, location)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn pause(&mut self, vm: &mut Vm<'_, '_>, location: Location) -> VmResult {
+        let name = vm.blocks[location.block].name;
+        let _ = writeln!(vm.stderr, "{} at line {}", name.as_str(), location.line);
+        self.print_line(vm, location.line);
+
+        loop {
+            let _ = write!(vm.stderr, "(debug) ");
+            let _ = vm.stderr.flush();
+
+            let mut input = String::new();
+            match vm.stdin.read_line(&mut input) {
+                Ok(0) | Err(_) => {
+                    // nobody is there to control us anymore, so just run the program to the end
+                    self.mode = StepMode::Detached;
+                    let _ = writeln!(vm.stderr);
+                    return Ok(());
+                }
+                Ok(_) => {}
+            }
+
+            let input = input.trim();
+            let command = if input.is_empty() {
+                self.last_command.clone()
+            } else {
+                self.last_command = input.to_string();
+                input.to_string()
+            };
+
+            let mut parts = command.split_whitespace();
+            let name = parts.next().unwrap_or_default();
+            let arg = parts.next();
+
+            match name {
+                "s" | "step" => {
+                    self.mode = StepMode::Into;
+                    return Ok(());
+                }
+                "n" | "next" => {
+                    self.mode = StepMode::Over(location.depth);
+                    return Ok(());
+                }
+                "o" | "out" => {
+                    self.mode = StepMode::Out(location.depth);
+                    return Ok(());
+                }
+                "c" | "continue" => {
+                    self.mode = StepMode::Continue;
+                    return Ok(());
+                }
+                "b" | "break" => match self.line_arg(arg) {
+                    Some(line) => {
+                        if !self.breakpoints.contains(&line) {
+                            self.breakpoints.push(line);
+                        }
+                        let _ = writeln!(vm.stderr, "breakpoint set on line {line}");
+                    }
+                    None => self.invalid_line(vm),
+                },
+                "d" | "delete" => match self.line_arg(arg) {
+                    Some(line) => {
+                        self.breakpoints.retain(|&breakpoint| breakpoint != line);
+                        let _ = writeln!(vm.stderr, "breakpoint removed from line {line}");
+                    }
+                    None => self.invalid_line(vm),
+                },
+                "bt" | "backtrace" => self.print_backtrace(vm),
+                "l" | "locals" => {
+                    let frame = arg.map(str::parse::<usize>).unwrap_or(Ok(0));
+                    match frame {
+                        Ok(frame) => self.print_locals(vm, frame),
+                        Err(_) => {
+                            let _ = writeln!(vm.stderr, "expected a frame number");
+                        }
+                    }
+                }
+                "p" | "print" => match arg {
+                    Some(var_name) => self.print_local(vm, var_name),
+                    None => {
+                        let _ = writeln!(vm.stderr, "expected a variable name");
+                    }
+                },
+                "i" | "instr" => {
+                    let instr = vm.current.code[vm.pc - 1];
+                    let _ = writeln!(vm.stderr, "next instruction: {instr:?}");
+                    let _ = writeln!(vm.stderr, "stack: {:?}", vm.stack);
+                }
+                "q" | "quit" => return Err(Box::new(VmErrorInner::Exit)),
+                "h" | "help" => {
+                    let _ = writeln!(vm.stderr, "{HELP}");
+                }
+                _ => {
+                    let _ = writeln!(vm.stderr, "unknown command `{name}`, try `help`");
+                }
+            }
+        }
+    }
+
+    fn line_arg(&self, arg: Option<&str>) -> Option<usize> {
+        arg.and_then(|arg| arg.parse().ok())
+            .filter(|line| (1..=self.lines.line_count()).contains(line))
+    }
+
+    fn invalid_line(&self, vm: &mut Vm<'_, '_>) {
+        let _ = writeln!(
+            vm.stderr,
+            "expected a line number between 1 and {}",
+            self.lines.line_count()
+        );
+    }
+
+    fn print_line(&self, vm: &mut Vm<'_, '_>, line: usize) {
+        if let Some(source_line) = self.source_lines.get(line - 1) {
+            let _ = writeln!(vm.stderr, "{line:>5} | {source_line}");
+        }
+    }
+
+    fn print_backtrace(&self, vm: &mut Vm<'_, '_>) {
+        for (i, frame) in frames(vm).iter().enumerate() {
+            let block = &vm.blocks[frame.block];
+            let line = self.lines.line(block.spans[frame.instr].start);
+            let _ = writeln!(vm.stderr, "#{i} {} at line {line}", block.name.as_str());
+        }
+    }
+
+    fn print_locals(&self, vm: &mut Vm<'_, '_>, frame: usize) {
+        let frames = frames(vm);
+        let frame = match frames.get(frame) {
+            Some(frame) => frame,
+            None => {
+                let _ = writeln!(vm.stderr, "there are only {} frames", frames.len());
+                return;
+            }
+        };
+
+        let locals = live_locals(vm, frame);
+        if locals.is_empty() {
+            let _ = writeln!(vm.stderr, "no locals");
+        }
+        for (name, value) in locals {
+            let _ = writeln!(vm.stderr, "{name} = {}", display_value(value));
+        }
+    }
+
+    fn print_local(&self, vm: &mut Vm<'_, '_>, var_name: &str) {
+        let frames = frames(vm);
+        let local = live_locals(vm, &frames[0])
+            .into_iter()
+            .find(|(name, _)| name == var_name);
+
+        match local {
+            Some((name, value)) => {
+                let _ = writeln!(vm.stderr, "{name} = {}", display_value(value));
+            }
+            None => {
+                let _ = writeln!(vm.stderr, "no local variable `{var_name}` in scope");
+            }
+        }
+    }
+}
+
+/// Walks the frame chain, starting at the current function
+fn frames(vm: &Vm<'_, '_>) -> Vec<FrameInfo> {
+    let mut frames = vec![FrameInfo {
+        block: vm.current_block_index,
+        instr: vm.pc - 1,
+        stack_frame_offset: vm.stack_frame_offset,
+    }];
+
+    for _ in 0..vm.call_depth {
+        let callee = frames.last().unwrap();
+        let arity = vm.blocks[callee.block].arity;
+        let frame = Frame::new(&vm.stack[callee.stack_frame_offset..], arity);
+
+        frames.push(FrameInfo {
+            block: frame.old_fn_block(),
+            // the old pc points after the call
+            instr: frame.old_pc() - 1,
+            stack_frame_offset: frame.old_stack_offset(),
+        });
+    }
+
+    frames
+}
+
+/// The locals of the frame that are in scope, shadowed variables are left out
+fn live_locals(vm: &Vm<'_, '_>, frame: &FrameInfo) -> Vec<(String, Value)> {
+    let block = &vm.blocks[frame.block];
+    let mut locals: Vec<(String, Value)> = Vec::new();
+
+    for local in block
+        .locals
+        .iter()
+        .filter(|local| local.is_live(frame.instr))
+    {
+        let value = match vm.stack.get(frame.stack_frame_offset + local.offset) {
+            Some(value) => value.unpack(),
+            None => continue,
+        };
+        let name = local.name.as_str();
+
+        match locals.iter_mut().find(|(existing, _)| existing == name) {
+            Some(shadowed) => shadowed.1 = value,
+            None => locals.push((name.to_string(), value)),
+        }
+    }
+
+    locals
+}
+
+fn display_value(value: Value) -> String {
+    match value {
+        Value::String(str) => format!("{:?}", str.as_str()),
+        Value::Array(_) | Value::Object(_) | Value::NativeU(_) => format!("{value:?}"),
+        _ => value.to_string(),
+    }
+}
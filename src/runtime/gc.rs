@@ -2,7 +2,10 @@
 //!
 //! The structure of the GC might change, but for now it's simply a `LinkedList` of `Object`s.
 
+pub mod shape;
+
 use std::{
+    cell::{Cell, RefCell},
     collections::LinkedList,
     fmt::{Debug, Formatter},
     hash::{Hash, Hasher},
@@ -12,7 +15,13 @@ use std::{
 
 use dbg_pls::DebugPls;
 
-use crate::{runtime::vm::Value, HashMap, HashSet};
+use crate::{
+    runtime::{
+        gc::shape::{FieldCache, ObjectData, Shapes},
+        vm::Value,
+    },
+    HashMap,
+};
 
 /// A pointer to a garbage collected value. This pointer *must* always be valid, and a value
 /// is only allowed to be freed once no Gc is pointing at it anymore. This is achieved through
@@ -49,7 +58,7 @@ impl<T: Debug + ?Sized> Debug for Gc<T> {
 
 impl<T: ?Sized> Clone for Gc<T> {
     fn clone(&self) -> Self {
-        Self { ..*self }
+        *self
     }
 }
 
@@ -58,11 +67,9 @@ impl<T: ?Sized> Copy for Gc<T> {}
 /// An reference to an interned String. Hashing and Equality are O(1) and just look at the pointer address
 #[derive(Clone, Copy)]
 pub struct Symbol {
-    gc: Gc<str>,
+    gc: Gc<HeapObject>,
 }
 
-type ObjectMap = HashMap<Symbol, Value>;
-
 /// A reference to an Object on the heap.
 /// ```js
 /// let x = {};
@@ -74,6 +81,23 @@ pub struct Object {
     gc: Gc<HeapObject>,
 }
 
+/// A reference to an Array on the heap.
+/// ```js
+/// let x = [];
+/// ```
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub struct Array {
+    gc: Gc<HeapObject>,
+}
+
+/// A reference to an int that is too big to be NaN-boxed inline, see [`nanbox`](super::nanbox)
+#[cfg(feature = "nanbox")]
+#[derive(Clone, Copy)]
+pub struct BoxedInt {
+    gc: Gc<HeapObject>,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 struct HeapObject {
@@ -81,16 +105,49 @@ struct HeapObject {
 }
 
 #[derive(Debug)]
-#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 enum HeapObjectKind {
-    String(Gc<str>),
-    Object(ObjectMap),
+    String(Box<str>),
+    Object {
+        data: RefCell<ObjectData>,
+        /// Fields that the object doesn't have are looked up here
+        prototype: Option<Object>,
+    },
+    Array(Vec<Value>),
+    #[cfg(feature = "nanbox")]
+    Int(i64),
+}
+
+#[cfg(feature = "_debug")]
+impl dbg_pls::DebugPls for HeapObjectKind {
+    fn fmt(&self, f: dbg_pls::Formatter<'_>) {
+        match self {
+            HeapObjectKind::String(str) => f.debug_tuple_struct("String").field(str).finish(),
+            HeapObjectKind::Object { data, .. } => {
+                let entries = data.borrow().entries();
+                let mut map = f.debug_map();
+                for (name, value) in &entries {
+                    map = map.entry(name, value);
+                }
+                map.finish();
+            }
+            HeapObjectKind::Array(elements) => {
+                f.debug_tuple_struct("Array").field(elements).finish()
+            }
+            #[cfg(feature = "nanbox")]
+            HeapObjectKind::Int(int) => f.debug_tuple_struct("Int").field(int).finish(),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct RtAlloc {
-    symbols: HashSet<NonNullStrStructuralEq>,
+    /// The interned strings, pointing to their `HeapObject`
+    symbols: HashMap<NonNullStrStructuralEq, NonNull<HeapObject>>,
+    /// The boxed ints, so that every int is only boxed once
+    #[cfg(feature = "nanbox")]
+    boxed_ints: HashMap<i64, NonNull<HeapObject>>,
     objects: LinkedList<HeapObject>,
+    shapes: Shapes,
 }
 
 #[derive(Debug)]
@@ -117,28 +174,44 @@ impl RtAlloc {
     /// Promise to not forget to mark any roots and to not deref `Gc<T>` after you've dropped me 🥺
     pub unsafe fn new() -> Self {
         Self {
-            symbols: HashSet::default(),
+            symbols: HashMap::default(),
+            #[cfg(feature = "nanbox")]
+            boxed_ints: HashMap::default(),
             objects: LinkedList::new(),
+            shapes: Shapes::new(),
         }
     }
 
-    fn alloc_str(&mut self, str: &str) -> Gc<str> {
-        let ptr = Box::into_raw(str.to_owned().into_boxed_str());
-        // SAFETY: Box cannot be null
-        let new_nonnull = unsafe { NonNull::new_unchecked(ptr) };
-        let gc = Gc { ptr: new_nonnull };
-        let object = HeapObject {
-            kind: HeapObjectKind::String(gc),
-        };
+    fn alloc_str(&mut self, str: &str) -> NonNull<HeapObject> {
+        self.objects.push_back(HeapObject {
+            kind: HeapObjectKind::String(str.into()),
+        });
+
+        NonNull::from(self.objects.back().unwrap())
+    }
+
+    /// Allocates an object with the fields, which are added in order
+    pub fn alloc_obj(&mut self, fields: impl IntoIterator<Item = (Symbol, Value)>) -> Object {
+        let mut data = ObjectData::new(&self.shapes);
+        for (name, value) in fields {
+            data.set(&mut self.shapes, name, value);
+        }
 
-        self.objects.push_back(object);
+        self.alloc_obj_data(data, None)
+    }
 
-        gc
+    /// Allocates an empty object that inherits the fields of the prototype
+    pub fn alloc_obj_with_prototype(&mut self, prototype: Object) -> Object {
+        let data = ObjectData::new(&self.shapes);
+        self.alloc_obj_data(data, Some(prototype))
     }
 
-    pub fn alloc_obj(&mut self, obj: ObjectMap) -> Object {
+    fn alloc_obj_data(&mut self, data: ObjectData, prototype: Option<Object>) -> Object {
         self.objects.push_back(HeapObject {
-            kind: HeapObjectKind::Object(obj),
+            kind: HeapObjectKind::Object {
+                data: RefCell::new(data),
+                prototype,
+            },
         });
 
         let ptr = self.objects.back().unwrap();
@@ -150,41 +223,71 @@ impl RtAlloc {
         }
     }
 
+    pub fn alloc_array(&mut self, elements: Vec<Value>) -> Array {
+        self.objects.push_back(HeapObject {
+            kind: HeapObjectKind::Array(elements),
+        });
+
+        let ptr = self.objects.back().unwrap();
+
+        Array {
+            gc: Gc {
+                ptr: NonNull::from(ptr),
+            },
+        }
+    }
+
     pub fn intern_string(&mut self, str: &str) -> Symbol {
         let original_nonnull = NonNull::from(str);
 
-        if let Some(interned) = self.symbols.get(&NonNullStrStructuralEq(original_nonnull)) {
-            Symbol::new(Gc { ptr: interned.0 })
-        } else {
-            let allocated = self.alloc_str(str);
-            self.symbols.insert(NonNullStrStructuralEq(allocated.ptr));
-            Symbol::new(allocated)
-        }
+        let ptr = match self.symbols.get(&NonNullStrStructuralEq(original_nonnull)) {
+            Some(interned) => *interned,
+            None => {
+                let allocated = self.alloc_str(str);
+                // SAFETY: We just allocated it, and the string never moves, even if the list does
+                let allocated_str = unsafe { allocated.as_ref() }.as_str();
+                self.symbols.insert(
+                    NonNullStrStructuralEq(NonNull::from(allocated_str)),
+                    allocated,
+                );
+                allocated
+            }
+        };
+
+        Symbol { gc: Gc { ptr } }
+    }
+
+    /// Boxes an int that doesn't fit into a NaN-boxed value
+    #[cfg(feature = "nanbox")]
+    pub(super) fn box_int(&mut self, int: i64) -> BoxedInt {
+        let objects = &mut self.objects;
+        let ptr = *self.boxed_ints.entry(int).or_insert_with(|| {
+            objects.push_back(HeapObject {
+                kind: HeapObjectKind::Int(int),
+            });
+            NonNull::from(objects.back().unwrap())
+        });
+
+        BoxedInt { gc: Gc { ptr } }
     }
 }
 
-impl Drop for RtAlloc {
-    fn drop(&mut self) {
-        // free all interned strings
-        for str in &self.symbols {
-            let raw = str.0.as_ptr();
-            // SAFETY: No one has free these, see `Gc<T>`
-            drop(unsafe { Box::from_raw(raw) });
+impl HeapObject {
+    fn as_str(&self) -> &str {
+        match self.kind {
+            HeapObjectKind::String(ref str) => str,
+            _ => unreachable!(),
         }
     }
 }
 
 impl Symbol {
-    pub fn new(gc: Gc<str>) -> Self {
-        Self { gc }
-    }
-
     fn address(&self) -> usize {
-        self.gc.ptr.as_ptr() as *mut u8 as usize
+        self.gc.ptr.as_ptr() as usize
     }
 
     pub fn as_str(&self) -> &str {
-        &*self.gc
+        self.gc.deref().as_str()
     }
 }
 
@@ -223,15 +326,86 @@ impl dbg_pls::DebugPls for Symbol {
     }
 }
 
-impl Deref for Object {
-    type Target = ObjectMap;
+impl Object {
+    pub(super) fn address(&self) -> usize {
+        self.gc.ptr.as_ptr() as usize
+    }
 
-    fn deref(&self) -> &Self::Target {
+    fn data(&self) -> &RefCell<ObjectData> {
+        match self.gc.deref().kind {
+            HeapObjectKind::Object { ref data, .. } => data,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn prototype(&self) -> Option<Object> {
         match self.gc.deref().kind {
-            HeapObjectKind::Object(ref map) => map,
+            HeapObjectKind::Object { prototype, .. } => prototype,
             _ => unreachable!(),
         }
     }
+
+    /// Gets a field of the object itself, without looking at its prototype
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        self.data().borrow().get(name)
+    }
+
+    /// Gets the field of the object, or of the closest prototype that has it. There can't be
+    /// cycles, since the prototype is set when the object is created.
+    pub fn lookup(&self, name: Symbol) -> Option<Value> {
+        let mut object = *self;
+        loop {
+            if let Some(value) = object.get(name) {
+                return Some(value);
+            }
+            object = object.prototype()?;
+        }
+    }
+
+    /// Sets the field, adding it if it doesn't exist yet
+    pub fn set(&self, alloc: &mut RtAlloc, name: Symbol, value: Value) {
+        self.data().borrow_mut().set(&mut alloc.shapes, name, value);
+    }
+
+    /// Gets the field using the inline cache of the instruction, see [`shape`]
+    #[inline]
+    pub fn get_cached(&self, name: Symbol, cache: &Cell<Option<FieldCache>>) -> Option<Value> {
+        self.data().borrow().get_cached(name, cache)
+    }
+
+    /// Sets the field using the inline cache of the instruction, see [`shape`]
+    #[inline]
+    pub fn set_cached(
+        &self,
+        alloc: &mut RtAlloc,
+        name: Symbol,
+        value: Value,
+        cache: &Cell<Option<FieldCache>>,
+    ) {
+        self.data()
+            .borrow_mut()
+            .set_cached(&mut alloc.shapes, name, value, cache);
+    }
+
+    /// All fields with their values, in the order they were added unless the object has too many
+    /// fields, see [`shape`]
+    pub fn entries(&self) -> Vec<(Symbol, Value)> {
+        self.data().borrow().entries()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data().borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
 }
 
 impl Debug for Object {
@@ -239,3 +413,67 @@ impl Debug for Object {
         Debug::fmt(self.gc.deref(), f)
     }
 }
+
+impl Array {
+    pub(super) fn address(&self) -> usize {
+        self.gc.ptr.as_ptr() as usize
+    }
+}
+
+impl Deref for Array {
+    type Target = Vec<Value>;
+
+    fn deref(&self) -> &Self::Target {
+        match self.gc.deref().kind {
+            HeapObjectKind::Array(ref elements) => elements,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl PartialEq for Array {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl Debug for Array {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.gc.deref(), f)
+    }
+}
+
+/// Converts the references from and to thin raw pointers, for the NaN-boxed values
+#[cfg(feature = "nanbox")]
+macro_rules! raw_pointers {
+    ($($ty:ident),*) => {$(
+        impl $ty {
+            pub(super) fn into_raw(self) -> *const () {
+                self.gc.ptr.as_ptr() as *const ()
+            }
+
+            /// # Safety
+            /// The pointer must come from `into_raw` of the same type and the value must still be alive
+            pub(super) unsafe fn from_raw(ptr: *const ()) -> Self {
+                Self {
+                    gc: Gc {
+                        ptr: NonNull::new_unchecked(ptr as *mut HeapObject),
+                    },
+                }
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "nanbox")]
+raw_pointers!(Symbol, Object, Array, BoxedInt);
+
+#[cfg(feature = "nanbox")]
+impl BoxedInt {
+    pub(super) fn get(self) -> i64 {
+        match self.gc.deref().kind {
+            HeapObjectKind::Int(int) => int,
+            _ => unreachable!(),
+        }
+    }
+}
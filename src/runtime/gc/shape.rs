@@ -0,0 +1,285 @@
+//! Hidden classes for objects
+//!
+//! Objects don't store their fields in a hash map. Every object points to a [`Shape`] instead,
+//! which maps the names of its fields to the slots where their values are stored. Shapes form a
+//! tree: adding a field moves the object to the child of its shape for that name, and every child
+//! is only created once. So all objects that got the same fields in the same order share a shape,
+//! and the shape alone says where a field is stored.
+//!
+//! `GetField` and `SetField` use this for their inline cache, see [`FieldCache`]. They remember the
+//! shape of the last object and the slot of the field, and if the next object has the same shape,
+//! the slot is used directly without looking up the name.
+//!
+//! Objects with more than [`MAX_SHAPE_FIELDS`] fields are most likely used as dictionaries with
+//! dynamic keys, which would only create lots of shapes that are never shared. They switch to a
+//! plain hash map and are never cached.
+
+use std::{
+    cell::{Cell, RefCell},
+    ptr::NonNull,
+};
+
+use crate::{
+    runtime::{
+        gc::{Gc, Symbol},
+        vm::Value,
+    },
+    HashMap,
+};
+
+/// Objects with more fields are stored as a hash map
+pub const MAX_SHAPE_FIELDS: usize = 32;
+
+/// The layout of an object. Two shapes are only equal if they are the same allocation.
+#[derive(Clone, Copy)]
+pub struct Shape {
+    gc: Gc<ShapeData>,
+}
+
+struct ShapeData {
+    /// The names of the fields, in the order of their slots
+    fields: Vec<Symbol>,
+    slots: HashMap<Symbol, usize>,
+    /// The shapes with one more field, created when they are first needed
+    transitions: RefCell<HashMap<Symbol, Shape>>,
+}
+
+/// Owns all shapes. They are only freed together with the `RtAlloc`, so a shape in a cache can
+/// never be confused with a new shape that got the same address.
+pub struct Shapes {
+    /// The shape of an empty object
+    root: Shape,
+    /// All shapes, freed when this is dropped
+    all: Vec<NonNull<ShapeData>>,
+}
+
+impl Shapes {
+    pub(super) fn new() -> Self {
+        let mut shapes = Self {
+            root: Shape {
+                gc: Gc {
+                    ptr: NonNull::dangling(),
+                },
+            },
+            all: Vec::new(),
+        };
+        shapes.root = shapes.alloc(Vec::new());
+        shapes
+    }
+
+    fn alloc(&mut self, fields: Vec<Symbol>) -> Shape {
+        let slots = fields
+            .iter()
+            .enumerate()
+            .map(|(slot, name)| (*name, slot))
+            .collect();
+        let ptr = NonNull::from(Box::leak(Box::new(ShapeData {
+            fields,
+            slots,
+            transitions: RefCell::default(),
+        })));
+        self.all.push(ptr);
+        Shape { gc: Gc { ptr } }
+    }
+
+    /// The shape after adding the field `name` to an object with the shape
+    fn with_field(&mut self, shape: Shape, name: Symbol) -> Shape {
+        if let Some(next) = shape.data().transitions.borrow().get(&name) {
+            return *next;
+        }
+
+        let mut fields = shape.data().fields.clone();
+        fields.push(name);
+        let next = self.alloc(fields);
+        shape.data().transitions.borrow_mut().insert(name, next);
+        next
+    }
+}
+
+impl std::fmt::Debug for Shapes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shapes")
+            .field("count", &self.all.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Shapes {
+    fn drop(&mut self) {
+        for ptr in self.all.drain(..) {
+            // SAFETY: the pointers come from `Box::leak` in `alloc` and are only freed here. Like
+            // for all `Gc`s, nothing may use a shape after its `RtAlloc` is dropped.
+            drop(unsafe { Box::from_raw(ptr.as_ptr()) });
+        }
+    }
+}
+
+impl Shape {
+    fn data(&self) -> &ShapeData {
+        &self.gc
+    }
+
+    fn slot(&self, name: Symbol) -> Option<usize> {
+        self.data().slots.get(&name).copied()
+    }
+}
+
+impl PartialEq for Shape {
+    fn eq(&self, other: &Self) -> bool {
+        self.gc.ptr == other.gc.ptr
+    }
+}
+
+impl std::fmt::Debug for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(&self.data().fields).finish()
+    }
+}
+
+/// The monomorphic inline cache of a field instruction, filled by the last object it accessed
+#[derive(Debug, Clone, Copy)]
+pub struct FieldCache {
+    /// The shape of the object before the access
+    shape: Shape,
+    slot: usize,
+    /// If the field was added by a `SetField`, the shape of the object afterwards
+    added: Option<Shape>,
+}
+
+/// The fields of an object
+#[derive(Debug)]
+pub enum ObjectData {
+    Shaped { shape: Shape, slots: Vec<Value> },
+    Dictionary(HashMap<Symbol, Value>),
+}
+
+impl ObjectData {
+    pub(super) fn new(shapes: &Shapes) -> Self {
+        ObjectData::Shaped {
+            shape: shapes.root,
+            slots: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        match self {
+            ObjectData::Shaped { shape, slots } => shape.slot(name).map(|slot| slots[slot]),
+            ObjectData::Dictionary(map) => map.get(&name).copied(),
+        }
+    }
+
+    /// Sets the field, adding it if it doesn't exist yet
+    pub fn set(&mut self, shapes: &mut Shapes, name: Symbol, value: Value) {
+        match self {
+            ObjectData::Shaped { shape, slots } => match shape.slot(name) {
+                Some(slot) => slots[slot] = value,
+                None if slots.len() < MAX_SHAPE_FIELDS => {
+                    *shape = shapes.with_field(*shape, name);
+                    slots.push(value);
+                }
+                None => {
+                    let mut map = shape
+                        .data()
+                        .fields
+                        .iter()
+                        .copied()
+                        .zip(slots.iter().copied())
+                        .collect::<HashMap<_, _>>();
+                    map.insert(name, value);
+                    *self = ObjectData::Dictionary(map);
+                }
+            },
+            ObjectData::Dictionary(map) => {
+                map.insert(name, value);
+            }
+        }
+    }
+
+    /// Like [`get`](Self::get), but skips the lookup if the object has the shape in the cache.
+    /// Fills the cache otherwise.
+    pub fn get_cached(&self, name: Symbol, cache: &Cell<Option<FieldCache>>) -> Option<Value> {
+        let (shape, slots) = match self {
+            ObjectData::Shaped { shape, slots } => (shape, slots),
+            ObjectData::Dictionary(map) => return map.get(&name).copied(),
+        };
+
+        match cache.get() {
+            Some(cached) if cached.shape == *shape => Some(slots[cached.slot]),
+            _ => {
+                let slot = shape.slot(name)?;
+                cache.set(Some(FieldCache {
+                    shape: *shape,
+                    slot,
+                    added: None,
+                }));
+                Some(slots[slot])
+            }
+        }
+    }
+
+    /// Like [`set`](Self::set), but skips the lookup and the transition if the object has the
+    /// shape in the cache. Fills the cache otherwise.
+    pub fn set_cached(
+        &mut self,
+        shapes: &mut Shapes,
+        name: Symbol,
+        value: Value,
+        cache: &Cell<Option<FieldCache>>,
+    ) {
+        let (shape, slots) = match self {
+            ObjectData::Shaped { shape, slots } => (shape, slots),
+            ObjectData::Dictionary(_) => return self.set(shapes, name, value),
+        };
+
+        if let Some(cached) = cache.get().filter(|cached| cached.shape == *shape) {
+            match cached.added {
+                Some(added) => {
+                    *shape = added;
+                    slots.push(value);
+                }
+                None => slots[cached.slot] = value,
+            }
+            return;
+        }
+
+        let before = *shape;
+        let existing = before.slot(name);
+        self.set(shapes, name, value);
+
+        if let ObjectData::Shaped { shape, .. } = self {
+            let (slot, added) = match existing {
+                Some(slot) => (slot, None),
+                None => (before.data().fields.len(), Some(*shape)),
+            };
+            cache.set(Some(FieldCache {
+                shape: before,
+                slot,
+                added,
+            }));
+        }
+    }
+
+    /// All fields with their values, in the order they were added unless the object is a
+    /// dictionary
+    pub fn entries(&self) -> Vec<(Symbol, Value)> {
+        match self {
+            ObjectData::Shaped { shape, slots } => shape
+                .data()
+                .fields
+                .iter()
+                .copied()
+                .zip(slots.iter().copied())
+                .collect(),
+            ObjectData::Dictionary(map) => {
+                map.iter().map(|(name, value)| (*name, *value)).collect()
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ObjectData::Shaped { slots, .. } => slots.len(),
+            ObjectData::Dictionary(map) => map.len(),
+        }
+    }
+}
@@ -1,4 +1,12 @@
 pub mod bytecode;
+mod coverage;
+mod debugger;
 pub mod gc;
-mod stack_frame;
+#[cfg(feature = "nanbox")]
+mod nanbox;
+pub mod native;
+mod profiler;
+pub mod register_vm;
+pub(crate) mod stack_frame;
+pub mod verify;
 pub mod vm;
@@ -0,0 +1,149 @@
+//! NaN-boxed values, enabled with the `nanbox` feature
+//!
+//! A [`Value`] is 16 bytes, which is moved around on every push and pop of the VM. A `f64` has
+//! many more NaN bit patterns than it needs, so with this feature the stack stores every value in 8
+//! bytes instead. Numbers are stored as they are, all other values are stored in the payload of a
+//! negative quiet NaN:
+//!
+//! ```text
+//!  sign  exponent     quiet  tag  payload
+//!  1     11111111111  1      ttt  48 bit pointer or index
+//! ```
+//!
+//! Real NaNs are canonicalized to a positive quiet NaN, so they are never mistaken for a tagged
+//! value. Pointers to heap objects fit into 48 bits on all supported platforms.
+//!
+//! Ints are stored in the payload of the other positive quiet NaNs. Ints with up to 50 bits are
+//! stored inline, bigger ones can't fit and are boxed on the heap of the [`RtAlloc`] instead, which
+//! interns them:
+//!
+//! ```text
+//!  sign  exponent     quiet  inline  boxed  payload
+//!  0     11111111111  1      1       -      50 bit int
+//!  0     11111111111  1      0       1      48 bit pointer to the int
+//! ```
+
+use std::fmt::{Debug, Formatter};
+
+use crate::{
+    runtime::{
+        gc::{Array, BoxedInt, Object, RtAlloc, Symbol},
+        vm::Value,
+    },
+    util,
+};
+
+/// The bits that are set for all tagged values
+const TAGGED: u64 = 0xFFF8_0000_0000_0000;
+const TAG_SHIFT: u32 = 48;
+const TAG_MASK: u64 = 0b111 << TAG_SHIFT;
+const PAYLOAD_MASK: u64 = (1 << TAG_SHIFT) - 1;
+
+const CANONICAL_NAN: u64 = 0x7FF8_0000_0000_0000;
+
+/// The bits that are set for ints that are stored inline, `INLINE_INT_MASK` selects them
+const INLINE_INT: u64 = 0x7FFC_0000_0000_0000;
+const INLINE_INT_MASK: u64 = 0xFFFC_0000_0000_0000;
+/// How far an inline int is shifted left to get its sign bit into the sign bit of an `i64`
+const INLINE_INT_SHIFT: u32 = INLINE_INT_MASK.count_ones();
+/// The bits that are set for boxed ints, `BOXED_INT_MASK` selects all bits above the pointer
+const BOXED_INT: u64 = 0x7FFA_0000_0000_0000;
+const BOXED_INT_MASK: u64 = !PAYLOAD_MASK;
+
+const TAG_NULL: u64 = 0;
+const TAG_BOOL: u64 = 1;
+const TAG_STRING: u64 = 2;
+const TAG_ARRAY: u64 = 3;
+const TAG_OBJECT: u64 = 4;
+const TAG_FUNCTION: u64 = 5;
+const TAG_NATIVE_FUNCTION: u64 = 6;
+const TAG_NATIVE_U: u64 = 7;
+
+/// A [`Value`] packed into 8 bytes
+#[derive(Clone, Copy)]
+pub struct NanBox(u64);
+
+util::assert_size!(NanBox <= 8);
+
+impl NanBox {
+    fn tagged(tag: u64, payload: usize) -> Self {
+        let payload = payload as u64;
+        debug_assert!(payload <= PAYLOAD_MASK, "payload {payload:#x} is too big");
+        Self(TAGGED | tag << TAG_SHIFT | payload)
+    }
+
+    fn tagged_ptr(tag: u64, ptr: *const ()) -> Self {
+        Self::tagged(tag, ptr as usize)
+    }
+
+    fn payload(self) -> usize {
+        (self.0 & PAYLOAD_MASK) as usize
+    }
+
+    fn ptr(self) -> *const () {
+        self.payload() as *const ()
+    }
+
+    /// Unpacks the value, the inverse of [`Value::pack`]
+    #[inline]
+    pub fn unpack(self) -> Value {
+        if self.0 & TAGGED != TAGGED {
+            return if self.0 & INLINE_INT_MASK == INLINE_INT {
+                // shift it back to the right to sign extend it
+                Value::Int(((self.0 << INLINE_INT_SHIFT) as i64) >> INLINE_INT_SHIFT)
+            } else if self.0 & BOXED_INT_MASK == BOXED_INT {
+                // SAFETY: The pointer was created from a `BoxedInt` in `Value::pack`, and the box is
+                // kept alive by the `RtAlloc` while it is on the stack
+                Value::Int(unsafe { BoxedInt::from_raw(self.ptr()) }.get())
+            } else {
+                Value::Num(f64::from_bits(self.0))
+            };
+        }
+
+        // SAFETY: The pointers were created from references of the same type in `Value::pack`, and
+        // the values are kept alive by the `RtAlloc` while they are on the stack
+        unsafe {
+            match (self.0 & TAG_MASK) >> TAG_SHIFT {
+                TAG_NULL => Value::Null,
+                TAG_BOOL => Value::Bool(self.payload() != 0),
+                TAG_STRING => Value::String(Symbol::from_raw(self.ptr())),
+                TAG_ARRAY => Value::Array(Array::from_raw(self.ptr())),
+                TAG_OBJECT => Value::Object(Object::from_raw(self.ptr())),
+                TAG_FUNCTION => Value::Function(self.payload()),
+                TAG_NATIVE_FUNCTION => Value::NativeFunction(self.payload()),
+                TAG_NATIVE_U => Value::NativeU(self.payload()),
+                _ => unreachable!("the tag has only three bits"),
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Packs the value into 8 bytes to be stored on the stack, ints that are too big are boxed in
+    /// the `alloc`
+    #[inline]
+    pub fn pack(self, alloc: &mut RtAlloc) -> NanBox {
+        match self {
+            Value::Num(num) if num.is_nan() => NanBox(CANONICAL_NAN),
+            Value::Num(num) => NanBox(num.to_bits()),
+            Value::Int(int) if (int << INLINE_INT_SHIFT) >> INLINE_INT_SHIFT == int => {
+                NanBox(INLINE_INT | (int as u64 & !INLINE_INT_MASK))
+            }
+            Value::Int(int) => NanBox(BOXED_INT | alloc.box_int(int).into_raw() as u64),
+            Value::Null => NanBox::tagged(TAG_NULL, 0),
+            Value::Bool(bool) => NanBox::tagged(TAG_BOOL, bool as usize),
+            Value::String(str) => NanBox::tagged_ptr(TAG_STRING, str.into_raw()),
+            Value::Array(array) => NanBox::tagged_ptr(TAG_ARRAY, array.into_raw()),
+            Value::Object(object) => NanBox::tagged_ptr(TAG_OBJECT, object.into_raw()),
+            Value::Function(function) => NanBox::tagged(TAG_FUNCTION, function),
+            Value::NativeFunction(native) => NanBox::tagged(TAG_NATIVE_FUNCTION, native),
+            Value::NativeU(n) => NanBox::tagged(TAG_NATIVE_U, n),
+        }
+    }
+}
+
+impl Debug for NanBox {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.unpack(), f)
+    }
+}
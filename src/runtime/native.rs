@@ -0,0 +1,65 @@
+//! Native functions, implemented in Rust and callable from scripts
+//!
+//! If an identifier is not a local variable, the compiler looks it up here. Native functions
+//! are referred to by their index into the native function table, so they can be used as values
+//! just like normal functions.
+
+mod io;
+mod json;
+mod object;
+
+use std::io::{BufRead, Write};
+
+use crate::runtime::{
+    gc::RtAlloc,
+    vm::{StackValue, Value, VmError},
+};
+
+/// Index into the native function table
+pub type NativeFunction = usize;
+
+type NativeFn = fn(&mut NativeCtx<'_>, &[Value]) -> Result<Value, VmError>;
+
+/// The parts of the VM that native functions have access to
+pub struct NativeCtx<'a> {
+    pub alloc: &'a mut RtAlloc,
+    pub stdout: &'a mut dyn Write,
+    pub stdin: &'a mut dyn BufRead,
+}
+
+static NATIVES: &[(&str, NativeFn)] = &[
+    ("create", object::create),
+    ("input", io::input),
+    ("json_parse", json::parse),
+    ("json_stringify", json::stringify),
+];
+
+/// Looks up the native function with that name
+pub fn lookup(name: &str) -> Option<NativeFunction> {
+    NATIVES
+        .iter()
+        .position(|(native_name, _)| *native_name == name)
+}
+
+/// Whether there is a native function with that index
+pub fn exists(native: NativeFunction) -> bool {
+    native < NATIVES.len()
+}
+
+/// The name of the native function, the inverse of [`lookup`]
+pub fn name(native: NativeFunction) -> &'static str {
+    NATIVES[native].0
+}
+
+/// Calls the native function with the arguments. The arguments are not removed from the stack.
+pub(super) fn call(
+    native: NativeFunction,
+    ctx: &mut NativeCtx<'_>,
+    args: &[StackValue],
+) -> Result<Value, VmError> {
+    let (_, function) = NATIVES[native];
+    // native functions match on the arguments, so they get them unpacked
+    #[cfg(feature = "nanbox")]
+    let args = &args.iter().map(|arg| arg.unpack()).collect::<Vec<_>>();
+    function(ctx, args)
+}
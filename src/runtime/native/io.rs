@@ -0,0 +1,42 @@
+//! Native functions for reading and writing the IO streams of the host
+
+use crate::runtime::{
+    native::NativeCtx,
+    vm::{err, Value, VmError},
+};
+
+/// `input(prompt?)`
+///
+/// Prints the prompt and reads a single line without the line break. Returns `null` if the input
+/// has ended.
+pub(super) fn input(ctx: &mut NativeCtx<'_>, args: &[Value]) -> Result<Value, VmError> {
+    match args {
+        [] => {}
+        [prompt] => {
+            write!(ctx.stdout, "{prompt}").map_err(|_| err("failed to write to stdout"))?;
+            ctx.stdout
+                .flush()
+                .map_err(|_| err("failed to write to stdout"))?;
+        }
+        _ => return Err(err("input: expected 0 or 1 arguments")),
+    }
+
+    let mut line = String::new();
+    let read = ctx
+        .stdin
+        .read_line(&mut line)
+        .map_err(|_| err("failed to read from stdin"))?;
+
+    if read == 0 {
+        return Ok(Value::Null);
+    }
+
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+
+    Ok(Value::String(ctx.alloc.intern_string(&line)))
+}
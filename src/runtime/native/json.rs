@@ -0,0 +1,439 @@
+//! `json_parse` and `json_stringify`
+//!
+//! Both are handwritten. Parsing allocates objects, arrays and strings into the GC. Stringifying
+//! sorts object keys, since objects with many fields don't keep their insertion order.
+
+use std::fmt::Write;
+
+use crate::runtime::{
+    gc::{RtAlloc, Symbol},
+    native::NativeCtx,
+    vm::{err, Value, VmError},
+};
+
+/// Protects the native stack, deeply nested documents are rejected instead
+const MAX_DEPTH: usize = 128;
+
+type JsonResult<T = ()> = Result<T, VmError>;
+
+/// `json_parse(str)`
+pub(super) fn parse(ctx: &mut NativeCtx<'_>, args: &[Value]) -> JsonResult<Value> {
+    let source = match args {
+        [Value::String(source)] => *source,
+        [_] => return Err(err("json_parse: expected a string")),
+        _ => return Err(err("json_parse: expected 1 argument")),
+    };
+
+    let mut parser = Parser {
+        src: source.as_bytes(),
+        pos: 0,
+        depth: 0,
+        alloc: ctx.alloc,
+    };
+
+    let value = parser.value()?;
+    parser.skip_whitespace();
+
+    if parser.pos != parser.src.len() {
+        return Err(err("json_parse: unexpected characters after the value"));
+    }
+
+    Ok(value)
+}
+
+/// `json_stringify(value, indent?)`
+pub(super) fn stringify(ctx: &mut NativeCtx<'_>, args: &[Value]) -> JsonResult<Value> {
+    let (value, indent) = match args {
+        [value] => (*value, String::new()),
+        [value, Value::Null] => (*value, String::new()),
+        [value, Value::Num(num)] => (*value, " ".repeat(num.clamp(0.0, 10.0) as usize)),
+        [value, Value::Int(int)] => (*value, " ".repeat((*int).clamp(0, 10) as usize)),
+        [value, Value::String(str)] => (*value, str.to_string()),
+        [_, _] => return Err(err("json_stringify: indent must be a number or a string")),
+        _ => return Err(err("json_stringify: expected 1 or 2 arguments")),
+    };
+
+    let mut stringifier = Stringifier {
+        out: String::new(),
+        indent,
+        depth: 0,
+        seen: Vec::new(),
+    };
+
+    stringifier.value(value)?;
+
+    Ok(Value::String(ctx.alloc.intern_string(&stringifier.out)))
+}
+
+struct Parser<'src, 'gc> {
+    src: &'src [u8],
+    pos: usize,
+    depth: usize,
+    alloc: &'gc mut RtAlloc,
+}
+
+impl Parser<'_, '_> {
+    fn value(&mut self) -> JsonResult<Value> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.keyword("true", Value::Bool(true)),
+            Some(b'f') => self.keyword("false", Value::Bool(false)),
+            Some(b'n') => self.keyword("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(err("json_parse: unexpected character")),
+            None => Err(err("json_parse: unexpected end of input")),
+        }
+    }
+
+    fn nested(&mut self, parser: fn(&mut Self) -> JsonResult<Value>) -> JsonResult<Value> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(err("json_parse: nesting too deep"));
+        }
+        let value = parser(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> JsonResult<Value> {
+        self.expect(b'{')?;
+        // in source order, so that objects with the same keys get the same shape
+        let mut fields = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(self.alloc.alloc_obj(fields)));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(err("json_parse: expected a string as object key"));
+            }
+            let key = self.string()?;
+
+            self.skip_whitespace();
+            self.expect(b':')?;
+
+            let value = self.value()?;
+            fields.push((key, value));
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(b',') => {}
+                Some(b'}') => break,
+                Some(_) => return Err(err("json_parse: expected `,` or `}` in object")),
+                None => return Err(err("json_parse: unexpected end of input")),
+            }
+        }
+
+        Ok(Value::Object(self.alloc.alloc_obj(fields)))
+    }
+
+    fn array(&mut self) -> JsonResult<Value> {
+        self.expect(b'[')?;
+        let mut elements = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(self.alloc.alloc_array(elements)));
+        }
+
+        loop {
+            elements.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(b',') => {}
+                Some(b']') => break,
+                Some(_) => return Err(err("json_parse: expected `,` or `]` in array")),
+                None => return Err(err("json_parse: unexpected end of input")),
+            }
+        }
+
+        Ok(Value::Array(self.alloc.alloc_array(elements)))
+    }
+
+    fn string(&mut self) -> JsonResult<Symbol> {
+        self.expect(b'"')?;
+        let mut buffer = String::new();
+
+        loop {
+            let start = self.pos;
+            // copy over everything that doesn't need special handling at once
+            while let Some(byte) = self.peek() {
+                if byte == b'"' || byte == b'\\' || byte < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // we only ever stop at ascii characters, so this is still valid utf-8
+            buffer.push_str(std::str::from_utf8(&self.src[start..self.pos]).unwrap());
+
+            match self.next() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let escaped = match self.next() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\x08',
+                        Some(b'f') => '\x0C',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(err("json_parse: invalid escape sequence")),
+                    };
+                    buffer.push(escaped);
+                }
+                Some(_) => return Err(err("json_parse: control character in string")),
+                None => return Err(err("json_parse: unterminated string")),
+            }
+        }
+
+        Ok(self.alloc.intern_string(&buffer))
+    }
+
+    /// Parses the part after `\u`, including a second escape for surrogate pairs
+    fn unicode_escape(&mut self) -> JsonResult<char> {
+        let first = self.hex4()?;
+
+        let code_point = if (0xD800..0xDC00).contains(&first) {
+            if self.next() != Some(b'\\') || self.next() != Some(b'u') {
+                return Err(err("json_parse: unpaired surrogate in string"));
+            }
+            let second = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&second) {
+                return Err(err("json_parse: unpaired surrogate in string"));
+            }
+            0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)
+        } else {
+            first
+        };
+
+        char::from_u32(code_point).ok_or_else(|| err("json_parse: unpaired surrogate in string"))
+    }
+
+    fn hex4(&mut self) -> JsonResult<u32> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| err("json_parse: unexpected end of input"))?;
+
+        let mut value = 0;
+        for digit in digits {
+            let digit = (*digit as char)
+                .to_digit(16)
+                .ok_or_else(|| err("json_parse: invalid escape sequence"))?;
+            value = value * 16 + digit;
+        }
+
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn number(&mut self) -> JsonResult<Value> {
+        let start = self.pos;
+
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+
+        match self.next() {
+            Some(b'0') => {}
+            Some(b'1'..=b'9') => self.skip_digits(),
+            _ => return Err(err("json_parse: invalid number")),
+        }
+
+        let mut is_int = true;
+
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            self.expect_digits()?;
+            is_int = false;
+        }
+
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            is_int = false;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            self.expect_digits()?;
+        }
+
+        // the grammar above only allows ascii
+        let number = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+        // integers that are too big for an int become floats
+        if let Some(int) = is_int.then(|| number.parse().ok()).flatten() {
+            return Ok(Value::Int(int));
+        }
+        number
+            .parse()
+            .map(Value::Num)
+            .map_err(|_| err("json_parse: invalid number"))
+    }
+
+    fn expect_digits(&mut self) -> JsonResult {
+        match self.peek() {
+            Some(b'0'..=b'9') => {
+                self.skip_digits();
+                Ok(())
+            }
+            _ => Err(err("json_parse: invalid number")),
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> JsonResult<Value> {
+        if self.src[self.pos..].starts_with(keyword.as_bytes()) {
+            self.pos += keyword.len();
+            Ok(value)
+        } else {
+            Err(err("json_parse: unexpected character"))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> JsonResult {
+        match self.next() {
+            Some(byte) if byte == expected => Ok(()),
+            Some(_) => Err(err("json_parse: unexpected character")),
+            None => Err(err("json_parse: unexpected end of input")),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        if byte.is_some() {
+            self.pos += 1;
+        }
+        byte
+    }
+}
+
+struct Stringifier {
+    out: String,
+    indent: String,
+    depth: usize,
+    /// The addresses of all arrays and objects that are currently being stringified
+    seen: Vec<usize>,
+}
+
+impl Stringifier {
+    fn value(&mut self, value: Value) -> JsonResult {
+        match value {
+            Value::Null => self.out.push_str("null"),
+            Value::Bool(bool) => write!(self.out, "{bool}").unwrap(),
+            Value::Num(num) if num.is_finite() => write!(self.out, "{num}").unwrap(),
+            // JSON has no representation for these
+            Value::Num(_) => self.out.push_str("null"),
+            Value::Int(int) => write!(self.out, "{int}").unwrap(),
+            Value::String(str) => self.string(str.as_str()),
+            Value::Array(array) => {
+                self.enter(array.address())?;
+                self.out.push('[');
+                for (i, element) in array.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.newline();
+                    self.value(*element)?;
+                }
+                self.exit(array.is_empty());
+                self.out.push(']');
+            }
+            Value::Object(obj) => {
+                self.enter(obj.address())?;
+                self.out.push('{');
+                let mut entries = obj.entries();
+                entries.sort_unstable_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+                for (i, (key, value)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.newline();
+                    self.string(key.as_str());
+                    self.out.push(':');
+                    if !self.indent.is_empty() {
+                        self.out.push(' ');
+                    }
+                    self.value(value)?;
+                }
+                self.exit(obj.is_empty());
+                self.out.push('}');
+            }
+            Value::Function(_) | Value::NativeFunction(_) => {
+                return Err(err("json_stringify: cannot stringify a function"))
+            }
+            Value::NativeU(_) => unreachable!("native value in json_stringify"),
+        }
+
+        Ok(())
+    }
+
+    fn enter(&mut self, address: usize) -> JsonResult {
+        if self.seen.contains(&address) {
+            return Err(err("json_stringify: cannot stringify a cyclic structure"));
+        }
+        self.seen.push(address);
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn exit(&mut self, empty: bool) {
+        self.seen.pop();
+        self.depth -= 1;
+        if !empty {
+            self.newline();
+        }
+    }
+
+    fn newline(&mut self) {
+        if !self.indent.is_empty() {
+            self.out.push('\n');
+            for _ in 0..self.depth {
+                self.out.push_str(&self.indent);
+            }
+        }
+    }
+
+    fn string(&mut self, str: &str) {
+        self.out.push('"');
+        for char in str.chars() {
+            match char {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                '\x08' => self.out.push_str("\\b"),
+                '\x0C' => self.out.push_str("\\f"),
+                char if (char as u32) < 0x20 => write!(self.out, "\\u{:04x}", char as u32).unwrap(),
+                char => self.out.push(char),
+            }
+        }
+        self.out.push('"');
+    }
+}
@@ -0,0 +1,20 @@
+//! Native functions for working with objects
+
+use crate::runtime::{
+    native::NativeCtx,
+    vm::{err, Value, VmError},
+};
+
+/// `create(prototype)`
+///
+/// Creates an empty object that inherits the fields of the prototype. Fields set on the new object
+/// shadow the inherited ones, without changing the prototype.
+pub(super) fn create(ctx: &mut NativeCtx<'_>, args: &[Value]) -> Result<Value, VmError> {
+    match args {
+        [Value::Object(prototype)] => Ok(Value::Object(
+            ctx.alloc.alloc_obj_with_prototype(*prototype),
+        )),
+        [_] => Err(err("create: expected an object")),
+        _ => Err(err("create: expected 1 argument")),
+    }
+}
@@ -0,0 +1,208 @@
+//! The execution profiler, enabled with `--profile`
+//!
+//! The VM reports every executed instruction, every call and every return to the profiler.
+//! Instructions and time are attributed to the `FnBlock` that is currently being executed
+//! (exclusive) and to all functions on the call stack (inclusive). Recursive calls are only
+//! counted once for the inclusive numbers.
+//!
+//! The folded stacks use the executed instructions as sample count, which is deterministic and
+//! works with all the usual flamegraph tools.
+
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    errors::LineIndex,
+    runtime::bytecode::{FnBlock, Function},
+    HashMap,
+};
+
+/// How many source lines are shown in the report
+const HOT_LINES: usize = 10;
+
+pub(super) struct Profiler {
+    lines: LineIndex,
+    source_lines: Vec<String>,
+    functions: Vec<FnProfile>,
+    /// Executed instructions per source line, indexed by the 0-based line number
+    line_hits: Vec<u64>,
+    /// The currently running functions, the innermost is last
+    call_stack: Vec<ActiveCall>,
+    /// Every distinct call stack, as its parent stack and innermost function
+    stacks: Vec<(Option<usize>, Function)>,
+    stack_ids: HashMap<(Option<usize>, Function), usize>,
+    /// Executed instructions per call stack, indexed like `stacks`
+    stack_samples: Vec<u64>,
+    total_instructions: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+struct FnProfile {
+    calls: u64,
+    self_instructions: u64,
+    total_instructions: u64,
+    self_time: Duration,
+    total_time: Duration,
+}
+
+struct ActiveCall {
+    block: Function,
+    stack_id: usize,
+    start: Instant,
+    start_instructions: u64,
+    /// The time spent in callees, which is subtracted for the exclusive time
+    child_time: Duration,
+}
+
+impl Profiler {
+    pub fn new(source: &str, blocks: &[FnBlock<'_>]) -> Self {
+        let lines = LineIndex::new(source);
+        let mut profiler = Self {
+            line_hits: vec![0; lines.line_count()],
+            lines,
+            source_lines: source.lines().map(ToOwned::to_owned).collect(),
+            functions: vec![FnProfile::default(); blocks.len()],
+            call_stack: Vec::new(),
+            stacks: Vec::new(),
+            stack_ids: HashMap::default(),
+            stack_samples: Vec::new(),
+            total_instructions: 0,
+        };
+        // the global script is always the first block
+        profiler.enter(0);
+        profiler
+    }
+
+    /// Called before every instruction
+    pub fn instr(&mut self, block: &FnBlock<'_>, index: usize) {
+        let call = self
+            .call_stack
+            .last()
+            .expect("instruction outside of function");
+        self.functions[call.block].self_instructions += 1;
+        self.stack_samples[call.stack_id] += 1;
+        self.total_instructions += 1;
+
+        let span = block.spans[index];
+        // instructions inserted by the compiler don't belong to any line
+        if span.len() != 0 {
+            let line = self.lines.line(span.start);
+            self.line_hits[line - 1] += 1;
+        }
+    }
+
+    /// Called after a new stack frame for `block` has been created
+    pub fn enter(&mut self, block: Function) {
+        let parent = self.call_stack.last().map(|call| call.stack_id);
+        let stack_id = *self.stack_ids.entry((parent, block)).or_insert_with(|| {
+            self.stacks.push((parent, block));
+            self.stack_samples.push(0);
+            self.stacks.len() - 1
+        });
+
+        self.functions[block].calls += 1;
+        self.call_stack.push(ActiveCall {
+            block,
+            stack_id,
+            start: Instant::now(),
+            start_instructions: self.total_instructions,
+            child_time: Duration::ZERO,
+        });
+    }
+
+    /// Called after the stack frame of the current function has been removed
+    pub fn exit(&mut self) {
+        let call = self.call_stack.pop().expect("returned from nothing");
+        let elapsed = call.start.elapsed();
+
+        let recursive = self
+            .call_stack
+            .iter()
+            .any(|outer| outer.block == call.block);
+
+        let profile = &mut self.functions[call.block];
+        profile.self_time += elapsed.saturating_sub(call.child_time);
+        if !recursive {
+            profile.total_time += elapsed;
+            profile.total_instructions += self.total_instructions - call.start_instructions;
+        }
+
+        if let Some(caller) = self.call_stack.last_mut() {
+            caller.child_time += elapsed;
+        }
+    }
+
+    /// Ends all calls that are still running, after the program has stopped
+    pub fn finish(&mut self) {
+        while !self.call_stack.is_empty() {
+            self.exit();
+        }
+    }
+
+    /// Writes the per function and per line report, sorted by exclusive time
+    pub fn write_report(&self, blocks: &[FnBlock<'_>], w: &mut dyn Write) -> io::Result<()> {
+        let mut functions = self.functions.iter().enumerate().collect::<Vec<_>>();
+        functions.sort_by_key(|(_, profile)| std::cmp::Reverse(profile.self_time));
+
+        writeln!(w, "profile: {} instructions", self.total_instructions)?;
+        writeln!(
+            w,
+            "{:<20} {:>8} {:>14} {:>14} {:>12} {:>12}",
+            "function", "calls", "self instrs", "total instrs", "self time", "total time"
+        )?;
+        for (block, profile) in functions.into_iter().filter(|(_, p)| p.calls > 0) {
+            writeln!(
+                w,
+                "{:<20} {:>8} {:>14} {:>14} {:>12} {:>12}",
+                blocks[block].name.as_str(),
+                profile.calls,
+                profile.self_instructions,
+                profile.total_instructions,
+                format!("{:.3?}", profile.self_time),
+                format!("{:.3?}", profile.total_time),
+            )?;
+        }
+
+        let mut lines = self
+            .line_hits
+            .iter()
+            .enumerate()
+            .filter(|(_, hits)| **hits > 0)
+            .collect::<Vec<_>>();
+        // the most hits first, and earlier lines first if they are equal
+        lines.sort_by(|(a_line, a), (b_line, b)| b.cmp(a).then(a_line.cmp(b_line)));
+
+        writeln!(w)?;
+        writeln!(w, "{:>6} {:>12}", "line", "instrs")?;
+        for (line, hits) in lines.into_iter().take(HOT_LINES) {
+            let source = self.source_lines.get(line).map_or("", |line| line.trim());
+            writeln!(w, "{:>6} {:>12}   {}", line + 1, hits, source)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the folded stacks, one line per call stack: `main;outer;inner 42`
+    pub fn write_folded(&self, blocks: &[FnBlock<'_>], w: &mut dyn Write) -> io::Result<()> {
+        for (stack_id, samples) in self.stack_samples.iter().enumerate() {
+            if *samples == 0 {
+                continue;
+            }
+
+            let mut names = Vec::new();
+            let mut current = Some(stack_id);
+            while let Some(id) = current {
+                let (parent, block) = self.stacks[id];
+                names.push(blocks[block].name.as_str());
+                current = parent;
+            }
+            names.reverse();
+
+            writeln!(w, "{} {}", names.join(";"), samples)?;
+        }
+
+        Ok(())
+    }
+}
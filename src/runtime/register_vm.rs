@@ -0,0 +1,390 @@
+//! The interpreter for the register based bytecode, enabled with `--register`
+//!
+//! The registers of all active functions live in one `Vec`, and the registers of the current
+//! function start at `base`. The operators are shared with the stack VM, so both behave the same.
+//! The register bytecode is only ever created by the compiler directly before running it, so it
+//! is not verified.
+
+use std::io::{BufRead, Write};
+
+use crate::{
+    runtime::{
+        bytecode::register::{Reg, RegBlock, RegInstr},
+        gc::RtAlloc,
+        native::{self, NativeCtx, NativeFunction},
+        vm::{self, err, PublicVmError, StackValue, Value, VmError, VmErrorInner, VmResult},
+    },
+    Config,
+};
+
+struct RegVm<'bc, 'io> {
+    blocks: &'bc [RegBlock<'bc>],
+    alloc: RtAlloc,
+    registers: Vec<StackValue>,
+    /// The callers of the current function, the innermost is last
+    frames: Vec<CallFrame<'bc>>,
+    stdout: &'io mut dyn Write,
+    stdin: &'io mut dyn BufRead,
+
+    // -- local to the current function
+    current: &'bc RegBlock<'bc>,
+    /// The index of the first register of the current function
+    base: usize,
+    /// Index of the next instruction being executed
+    pc: usize,
+}
+
+/// The state of a caller, restored after the callee returns
+struct CallFrame<'bc> {
+    block: &'bc RegBlock<'bc>,
+    base: usize,
+    pc: usize,
+    /// The register of the caller that gets the return value
+    dst: Reg,
+}
+
+pub fn execute<'bc>(
+    blocks: &'bc [RegBlock<'bc>],
+    mut alloc: RtAlloc,
+    cfg: &mut Config,
+) -> Result<(), PublicVmError> {
+    let main = blocks.first().ok_or("no bytecode found")?;
+    let registers = vec![Value::Null.pack(&mut alloc); main.registers];
+    let mut vm = RegVm {
+        blocks,
+        alloc,
+        registers,
+        frames: Vec::new(),
+        stdout: cfg.stdout,
+        stdin: cfg.stdin,
+        current: main,
+        base: 0,
+        pc: 0,
+    };
+
+    match vm.run() {
+        Ok(()) => Ok(()),
+        Err(boxed) => match *boxed {
+            VmErrorInner::Exit => Ok(()),
+            VmErrorInner::Error(err) => Err(err),
+        },
+    }
+}
+
+impl<'bc> RegVm<'bc, '_> {
+    fn run(&mut self) -> VmResult {
+        loop {
+            let instr = self.current.code[self.pc];
+            self.pc += 1;
+
+            match instr {
+                RegInstr::Move { dst, src } => {
+                    self.registers[self.base + dst as usize] =
+                        self.registers[self.base + src as usize];
+                }
+                RegInstr::LoadConst { dst, index } => {
+                    self.write(dst, self.current.consts[index as usize]);
+                }
+                RegInstr::Neg { dst, src } => self.write(dst, vm::neg(self.read(src))?),
+                RegInstr::NewObject { dst } => {
+                    let object = self.alloc.alloc_obj([]);
+                    self.write(dst, Value::Object(object));
+                }
+                RegInstr::NewArray { dst, args, count } => {
+                    let start = self.base + args as usize;
+                    let elements = self.registers[start..start + count as usize]
+                        .iter()
+                        .map(|value| value.unpack())
+                        .collect();
+                    let array = self.alloc.alloc_array(elements);
+                    self.write(dst, Value::Array(array));
+                }
+                RegInstr::ExtendArray { array, src } => {
+                    let (head, tail) = (self.read(array), self.read(src));
+                    let extended = vm::concat_arrays(&mut self.alloc, head, tail)?;
+                    self.write(array, extended);
+                }
+                RegInstr::GetField { dst, object, site } => {
+                    let site = &self.current.field_sites[site as usize];
+                    self.write(dst, vm::get_field(self.read(object), site)?);
+                }
+                RegInstr::SetField { object, src, site } => {
+                    let (object, value) = (self.read(object), self.read(src));
+                    let site = &self.current.field_sites[site as usize];
+                    vm::set_field(&mut self.alloc, object, site, value)?;
+                }
+                RegInstr::MatchConst { dst, src, index } => {
+                    let literal = self.current.consts[index as usize];
+                    self.write(
+                        dst,
+                        Value::Bool(vm::matches_literal(self.read(src), literal)),
+                    );
+                }
+                RegInstr::MatchArray { dst, src, len } => {
+                    self.write(dst, Value::Bool(vm::matches_array(self.read(src), len)));
+                }
+                RegInstr::MatchArrayMin { dst, src, len } => {
+                    self.write(dst, Value::Bool(vm::matches_array_min(self.read(src), len)));
+                }
+                RegInstr::HasField { dst, object, site } => {
+                    let site = &self.current.field_sites[site as usize];
+                    self.write(dst, Value::Bool(vm::has_field(self.read(object), site)));
+                }
+                RegInstr::GetElement { dst, array, index } => {
+                    self.write(dst, vm::get_element(self.read(array), index)?);
+                }
+                RegInstr::SliceArray { dst, array, start } => {
+                    let array = self.read(array);
+                    let slice = vm::slice_array(&mut self.alloc, array, start)?;
+                    self.write(dst, slice);
+                }
+                RegInstr::MatchFailed => return Err(vm::match_failed()),
+                RegInstr::Add { dst, lhs, rhs } => self.binary(vm::add, dst, lhs, rhs)?,
+                RegInstr::Sub { dst, lhs, rhs } => self.binary(vm::sub, dst, lhs, rhs)?,
+                RegInstr::Mul { dst, lhs, rhs } => self.binary(vm::mul, dst, lhs, rhs)?,
+                RegInstr::Div { dst, lhs, rhs } => self.binary(vm::div, dst, lhs, rhs)?,
+                RegInstr::IntDiv { dst, lhs, rhs } => self.binary(vm::int_div, dst, lhs, rhs)?,
+                RegInstr::Mod { dst, lhs, rhs } => self.binary(vm::rem, dst, lhs, rhs)?,
+                RegInstr::And { dst, lhs, rhs } => self.binary(vm::and, dst, lhs, rhs)?,
+                RegInstr::Or { dst, lhs, rhs } => self.binary(vm::or, dst, lhs, rhs)?,
+                RegInstr::BitAnd { dst, lhs, rhs } => self.binary(vm::bit_and, dst, lhs, rhs)?,
+                RegInstr::BitOr { dst, lhs, rhs } => self.binary(vm::bit_or, dst, lhs, rhs)?,
+                RegInstr::BitXor { dst, lhs, rhs } => self.binary(vm::bit_xor, dst, lhs, rhs)?,
+                RegInstr::Shl { dst, lhs, rhs } => self.binary(vm::shl, dst, lhs, rhs)?,
+                RegInstr::Shr { dst, lhs, rhs } => self.binary(vm::shr, dst, lhs, rhs)?,
+                RegInstr::Greater { dst, lhs, rhs } => self.binary(vm::greater, dst, lhs, rhs)?,
+                RegInstr::GreaterEq { dst, lhs, rhs } => {
+                    self.binary(vm::greater_eq, dst, lhs, rhs)?
+                }
+                RegInstr::Less { dst, lhs, rhs } => self.binary(vm::less, dst, lhs, rhs)?,
+                RegInstr::LessEq { dst, lhs, rhs } => self.binary(vm::less_eq, dst, lhs, rhs)?,
+                RegInstr::Eq { dst, lhs, rhs } => self.binary(vm::eq, dst, lhs, rhs)?,
+                RegInstr::NotEq { dst, lhs, rhs } => self.binary(vm::not_eq, dst, lhs, rhs)?,
+                RegInstr::Print { src } => {
+                    let val = self.read(src);
+                    writeln!(self.stdout, "{}", val)
+                        .map_err(|_| err("failed to write to stdout"))?;
+                }
+                RegInstr::JmpFalse { cond, offset } => {
+                    if !vm::condition(self.read(cond))? {
+                        self.jump(offset);
+                    }
+                }
+                RegInstr::Jmp { offset } => self.jump(offset),
+                RegInstr::JmpNull { src, offset } => {
+                    if let Value::Null = self.read(src) {
+                        self.jump(offset);
+                    }
+                }
+                RegInstr::Call {
+                    dst,
+                    callee,
+                    args,
+                    count,
+                } => self.call(dst, self.read(callee), args, count)?,
+                RegInstr::CallMethod {
+                    dst,
+                    args,
+                    count,
+                    site,
+                } => {
+                    self.call_method(dst, site, args, count)?;
+                }
+                RegInstr::CallSpread { dst, callee, args } => {
+                    // the elements may overwrite the callee
+                    let callee = self.read(callee);
+                    let count = self.spread_args(args)?;
+                    self.call(dst, callee, args, count)?;
+                }
+                RegInstr::CallMethodSpread { dst, args, site } => {
+                    let count = self.spread_args(args)?;
+                    if count == 0 {
+                        return Err(err("tried to call a method without a receiver"));
+                    }
+                    self.call_method(dst, site, args, count)?;
+                }
+                RegInstr::Return { src } => self.ret(src),
+                RegInstr::TailCall {
+                    callee,
+                    args,
+                    count,
+                } => self.tail_call(callee, args, count)?,
+                RegInstr::Exit => return Ok(()),
+            }
+        }
+    }
+
+    fn read(&self, reg: Reg) -> Value {
+        self.registers[self.base + reg as usize].unpack()
+    }
+
+    fn write(&mut self, reg: Reg, value: Value) {
+        self.registers[self.base + reg as usize] = value.pack(&mut self.alloc);
+    }
+
+    fn binary(&mut self, op: vm::BinaryOp, dst: Reg, lhs: Reg, rhs: Reg) -> VmResult {
+        let result = op(self.read(lhs), self.read(rhs))?;
+        self.write(dst, result);
+        Ok(())
+    }
+
+    fn jump(&mut self, offset: i32) {
+        self.pc = (self.pc as isize + offset as isize) as usize;
+    }
+
+    /// Calls the function from the field site on the receiver in `args`, the first of the `count`
+    /// arguments. Only methods get the receiver as `self`, all other functions are called with the
+    /// arguments after it
+    fn call_method(&mut self, dst: Reg, site: u32, args: Reg, count: u16) -> VmResult {
+        let site = &self.current.field_sites[site as usize];
+        let method = vm::get_field(self.read(args), site)?;
+        if matches!(method, Value::Function(function) if self.blocks[function].is_method) {
+            self.call(dst, method, args, count)
+        } else {
+            self.call(dst, method, args + 1, count - 1)
+        }
+    }
+
+    fn call(&mut self, dst: Reg, callee: Value, args: Reg, count: u16) -> VmResult {
+        let function = match callee {
+            Value::Function(function) => function,
+            Value::NativeFunction(native) => return self.call_native(native, dst, args, count),
+            _ => return Err(err("tried to call a value that is not a function")),
+        };
+        let block = &self.blocks[function];
+
+        vm::check_arg_count(
+            block.arity,
+            block.required,
+            block.variadic,
+            u32::from(count),
+        )?;
+
+        // the arguments become the first registers of the callee
+        let new_base = self.base + args as usize;
+        let needed = new_base + block.registers;
+        if self.registers.len() < needed {
+            self.registers
+                .resize(needed, Value::Null.pack(&mut self.alloc));
+        }
+        self.bind_args(block, new_base, count);
+
+        self.frames.push(CallFrame {
+            block: self.current,
+            base: self.base,
+            pc: self.pc,
+            dst,
+        });
+        self.current = block;
+        self.base = new_base;
+        self.pc = 0;
+
+        Ok(())
+    }
+
+    fn tail_call(&mut self, callee: Reg, args: Reg, count: u16) -> VmResult {
+        let function = match self.read(callee) {
+            Value::Function(function) => function,
+            Value::NativeFunction(native) => {
+                self.call_native(native, args, args, count)?;
+                self.ret(args);
+                return Ok(());
+            }
+            _ => return Err(err("tried to call a value that is not a function")),
+        };
+        let block = &self.blocks[function];
+
+        vm::check_arg_count(
+            block.arity,
+            block.required,
+            block.variadic,
+            u32::from(count),
+        )?;
+
+        // the callee returns to our caller, so the frame and the base stay the same
+        let args_start = self.base + args as usize;
+        self.registers
+            .copy_within(args_start..args_start + count as usize, self.base);
+        let needed = self.base + block.registers;
+        if self.registers.len() < needed {
+            self.registers
+                .resize(needed, Value::Null.pack(&mut self.alloc));
+        }
+        self.bind_args(block, self.base, count);
+
+        self.current = block;
+        self.pc = 0;
+
+        Ok(())
+    }
+
+    /// Turns the `count` arguments in the registers from `start` on into the parameters of the
+    /// block. Missing arguments are `null` and the rest parameter gets an array of the remaining
+    /// arguments, just like in the stack VM.
+    fn bind_args(&mut self, block: &RegBlock<'_>, start: usize, count: u16) {
+        let count = u32::from(count);
+        let positional = block.arity - u32::from(block.variadic);
+        for param in count..positional {
+            self.registers[start + param as usize] = Value::Null.pack(&mut self.alloc);
+        }
+
+        if block.variadic {
+            let rest_start = start + positional as usize;
+            let rest_end = start + count.max(positional) as usize;
+            let rest = self.registers[rest_start..rest_end]
+                .iter()
+                .map(|value| value.unpack())
+                .collect();
+            let rest = Value::Array(self.alloc.alloc_array(rest));
+            self.registers[rest_start] = rest.pack(&mut self.alloc);
+        }
+    }
+
+    /// Writes the elements of the array in `args` to the registers starting at `args`, and returns
+    /// how many there are
+    fn spread_args(&mut self, args: Reg) -> Result<u16, VmError> {
+        let array = vm::spread(self.read(args))?;
+        let count = u16::try_from(array.len()).map_err(|_| err("too many arguments"))?;
+
+        let start = self.base + args as usize;
+        let needed = start + array.len();
+        if self.registers.len() < needed {
+            self.registers
+                .resize(needed, Value::Null.pack(&mut self.alloc));
+        }
+        for (i, &element) in array.iter().enumerate() {
+            self.registers[start + i] = element.pack(&mut self.alloc);
+        }
+        Ok(count)
+    }
+
+    fn call_native(&mut self, native: NativeFunction, dst: Reg, args: Reg, count: u16) -> VmResult {
+        let args_start = self.base + args as usize;
+
+        let mut ctx = NativeCtx {
+            alloc: &mut self.alloc,
+            stdout: &mut *self.stdout,
+            stdin: &mut *self.stdin,
+        };
+        let return_value = native::call(
+            native,
+            &mut ctx,
+            &self.registers[args_start..args_start + count as usize],
+        )?;
+
+        self.write(dst, return_value);
+
+        Ok(())
+    }
+
+    fn ret(&mut self, src: Reg) {
+        let return_value = self.read(src);
+        // the global script exits instead of returning
+        let frame = self.frames.pop().expect("returned from the global script");
+
+        self.current = frame.block;
+        self.base = frame.base;
+        self.pc = frame.pc;
+        self.write(frame.dst, return_value);
+    }
+}
@@ -5,7 +5,10 @@
 //!
 //! When a call happens, the current stack offset is pushed onto the stack as a `Value::Native` and
 //! the element before it is stored as the new offset.
-//! Then all parameters are pushed onto the stack, from first to last
+//! Then all parameters are pushed onto the stack, from first to last. A call can pass fewer
+//! arguments than the function has parameters if they have defaults, the missing ones are `null`.
+//! A rest parameter gets an array of the arguments after the other parameters. So the stack frame
+//! always has exactly `arity` parameters, no matter how many arguments were passed.
 //! Afterwards, execution of the code is started. A function always has to return, and compiler
 //! inserts `return null` at the end of every function implicitly.
 //!
@@ -14,6 +17,12 @@
 //! into its stack offset. It then removes the whole stack frame from the stack, and pushes the
 //! returned value.
 //!
+//! A `TailCall` doesn't create a new stack frame, since the current function would return
+//! directly afterwards anyways. The arguments are moved down into the parameter slots of the
+//! current stack frame and the bookkeeping data is copied behind them, so the callee returns
+//! directly to the caller of the current function. This way, deep tail recursion only needs a
+//! single stack frame.
+//!
 //! ```text
 //!             old stack frame offset─╮
 //!         ╭─Parameters─╮             │           old Function─╮     local─╮
@@ -34,36 +43,65 @@
 
 use crate::runtime::{
     bytecode::Function,
-    vm::{Value, Vm},
+    vm::{StackValue, Value, Vm},
 };
 
+/// The amount of values that are pushed after the parameters to save the state of the caller
+pub const CALLCONV_OFFSET_DATA: usize = 3;
+
 pub struct Frame<'s> {
-    frame_slice: &'s [Value],
+    frame_slice: &'s [StackValue],
     params: u32,
 }
 
 impl<'s> Frame<'s> {
     /// Create a new stack frame with the VM state. The VM must set its state to the new function itself.
-    /// The parameters need to be pushed already and be the topmost values on the stack.
+    /// The `args` arguments need to be pushed already and be the topmost values on the stack, and
+    /// their amount must have been checked against the parameters of `callee`.
     ///
     /// Returns the new stack frame offset
-    pub(super) fn create(vm_state: &'s mut Vm, params: u32) -> usize {
+    pub(super) fn create(vm_state: &'s mut Vm, args: u32, callee: Function) -> usize {
+        let params = bind_args(vm_state, args, callee);
         let new_frame_offset = vm_state.stack.len() - (params as usize);
 
         let old_stack_offset = vm_state.stack_frame_offset;
         let old_fn_block = vm_state.current_block_index;
         let old_pc = vm_state.pc;
 
-        vm_state.stack.push(Value::NativeU(old_stack_offset));
-        vm_state.stack.push(Value::NativeU(old_pc));
-        vm_state.stack.push(Value::Function(old_fn_block));
+        vm_state
+            .stack
+            .push(Value::NativeU(old_stack_offset).pack(&mut vm_state.alloc));
+        vm_state
+            .stack
+            .push(Value::NativeU(old_pc).pack(&mut vm_state.alloc));
+        vm_state
+            .stack
+            .push(Value::Function(old_fn_block).pack(&mut vm_state.alloc));
 
         // let frame_slice = &vm_state.stack[new_frame_offset..];
 
         new_frame_offset
     }
 
-    pub fn new(frame_slice: &'s [Value], params: u32) -> Self {
+    /// Replace the stack frame of the current function with a new one for a tail call. The
+    /// arguments need to be the topmost values on the stack, like for [`Frame::create`]. The VM
+    /// must set its state to the new function itself, the stack frame offset stays the same.
+    pub(super) fn reuse(vm_state: &mut Vm, args: u32, callee: Function) {
+        let params = bind_args(vm_state, args, callee);
+        let frame_offset = vm_state.stack_frame_offset;
+        let bookkeeping_start = frame_offset + vm_state.current.arity as usize;
+        let mut bookkeeping = [Value::Null.pack(&mut vm_state.alloc); CALLCONV_OFFSET_DATA];
+        bookkeeping.copy_from_slice(
+            &vm_state.stack[bookkeeping_start..bookkeeping_start + CALLCONV_OFFSET_DATA],
+        );
+
+        let args_start = vm_state.stack.len() - (params as usize);
+        vm_state.stack.copy_within(args_start.., frame_offset);
+        vm_state.stack.truncate(frame_offset + params as usize);
+        vm_state.stack.extend_from_slice(&bookkeeping);
+    }
+
+    pub fn new(frame_slice: &'s [StackValue], params: u32) -> Self {
         Self {
             frame_slice,
             params,
@@ -71,16 +109,56 @@ impl<'s> Frame<'s> {
     }
 
     pub fn old_stack_offset(&self) -> usize {
-        self.frame_slice[self.params as usize].unwrap_native_int()
+        self.frame_slice[self.params as usize]
+            .unpack()
+            .unwrap_native_int()
     }
 
     pub fn old_pc(&self) -> usize {
-        self.frame_slice[self.params as usize + 1].unwrap_native_int()
+        self.frame_slice[self.params as usize + 1]
+            .unpack()
+            .unwrap_native_int()
     }
 
     pub fn old_fn_block(&self) -> Function {
-        self.frame_slice[self.params as usize + 2].unwrap_function()
+        self.frame_slice[self.params as usize + 2]
+            .unpack()
+            .unwrap_function()
+    }
+}
+
+/// Turns the `args` arguments on top of the stack into the parameters of `callee`. Missing
+/// arguments are `null` and the rest parameter gets an array of the remaining arguments.
+///
+/// Returns the amount of parameters
+fn bind_args(vm_state: &mut Vm, args: u32, callee: Function) -> u32 {
+    let block = &vm_state.blocks[callee];
+    let (arity, variadic) = (block.arity, block.variadic);
+    let positional = arity - u32::from(variadic);
+    let args_start = vm_state.stack.len() - (args as usize);
+
+    if args < positional {
+        let missing = (positional - args) as usize;
+        vm_state.stack.extend(std::iter::repeat_n(
+            Value::Null.pack(&mut vm_state.alloc),
+            missing,
+        ));
     }
+
+    if variadic {
+        let rest_start = args_start + positional as usize;
+        let rest = vm_state
+            .stack
+            .drain(rest_start..)
+            .map(|value| value.unpack())
+            .collect();
+        let rest = vm_state.alloc.alloc_array(rest);
+        vm_state
+            .stack
+            .push(Value::Array(rest).pack(&mut vm_state.alloc));
+    }
+
+    arity
 }
 
 impl Value {
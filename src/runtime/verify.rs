@@ -0,0 +1,348 @@
+//! The bytecode verifier
+//!
+//! The VM trusts the bytecode: instructions are fetched without bounds checks, locals are indexed
+//! relative to the stack frame without checks and `ShrinkStack` and `Return` cut the stack with
+//! `set_len`. So before anything is executed, every `FnBlock` is checked here, to catch compiler
+//! bugs and broken bytecode files early with a precise error instead of corrupting the stack at
+//! runtime.
+//!
+//! The verifier follows every path through the function and simulates the stack depth relative to
+//! the stack frame offset, just like the `stack_sizes` of the compiler. Every instruction must be
+//! reached with the same stack depth on all paths, so loops can't grow or shrink the stack.
+
+use std::fmt::{Display, Formatter};
+
+use crate::runtime::{
+    bytecode::{FnBlock, Instr},
+    native,
+    stack_frame::CALLCONV_OFFSET_DATA,
+    vm::Value,
+};
+
+/// The bytecode is malformed
+#[derive(Debug)]
+pub struct VerifyError {
+    function: String,
+    /// The index of the instruction that is malformed
+    instr: usize,
+    message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid bytecode in function `{}` at instruction {}: {}",
+            self.function, self.instr, self.message
+        )
+    }
+}
+
+/// Verifies all functions, the first block is the global script
+pub fn verify(blocks: &[FnBlock<'_>]) -> Result<(), VerifyError> {
+    for (index, block) in blocks.iter().enumerate() {
+        Verifier {
+            blocks,
+            block,
+            is_main: index == 0,
+        }
+        .verify()?;
+    }
+    Ok(())
+}
+
+struct Verifier<'a, 'bc> {
+    blocks: &'a [FnBlock<'bc>],
+    block: &'a FnBlock<'bc>,
+    /// The global script has no stack frame, so it can't return
+    is_main: bool,
+}
+
+impl Verifier<'_, '_> {
+    fn error(&self, instr: usize, message: impl Into<String>) -> VerifyError {
+        VerifyError {
+            function: self.block.name.as_str().to_owned(),
+            instr,
+            message: message.into(),
+        }
+    }
+
+    /// The parameters, which are part of the frame
+    fn params(&self) -> usize {
+        if self.is_main {
+            0
+        } else {
+            self.block.arity as usize
+        }
+    }
+
+    /// The stack depth at the start of the function, the values below can never be popped
+    fn frame_base(&self) -> usize {
+        if self.is_main {
+            0
+        } else {
+            self.params() + CALLCONV_OFFSET_DATA
+        }
+    }
+
+    fn verify(&self) -> Result<(), VerifyError> {
+        let code = &self.block.code;
+        if code.is_empty() {
+            return Err(self.error(0, "the function is empty"));
+        }
+
+        // the stack depth before every instruction, `None` if it has not been reached yet
+        let mut depths: Vec<Option<usize>> = vec![None; code.len()];
+        let mut worklist = vec![(0, self.frame_base())];
+
+        while let Some((index, depth)) = worklist.pop() {
+            let instr =
+                match code.get(index) {
+                    Some(instr) => *instr,
+                    None => return Err(self.error(
+                        index - 1,
+                        "control flow reaches the end of the function without `Return` or `Exit`",
+                    )),
+                };
+
+            match depths[index] {
+                Some(known) if known == depth => continue,
+                Some(known) => {
+                    return Err(self.error(
+                        index,
+                        format!("reached with stack depth {depth} and {known} on different paths"),
+                    ))
+                }
+                None => depths[index] = Some(depth),
+            }
+
+            let next = index + 1;
+            match instr {
+                Instr::Nop => worklist.push((next, depth)),
+                Instr::Store(offset) => {
+                    let depth = self.pop(index, depth, 1)?;
+                    self.check_local(index, offset, depth)?;
+                    worklist.push((next, depth));
+                }
+                Instr::Load(offset) => {
+                    self.check_local(index, offset, depth)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::PushConst(const_index) => {
+                    self.check_const(index, const_index)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::PushNull | Instr::PushTrue | Instr::PushFalse => {
+                    worklist.push((next, depth + 1))
+                }
+                Instr::Neg => {
+                    self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                }
+                Instr::NewObject => worklist.push((next, depth + 1)),
+                Instr::NewArray(len) => {
+                    let depth = self.pop(index, depth, len as usize)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::ExtendArray => {
+                    let depth = self.pop(index, depth, 2)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::Dup => {
+                    self.pop(index, depth, 1)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::GetField(site) => {
+                    self.check_field_site(index, site)?;
+                    self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                }
+                Instr::SetField(site) => {
+                    self.check_field_site(index, site)?;
+                    let depth = self.pop(index, depth, 2)?;
+                    worklist.push((next, depth));
+                }
+                Instr::MatchConst(const_index) => {
+                    self.check_const(index, const_index)?;
+                    self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                }
+                Instr::HasField(site) => {
+                    self.check_field_site(index, site)?;
+                    self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                }
+                Instr::MatchArray(_)
+                | Instr::MatchArrayMin(_)
+                | Instr::GetElement(_)
+                | Instr::SliceArray(_) => {
+                    self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                }
+                Instr::BinAdd
+                | Instr::BinSub
+                | Instr::BinMul
+                | Instr::BinDiv
+                | Instr::BinIntDiv
+                | Instr::BinMod
+                | Instr::BinAnd
+                | Instr::BinOr
+                | Instr::BinBitAnd
+                | Instr::BinBitOr
+                | Instr::BinBitXor
+                | Instr::BinShl
+                | Instr::BinShr
+                | Instr::CmpGreater
+                | Instr::CmpGreaterEq
+                | Instr::CmpLess
+                | Instr::CmpLessEq
+                | Instr::CmpEq
+                | Instr::CmpNotEq => {
+                    let depth = self.pop(index, depth, 2)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::Print => {
+                    let depth = self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                }
+                Instr::JmpFalse(offset) => {
+                    let depth = self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                    worklist.push((self.jump_target(index, offset)?, depth));
+                }
+                Instr::Jmp(offset) => worklist.push((self.jump_target(index, offset)?, depth)),
+                Instr::JmpNull(offset) => {
+                    // the value stays on the stack on both paths
+                    self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                    worklist.push((self.jump_target(index, offset)?, depth));
+                }
+                Instr::Call(args) => {
+                    // the function and the arguments are replaced by the return value
+                    let depth = self.pop(index, depth, args as usize + 1)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::CallSpread => {
+                    // the function and the array of arguments are replaced by the return value
+                    let depth = self.pop(index, depth, 2)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::CallMethodSpread(site) => {
+                    self.check_field_site(index, site)?;
+                    // the array of arguments is replaced by the return value
+                    self.pop(index, depth, 1)?;
+                    worklist.push((next, depth));
+                }
+                Instr::CallMethod { site, args } => {
+                    self.check_field_site(index, site)?;
+                    // the receiver and the arguments are replaced by the return value
+                    let depth = self.pop(index, depth, args as usize + 1)?;
+                    worklist.push((next, depth + 1));
+                }
+                Instr::Return => {
+                    if self.is_main {
+                        return Err(self.error(
+                            index,
+                            "`Return` outside of a function, the global script must `Exit`",
+                        ));
+                    }
+                    self.pop(index, depth, 1)?;
+                }
+                Instr::TailCall(args) => {
+                    if self.is_main {
+                        return Err(self.error(
+                            index,
+                            "`TailCall` outside of a function, the global script must `Exit`",
+                        ));
+                    }
+                    self.pop(index, depth, args as usize + 1)?;
+                }
+                // both end the program, so nothing comes after them
+                Instr::Exit | Instr::MatchFailed => {}
+                Instr::ShrinkStack(amount) => {
+                    let depth = self.pop(index, depth, amount)?;
+                    worklist.push((next, depth));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pops `amount` values and returns the new depth. Values of the frame can't be popped.
+    fn pop(&self, index: usize, depth: usize, amount: usize) -> Result<usize, VerifyError> {
+        match depth.checked_sub(amount) {
+            Some(new_depth) if new_depth >= self.frame_base() => Ok(new_depth),
+            _ => Err(self.error(
+                index,
+                format!(
+                    "pops {amount} values, but only {} are on the stack",
+                    depth - self.frame_base()
+                ),
+            )),
+        }
+    }
+
+    /// Locals must be a parameter or a value on the stack, but never the call bookkeeping
+    fn check_local(&self, index: usize, offset: usize, depth: usize) -> Result<(), VerifyError> {
+        let params = self.params();
+        if offset >= depth {
+            Err(self.error(
+                index,
+                format!("local {offset} is outside of the stack frame with size {depth}"),
+            ))
+        } else if (params..self.frame_base()).contains(&offset) {
+            Err(self.error(
+                index,
+                format!("local {offset} is the call bookkeeping data, not a variable"),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_value(&self, index: usize, value: Value) -> Result<(), VerifyError> {
+        match value {
+            Value::Function(function) if function >= self.blocks.len() => {
+                Err(self.error(index, format!("function {function} does not exist")))
+            }
+            Value::NativeFunction(native) if !native::exists(native) => {
+                Err(self.error(index, format!("native function {native} does not exist")))
+            }
+            Value::NativeU(_) => Err(self.error(index, "bookkeeping values can't be pushed")),
+            Value::Array(array) => array
+                .iter()
+                .try_for_each(|element| self.check_value(index, *element)),
+            Value::Object(object) => object
+                .entries()
+                .into_iter()
+                .try_for_each(|(_, value)| self.check_value(index, value)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_const(&self, index: usize, const_index: u32) -> Result<(), VerifyError> {
+        let value =
+            self.block.consts.get(const_index as usize).ok_or_else(|| {
+                self.error(index, format!("constant {const_index} does not exist"))
+            })?;
+        self.check_value(index, *value)
+    }
+
+    fn check_field_site(&self, index: usize, site: u32) -> Result<(), VerifyError> {
+        if (site as usize) < self.block.field_sites.len() {
+            Ok(())
+        } else {
+            Err(self.error(index, format!("field site {site} does not exist")))
+        }
+    }
+
+    fn jump_target(&self, index: usize, offset: isize) -> Result<usize, VerifyError> {
+        // offsets are relative to the instruction after the jump
+        (index as isize + 1)
+            .checked_add(offset)
+            .and_then(|target| usize::try_from(target).ok())
+            .filter(|target| *target < self.block.code.len())
+            .ok_or_else(|| self.error(index, format!("jump by {offset} leaves the function")))
+    }
+}
@@ -1,56 +1,76 @@
+mod dispatch;
+
 use std::{
+    cmp::Ordering,
     fmt::{Debug, Display, Formatter},
-    io::{Read, Write},
+    io::{BufRead, Write},
 };
 
 use crate::{
     runtime::{
-        bytecode::{FnBlock, Function, Instr},
-        gc::{Object, RtAlloc, Symbol},
+        bytecode::{FieldSite, FnBlock, Function},
+        coverage::Coverage,
+        debugger::Debugger,
+        gc::{Array, Object, RtAlloc, Symbol},
+        native::{self, NativeCtx, NativeFunction},
+        profiler::Profiler,
         stack_frame::Frame,
+        vm::dispatch::Op,
     },
     util, Config,
 };
 
 type ActualBackingVmError = &'static str;
 
-type VmError = Box<VmErrorInner>;
+pub(crate) type VmError = Box<VmErrorInner>;
 
 #[derive(Debug)]
-enum VmErrorInner {
+pub(crate) enum VmErrorInner {
     Exit,
     Error(ActualBackingVmError),
 }
 
-type VmResult = Result<(), VmError>;
+pub(super) type VmResult = Result<(), VmError>;
 
 // never get bigger than a machine word.
 util::assert_size!(VmResult <= std::mem::size_of::<usize>());
 
-type PublicVmError = ActualBackingVmError;
+pub(super) type PublicVmError = ActualBackingVmError;
 
 pub(super) struct Vm<'bc, 'io> {
     // -- global
-    blocks: &'bc [FnBlock<'bc>],
-    _alloc: RtAlloc,
-    pub stack: Vec<Value>,
+    pub blocks: &'bc [FnBlock<'bc>],
+    pub alloc: RtAlloc,
+    pub stack: Vec<StackValue>,
     stdout: &'io mut dyn Write,
-    step: bool,
+    pub stdin: &'io mut dyn BufRead,
+    pub stderr: &'io mut dyn Write,
+    debugger: Option<Debugger>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    /// How many stack frames there are below the current function
+    pub call_depth: usize,
 
     // -- local to the current function
     /// The current function
-    current: &'bc FnBlock<'bc>,
+    pub current: &'bc FnBlock<'bc>,
     pub current_block_index: usize,
     /// The offset of the first parameter of the current function
     pub stack_frame_offset: usize,
     /// Index of the next instruction being executed. is out of bounds if the current
     /// instruction is the last one
     pub pc: usize,
+    /// The decoded code of the current function, see [`dispatch`]
+    ops: *const Op,
+    /// The decoded code of all functions, `ops` points into it
+    decoded: Vec<Box<[Op]>>,
 }
 
+/// Runs the bytecode, which must have been checked by the verifier before
 pub fn execute<'bc>(
     bytecode: &'bc [FnBlock<'bc>],
     alloc: RtAlloc,
+    source: &str,
     cfg: &mut Config,
 ) -> Result<(), PublicVmError> {
     let mut vm = Vm {
@@ -60,12 +80,36 @@ pub fn execute<'bc>(
         stack_frame_offset: 0,
         pc: 0,
         stack: Vec::with_capacity(1024 << 5),
-        _alloc: alloc,
+        alloc,
         stdout: cfg.stdout,
-        step: cfg.step,
+        stdin: cfg.stdin,
+        stderr: cfg.stderr,
+        debugger: cfg.step.then(|| Debugger::new(source)),
+        profiler: cfg.profile.then(|| Profiler::new(source, bytecode)),
+        coverage: cfg.coverage.then(|| Coverage::new(source, bytecode)),
+        call_depth: 0,
+        ops: std::ptr::null(),
+        decoded: dispatch::decode(bytecode),
     };
 
-    match vm.execute_function() {
+    let result = vm.execute_function();
+
+    if let Some(mut profiler) = vm.profiler.take() {
+        profiler.finish();
+        let _ = profiler.write_report(bytecode, vm.stderr);
+        if let Some(folded) = &mut cfg.profile_folded {
+            let _ = profiler.write_folded(bytecode, *folded);
+        }
+    }
+
+    if let Some(coverage) = vm.coverage.take() {
+        let _ = coverage.write_summary(bytecode, vm.stderr);
+        if let Some(lcov) = &mut cfg.coverage_lcov {
+            let _ = coverage.write_lcov(bytecode, cfg.filename, *lcov);
+        }
+    }
+
+    match result {
         Ok(()) => Ok(()),
         Err(boxed) => match *boxed {
             VmErrorInner::Exit => Ok(()),
@@ -83,183 +127,281 @@ pub enum Value {
     Bool(bool),
     /// A floating point number
     Num(f64),
+    /// A 64 bit signed integer, operations that overflow are an error
+    Int(i64),
     /// An interned string
     String(Symbol),
     /// An array of values
-    Array,
+    Array(Array),
     /// A map from string to value
     Object(Object),
     /// A first-class function object
     Function(Function),
+    /// A function implemented by the host, see [`native`]
+    NativeFunction(NativeFunction),
     /// A value that is stored by the vm for bookkeeping and should never be accessed for anything else
     NativeU(usize),
 }
 
-util::assert_size!(Value <= 24);
+util::assert_size!(Value <= 16);
+
+/// How values are stored on the stack, packed into 8 bytes with the `nanbox` feature
+#[cfg(feature = "nanbox")]
+pub type StackValue = crate::runtime::nanbox::NanBox;
+
+/// How values are stored on the stack, packed into 8 bytes with the `nanbox` feature
+#[cfg(not(feature = "nanbox"))]
+pub type StackValue = Value;
+
+/// Without the `nanbox` feature, values are stored on the stack as they are
+#[cfg(not(feature = "nanbox"))]
+impl Value {
+    #[inline]
+    pub fn pack(self, _alloc: &mut RtAlloc) -> StackValue {
+        self
+    }
+
+    #[inline]
+    pub fn unpack(self) -> Value {
+        self
+    }
+}
 
 const TRUE: Value = Value::Bool(true);
 const FALSE: Value = Value::Bool(false);
 
 impl<'bc> Vm<'bc, '_> {
     fn execute_function(&mut self) -> VmResult {
+        self.enter_block(self.current_block_index);
+
+        if self.debugger.is_some() || self.profiler.is_some() || self.coverage.is_some() {
+            self.run_debug()
+        } else {
+            self.run_fast()
+        }
+    }
+
+    /// Runs the instructions without any checks in between. The only way out of this loop is an
+    /// error, which includes `Exit`.
+    fn run_fast(&mut self) -> VmResult {
         loop {
-            let instr = self.current.code.get(self.pc);
+            // SAFETY: The bytecode was verified, so every path through a function ends in `Return`,
+            // `TailCall` or `Exit` and every jump target is an instruction of the function. So `pc`
+            // is always in bounds of the ops of the current function, which `ops` points to.
+            let op = unsafe { *self.ops.add(self.pc) };
             self.pc += 1;
-            match instr {
-                Some(&instr) => self.dispatch_instr(instr)?,
-                None => return Ok(()),
-            }
-            if self.pc > 0 {
-                // this must respect stack frame stuff
-                // debug_assert_eq!(self.current.stack_sizes[self.pc - 1], self.stack.len());
-            }
+            op.run(self)?;
         }
     }
 
-    fn dispatch_instr(&mut self, instr: Instr) -> VmResult {
-        if self.step {
-            self.step_debug(instr);
-        }
+    /// Like `run_fast`, but calls the debugger, profiler and coverage before every instruction
+    fn run_debug(&mut self) -> VmResult {
+        loop {
+            let op = self.decoded[self.current_block_index][self.pc];
+            self.pc += 1;
 
-        match instr {
-            Instr::Nop => {}
-            Instr::Store(index) => {
-                let val = self.stack.pop().unwrap();
-                self.stack[self.stack_frame_offset + index] = val;
+            if let Some(mut debugger) = self.debugger.take() {
+                let result = debugger.before_instr(self);
+                self.debugger = Some(debugger);
+                result?;
             }
-            // todo: no no no no no no this is wrong
-            Instr::Load(index) => self.stack.push(self.stack[self.stack_frame_offset + index]),
-            Instr::PushVal(value) => self.stack.push(value),
-            Instr::Neg => {
-                let val = self.stack.pop().unwrap();
-                match val {
-                    Value::Bool(bool) => self.stack.push(Value::Bool(!bool)),
-                    Value::Num(float) => self.stack.push(Value::Num(-float)),
-                    _ => return Err(err("bad type")),
-                }
-            }
-            Instr::BinAdd => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Num(a + b)),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::BinSub => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Num(a - b)),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::BinMul => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Num(a * b)),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::BinDiv => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Num(a / b)),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::BinMod => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Num(a % b)),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::BinAnd => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a && b)),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::BinOr => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a || b)),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::CmpGreater => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Bool(a > b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a.as_str() > b.as_str())),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::CmpGreaterEq => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Bool(a >= b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a.as_str() >= b.as_str())),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::CmpLess => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Bool(a < b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a.as_str() < b.as_str())),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::CmpLessEq => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Bool(a <= b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a.as_str() <= b.as_str())),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::CmpEq => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Null, Value::Null) => Ok(TRUE),
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Bool(a == b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a == b)),
-                (Value::Object(_a), Value::Object(_b)) => todo!(),
-                (Value::Array, Value::Array) => Ok(TRUE),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::CmpNotEq => self.bin_op(|lhs, rhs| match (lhs, rhs) {
-                (Value::Null, Value::Null) => Ok(FALSE),
-                (Value::Num(a), Value::Num(b)) => Ok(Value::Bool(a != b)),
-                (Value::String(a), Value::String(b)) => Ok(Value::Bool(a != b)),
-                (Value::Object(_a), Value::Object(_b)) => todo!(),
-                (Value::Array, Value::Array) => Ok(FALSE),
-                _ => Err(err("bad type")),
-            })?,
-            Instr::Print => {
-                let val = self.stack.pop().unwrap();
-                writeln!(self.stdout, "{}", val).map_err(|_| err("failed to write to stdout"))?;
-            }
-            Instr::JmpFalse(pos) => {
-                let val = self.stack.pop().unwrap();
-                match val {
-                    Value::Bool(false) => self.pc = (self.pc as isize + pos) as usize,
-                    Value::Bool(true) => {}
-                    _ => return Err(err("bad type")),
-                }
+
+            if let Some(profiler) = &mut self.profiler {
+                profiler.instr(self.current, self.pc - 1);
             }
-            Instr::Jmp(pos) => self.pc = (self.pc as isize + pos) as usize,
-            Instr::Call => self.call()?,
-            Instr::Return => self.ret()?,
-            Instr::Exit => return Err(Box::new(VmErrorInner::Exit)),
-            Instr::ShrinkStack(size) => {
-                assert!(self.stack.len() >= size);
-                let new_len = self.stack.len() - size;
-                // SAFETY: We only ever shrink the vec, and we don't overflow. Value is copy so no leaks as a bonus
-                unsafe { self.stack.set_len(new_len) }
+
+            if let Some(coverage) = &mut self.coverage {
+                coverage.instr(self.current_block_index, self.pc - 1);
             }
+
+            op.run(self)?;
         }
+    }
 
-        Ok(())
+    /// Makes the function the current one, the caller has to set `pc` and the stack frame
+    fn enter_block(&mut self, function: Function) {
+        self.current_block_index = function;
+        self.current = &self.blocks[function];
+        self.ops = self.decoded[function].as_ptr();
+    }
+
+    #[inline(always)]
+    fn push(&mut self, value: Value) {
+        self.stack.push(value.pack(&mut self.alloc));
     }
 
-    fn bin_op<F>(&mut self, f: F) -> VmResult
-    where
-        F: FnOnce(Value, Value) -> Result<Value, VmError>,
-    {
-        let rhs = self.stack.pop().unwrap();
-        let lhs = self.stack.pop().unwrap();
+    #[inline(always)]
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow").unpack()
+    }
+
+    #[inline(always)]
+    fn bin_op(&mut self, f: BinaryOp) -> VmResult {
+        let rhs = self.pop();
+        let lhs = self.pop();
 
         let result = f(lhs, rhs)?;
-        self.stack.push(result);
+        self.push(result);
         Ok(())
     }
 
-    fn call(&mut self) -> VmResult {
-        // save the function to be called
-        let to_be_called_fn = self.stack.pop().unwrap().unwrap_function();
-        let to_be_called_fn_block = &self.blocks[to_be_called_fn];
+    fn call(&mut self, args: u32) -> VmResult {
+        let callee = self.pop();
+        self.call_value(callee, args)
+    }
+
+    /// Calls the method from the field site on the receiver below the `args` arguments, which
+    /// becomes its first argument
+    fn call_method(&mut self, site: u32, args: u32) -> VmResult {
+        let receiver_idx = self.stack.len() - args as usize - 1;
+        let receiver = self.stack[receiver_idx].unpack();
+        let method = get_field(receiver, &self.current.field_sites[site as usize])?;
+        self.call_field(method, receiver_idx, args + 1)
+    }
+
+    /// Calls the function on top of the stack with the elements of the array below it
+    fn call_spread(&mut self) -> VmResult {
+        let callee = self.pop();
+        let array = self.pop();
+        let args = self.push_elements(array)?;
+        self.call_value(callee, args)
+    }
 
-        // create a new frame (the params are already pushed)
-        let new_stack_frame_start = Frame::create(self, to_be_called_fn_block.arity);
+    /// Calls the method from the field site with the elements of the array on top of the stack,
+    /// the first one is the receiver
+    fn call_method_spread(&mut self, site: u32) -> VmResult {
+        let array = self.pop();
+        let args = self.push_elements(array)?;
+        if args == 0 {
+            return Err(err("tried to call a method without a receiver"));
+        }
+        let receiver_idx = self.stack.len() - args as usize;
+        let receiver = self.stack[receiver_idx].unpack();
+        let method = get_field(receiver, &self.current.field_sites[site as usize])?;
+        self.call_field(method, receiver_idx, args)
+    }
+
+    /// Calls the function from a field of the receiver at `receiver_idx`, the first of the `args`
+    /// arguments. Only methods get the receiver as `self`, it is removed for all other functions
+    fn call_field(&mut self, callee: Value, receiver_idx: usize, args: u32) -> VmResult {
+        if matches!(callee, Value::Function(function) if self.blocks[function].is_method) {
+            self.call_value(callee, args)
+        } else {
+            self.stack.remove(receiver_idx);
+            self.call_value(callee, args - 1)
+        }
+    }
+
+    /// Pushes the elements of the array as arguments, and returns how many there are
+    fn push_elements(&mut self, array: Value) -> Result<u32, VmError> {
+        let array = spread(array)?;
+        let args = u32::try_from(array.len()).map_err(|_| err("too many arguments"))?;
+        for &element in array.iter() {
+            self.push(element);
+        }
+        Ok(args)
+    }
+
+    /// Replaces the `len` values on top of the stack with an array of them
+    fn new_array(&mut self, len: u32) {
+        let start = self.stack.len() - len as usize;
+        let elements = self
+            .stack
+            .drain(start..)
+            .map(|value| value.unpack())
+            .collect();
+        let array = self.alloc.alloc_array(elements);
+        self.push(Value::Array(array));
+    }
+
+    fn extend_array(&mut self) -> VmResult {
+        let tail = self.pop();
+        let array = self.pop();
+        let array = concat_arrays(&mut self.alloc, array, tail)?;
+        self.push(array);
+        Ok(())
+    }
+
+    /// Calls the function with the `args` arguments on top of the stack
+    fn call_value(&mut self, callee: Value, args: u32) -> VmResult {
+        let to_be_called_fn = match callee {
+            Value::Function(function) => function,
+            Value::NativeFunction(native) => return self.call_native(native, args),
+            _ => return Err(err("tried to call a value that is not a function")),
+        };
+        let block = &self.blocks[to_be_called_fn];
+        check_arg_count(block.arity, block.required, block.variadic, args)?;
+
+        // create a new frame (the args are already pushed)
+        let new_stack_frame_start = Frame::create(self, args, to_be_called_fn);
 
         self.stack_frame_offset = new_stack_frame_start;
-        self.current_block_index = to_be_called_fn;
-        self.current = to_be_called_fn_block;
+        self.call_depth += 1;
+        self.enter_block(to_be_called_fn);
 
         self.pc = 0;
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(to_be_called_fn);
+        }
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.enter(to_be_called_fn);
+        }
+
         // we are now set up correctly, let the next instruction run
 
         Ok(())
     }
 
+    fn tail_call(&mut self, args: u32) -> VmResult {
+        let to_be_called_fn = match self.pop() {
+            Value::Function(function) => function,
+            Value::NativeFunction(native) => {
+                // natives don't need a stack frame, so this is just a call and a return
+                self.call_native(native, args)?;
+                return self.ret();
+            }
+            _ => return Err(err("tried to call a value that is not a function")),
+        };
+        let block = &self.blocks[to_be_called_fn];
+        check_arg_count(block.arity, block.required, block.variadic, args)?;
+
+        // the callee returns to our caller, so the call depth stays the same
+        Frame::reuse(self, args, to_be_called_fn);
+
+        self.enter_block(to_be_called_fn);
+
+        self.pc = 0;
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
+            profiler.enter(to_be_called_fn);
+        }
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.enter(to_be_called_fn);
+        }
+
+        Ok(())
+    }
+
+    fn call_native(&mut self, native: NativeFunction, args: u32) -> VmResult {
+        let args_start = self.stack.len() - args as usize;
+
+        let mut ctx = NativeCtx {
+            alloc: &mut self.alloc,
+            stdout: &mut *self.stdout,
+            stdin: &mut *self.stdin,
+        };
+        let return_value = native::call(native, &mut ctx, &self.stack[args_start..])?;
+
+        self.stack.truncate(args_start);
+        self.push(return_value);
+
+        Ok(())
+    }
+
     fn ret(&mut self) -> VmResult {
         // we save the return value first.
         let return_value = self.stack.pop().expect("return value");
@@ -275,9 +417,9 @@ impl<'bc> Vm<'bc, '_> {
 
         // get the interpreter back to the nice state
         self.stack_frame_offset = old_stack_offset;
+        self.call_depth -= 1;
         self.pc = old_pc;
-        self.current_block_index = old_function;
-        self.current = &self.blocks[old_function];
+        self.enter_block(old_function);
 
         // and kill the function stack frame
         // note: don't emit a return instruction from the whole global script.
@@ -286,24 +428,11 @@ impl<'bc> Vm<'bc, '_> {
         // everything that remains...
         self.stack.push(return_value);
 
-        Ok(())
-    }
-
-    fn step_debug(&self, current_instr: Instr) {
-        let curr_stack_size = self.stack.len();
-        // at this point, we've always incremented the pc already
-        let expected_stack_size = &self.current.stack_sizes[self.pc - 1];
-
-        eprintln!(
-            "Next Instruction: {current_instr:?}
-Current Stack size: {curr_stack_size}
-Expected Stack size after instruction: {expected_stack_size}
-Stack: {:?}",
-            self.stack
-        );
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
+        }
 
-        let mut buf = [0; 64];
-        let _ = std::io::stdin().read(&mut buf);
+        Ok(())
     }
 }
 
@@ -313,15 +442,484 @@ impl Display for Value {
             Value::Null => f.write_str("null"),
             Value::Bool(bool) => Display::fmt(bool, f),
             Value::Num(num) => Display::fmt(num, f),
+            Value::Int(int) => Display::fmt(int, f),
             Value::String(str) => f.write_str(str.as_str()),
-            Value::Array => todo!(),
-            Value::Object(_) => todo!(),
+            Value::Array(_) | Value::Object(_) => fmt_nested(*self, &mut Vec::new(), f),
             Value::Function(_) => f.write_str("[function]"),
+            Value::NativeFunction(_) => f.write_str("[native function]"),
             Value::NativeU(_) => panic!("Called display on native value!"),
         }
     }
 }
 
-fn err(msg: &'static str) -> VmError {
+/// Writes arrays and objects like `json_stringify` without an indent, with the fields sorted by
+/// name. Nested strings are quoted, and a reference back to an array or object that is currently
+/// being written is written as `[cycle]` instead of an error. `seen` contains their addresses.
+fn fmt_nested(value: Value, seen: &mut Vec<usize>, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let address = match value {
+        Value::Array(array) => array.address(),
+        Value::Object(object) => object.address(),
+        Value::String(str) => return Debug::fmt(str.as_str(), f),
+        value => return Display::fmt(&value, f),
+    };
+    if seen.contains(&address) {
+        return f.write_str("[cycle]");
+    }
+    seen.push(address);
+
+    match value {
+        Value::Array(array) => {
+            f.write_str("[")?;
+            for (i, element) in array.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                fmt_nested(*element, seen, f)?;
+            }
+            f.write_str("]")?;
+        }
+        Value::Object(object) => {
+            let mut entries = object.entries();
+            entries.sort_unstable_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+            f.write_str("{")?;
+            for (i, (name, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{:?}:", name.as_str())?;
+                fmt_nested(value, seen, f)?;
+            }
+            f.write_str("}")?;
+        }
+        _ => unreachable!("only arrays and objects have an address"),
+    }
+
+    seen.pop();
+    Ok(())
+}
+
+// The semantics of the operators, which are shared with the register VM and the constant folding
+// of the optimizer. They are all inlined into the instruction handlers, calling them would spill
+// the values to memory.
+
+pub(crate) type BinaryOp = fn(Value, Value) -> Result<Value, VmError>;
+
+/// The operands of an arithmetic operation. Two ints stay ints, but if one of them is a float, the
+/// int is converted to a float, possibly losing precision
+enum Numbers {
+    Ints(i64, i64),
+    Floats(f64, f64),
+}
+
+#[inline]
+fn numbers(lhs: Value, rhs: Value) -> Result<Numbers, VmError> {
+    match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => Ok(Numbers::Ints(a, b)),
+        (Value::Num(a), Value::Num(b)) => Ok(Numbers::Floats(a, b)),
+        (Value::Int(a), Value::Num(b)) => Ok(Numbers::Floats(a as f64, b)),
+        (Value::Num(a), Value::Int(b)) => Ok(Numbers::Floats(a, b as f64)),
+        _ => Err(err("bad type")),
+    }
+}
+
+/// The operands of a bitwise operation, which only works on ints
+#[inline]
+fn ints(lhs: Value, rhs: Value) -> Result<(i64, i64), VmError> {
+    match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => Ok((a, b)),
+        _ => Err(err("bad type")),
+    }
+}
+
+/// Int operations error instead of wrapping around
+#[inline]
+fn checked(result: Option<i64>) -> Result<Value, VmError> {
+    result
+        .map(Value::Int)
+        .ok_or_else(|| err("integer overflow"))
+}
+
+/// `-` and `not`
+#[inline]
+pub(crate) fn neg(value: Value) -> Result<Value, VmError> {
+    match value {
+        Value::Bool(bool) => Ok(Value::Bool(!bool)),
+        Value::Num(float) => Ok(Value::Num(-float)),
+        Value::Int(int) => checked(int.checked_neg()),
+        _ => Err(err("bad type")),
+    }
+}
+
+#[inline]
+pub(crate) fn add(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match numbers(lhs, rhs)? {
+        Numbers::Ints(a, b) => checked(a.checked_add(b)),
+        Numbers::Floats(a, b) => Ok(Value::Num(a + b)),
+    }
+}
+
+#[inline]
+pub(crate) fn sub(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match numbers(lhs, rhs)? {
+        Numbers::Ints(a, b) => checked(a.checked_sub(b)),
+        Numbers::Floats(a, b) => Ok(Value::Num(a - b)),
+    }
+}
+
+#[inline]
+pub(crate) fn mul(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match numbers(lhs, rhs)? {
+        Numbers::Ints(a, b) => checked(a.checked_mul(b)),
+        Numbers::Floats(a, b) => Ok(Value::Num(a * b)),
+    }
+}
+
+/// `/` always divides floats, use `//` for the integer division
+#[inline]
+pub(crate) fn div(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match numbers(lhs, rhs)? {
+        Numbers::Ints(a, b) => Ok(Value::Num(a as f64 / b as f64)),
+        Numbers::Floats(a, b) => Ok(Value::Num(a / b)),
+    }
+}
+
+/// `//`, rounds the quotient towards negative infinity
+#[inline]
+pub(crate) fn int_div(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match numbers(lhs, rhs)? {
+        Numbers::Ints(_, 0) => Err(err("division by zero")),
+        Numbers::Ints(a, b) => {
+            let quotient = checked(a.checked_div(b))?;
+            match quotient {
+                Value::Int(quotient) if a % b != 0 && (a < 0) != (b < 0) => {
+                    Ok(Value::Int(quotient - 1))
+                }
+                _ => Ok(quotient),
+            }
+        }
+        Numbers::Floats(a, b) => Ok(Value::Num((a / b).floor())),
+    }
+}
+
+/// `%`, the remainder of `//`, so it has the sign of the divisor and `a == a // b * b + a % b`
+#[inline]
+pub(crate) fn rem(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match numbers(lhs, rhs)? {
+        Numbers::Ints(_, 0) => Err(err("division by zero")),
+        Numbers::Ints(a, b) => {
+            // `i64::MIN % -1` is 0, but overflows in the division that `%` does
+            let rem = a.wrapping_rem(b);
+            Ok(Value::Int(if rem != 0 && (rem < 0) != (b < 0) {
+                rem + b
+            } else {
+                rem
+            }))
+        }
+        Numbers::Floats(a, b) => {
+            let rem = a % b;
+            Ok(Value::Num(if rem != 0.0 && (rem < 0.0) != (b < 0.0) {
+                rem + b
+            } else {
+                rem
+            }))
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn and(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match (lhs, rhs) {
+        (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a && b)),
+        _ => Err(err("bad type")),
+    }
+}
+
+#[inline]
+pub(crate) fn or(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match (lhs, rhs) {
+        (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a || b)),
+        _ => Err(err("bad type")),
+    }
+}
+
+#[inline]
+pub(crate) fn bit_and(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    let (a, b) = ints(lhs, rhs)?;
+    Ok(Value::Int(a & b))
+}
+
+#[inline]
+pub(crate) fn bit_or(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    let (a, b) = ints(lhs, rhs)?;
+    Ok(Value::Int(a | b))
+}
+
+#[inline]
+pub(crate) fn bit_xor(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    let (a, b) = ints(lhs, rhs)?;
+    Ok(Value::Int(a ^ b))
+}
+
+/// `<<`, shifting bits out of the int is an overflow
+#[inline]
+pub(crate) fn shl(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    let (a, b) = ints(lhs, rhs)?;
+    let shift = shift_amount(b)?;
+    let result = a << shift;
+    checked((result >> shift == a).then_some(result))
+}
+
+/// `>>`, keeps the sign
+#[inline]
+pub(crate) fn shr(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    let (a, b) = ints(lhs, rhs)?;
+    Ok(Value::Int(a >> shift_amount(b)?))
+}
+
+#[inline]
+fn shift_amount(amount: i64) -> Result<u32, VmError> {
+    match amount {
+        0..=63 => Ok(amount as u32),
+        _ => Err(err("shift amount out of range")),
+    }
+}
+
+/// Compares two numbers by their exact value, so ints are never rounded to a float. `None` if one
+/// of them is NaN
+#[inline]
+fn compare_numbers(lhs: Value, rhs: Value) -> Result<Option<Ordering>, VmError> {
+    match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => Ok(Some(a.cmp(&b))),
+        (Value::Num(a), Value::Num(b)) => Ok(a.partial_cmp(&b)),
+        (Value::Int(a), Value::Num(b)) => Ok(compare_int_float(a, b)),
+        (Value::Num(a), Value::Int(b)) => Ok(compare_int_float(b, a).map(Ordering::reverse)),
+        _ => Err(err("bad type")),
+    }
+}
+
+fn compare_int_float(int: i64, float: f64) -> Option<Ordering> {
+    // 2^63, every float that is at least this big is bigger than all ints
+    const INT_END: f64 = 9_223_372_036_854_775_808.0;
+
+    if float.is_nan() {
+        None
+    } else if float >= INT_END {
+        Some(Ordering::Less)
+    } else if float < -INT_END {
+        Some(Ordering::Greater)
+    } else {
+        // the integer part fits into an int now, and only the fraction decides if they are equal
+        let trunc = float.trunc();
+        match int.cmp(&(trunc as i64)) {
+            Ordering::Equal => 0.0.partial_cmp(&(float - trunc)),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn greater(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match (lhs, rhs) {
+        (Value::String(a), Value::String(b)) => Ok(Value::Bool(a.as_str() > b.as_str())),
+        _ => Ok(Value::Bool(
+            compare_numbers(lhs, rhs)? == Some(Ordering::Greater),
+        )),
+    }
+}
+
+#[inline]
+pub(crate) fn greater_eq(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match (lhs, rhs) {
+        (Value::String(a), Value::String(b)) => Ok(Value::Bool(a.as_str() >= b.as_str())),
+        _ => Ok(Value::Bool(matches!(
+            compare_numbers(lhs, rhs)?,
+            Some(Ordering::Greater | Ordering::Equal)
+        ))),
+    }
+}
+
+#[inline]
+pub(crate) fn less(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match (lhs, rhs) {
+        (Value::String(a), Value::String(b)) => Ok(Value::Bool(a.as_str() < b.as_str())),
+        _ => Ok(Value::Bool(
+            compare_numbers(lhs, rhs)? == Some(Ordering::Less),
+        )),
+    }
+}
+
+#[inline]
+pub(crate) fn less_eq(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match (lhs, rhs) {
+        (Value::String(a), Value::String(b)) => Ok(Value::Bool(a.as_str() <= b.as_str())),
+        _ => Ok(Value::Bool(matches!(
+            compare_numbers(lhs, rhs)?,
+            Some(Ordering::Less | Ordering::Equal)
+        ))),
+    }
+}
+
+#[inline]
+pub(crate) fn eq(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match (lhs, rhs) {
+        (Value::Null, Value::Null) => Ok(TRUE),
+        (Value::String(a), Value::String(b)) => Ok(Value::Bool(a == b)),
+        (Value::Object(a), Value::Object(b)) => Ok(Value::Bool(a == b)),
+        (Value::Array(a), Value::Array(b)) => Ok(Value::Bool(a == b)),
+        _ => Ok(Value::Bool(
+            compare_numbers(lhs, rhs)? == Some(Ordering::Equal),
+        )),
+    }
+}
+
+#[inline]
+pub(crate) fn not_eq(lhs: Value, rhs: Value) -> Result<Value, VmError> {
+    match (lhs, rhs) {
+        (Value::Null, Value::Null) => Ok(FALSE),
+        (Value::String(a), Value::String(b)) => Ok(Value::Bool(a != b)),
+        (Value::Object(a), Value::Object(b)) => Ok(Value::Bool(a != b)),
+        (Value::Array(a), Value::Array(b)) => Ok(Value::Bool(a != b)),
+        _ => Ok(Value::Bool(
+            compare_numbers(lhs, rhs)? != Some(Ordering::Equal),
+        )),
+    }
+}
+
+/// Reads the field of the object, using the inline cache of the site. Fields that the object
+/// inherits from its prototypes are looked up without the cache.
+#[inline]
+pub(super) fn get_field(object: Value, site: &FieldSite) -> Result<Value, VmError> {
+    match object {
+        Value::Object(object) => object
+            .get_cached(site.name, &site.cache)
+            .or_else(|| object.prototype()?.lookup(site.name))
+            .ok_or_else(|| err("field not found")),
+        _ => Err(err(
+            "tried to access a field of a value that is not an object",
+        )),
+    }
+}
+
+/// Sets or adds the field of the object, using the inline cache of the site
+#[inline]
+pub(super) fn set_field(
+    alloc: &mut RtAlloc,
+    object: Value,
+    site: &FieldSite,
+    value: Value,
+) -> VmResult {
+    match object {
+        Value::Object(object) => {
+            object.set_cached(alloc, site.name, value, &site.cache);
+            Ok(())
+        }
+        _ => Err(err(
+            "tried to access a field of a value that is not an object",
+        )),
+    }
+}
+
+// The operations for `match`, they never fail on values of the wrong type, the value just doesn't
+// match then
+
+/// Whether the value is equal to the literal of a pattern, which is never an object or array. An
+/// int and a float are equal if they have the same value, like with `==`
+pub(super) fn matches_literal(value: Value, literal: Value) -> bool {
+    match (value, literal) {
+        (Value::Null, Value::Null) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Num(_) | Value::Int(_), Value::Num(_) | Value::Int(_)) => {
+            matches!(compare_numbers(value, literal), Ok(Some(Ordering::Equal)))
+        }
+        _ => false,
+    }
+}
+
+/// Whether the value is an array with exactly `len` elements
+pub(super) fn matches_array(value: Value, len: u32) -> bool {
+    matches!(value, Value::Array(array) if array.len() == len as usize)
+}
+
+/// Whether the value is an array with at least `len` elements
+pub(super) fn matches_array_min(value: Value, len: u32) -> bool {
+    matches!(value, Value::Array(array) if array.len() >= len as usize)
+}
+
+/// Whether the value is an object that has or inherits the field
+pub(super) fn has_field(value: Value, site: &FieldSite) -> bool {
+    matches!(value, Value::Object(object) if object.lookup(site.name).is_some())
+}
+
+/// Errors if the function can't be called with `args` arguments, it needs at least one for every
+/// parameter without a default, and only takes more than its arity with a rest parameter
+pub(super) fn check_arg_count(arity: u32, required: u32, variadic: bool, args: u32) -> VmResult {
+    if args < required || (!variadic && args > arity) {
+        return Err(err("wrong number of arguments"));
+    }
+    Ok(())
+}
+
+/// The array that is spread into arguments
+pub(super) fn spread(value: Value) -> Result<Array, VmError> {
+    match value {
+        Value::Array(array) => Ok(array),
+        _ => Err(err("tried to spread a value that is not an array")),
+    }
+}
+
+/// A new array with the elements of both arrays
+pub(super) fn concat_arrays(
+    alloc: &mut RtAlloc,
+    array: Value,
+    tail: Value,
+) -> Result<Value, VmError> {
+    let (array, tail) = (spread(array)?, spread(tail)?);
+    let elements = array.iter().chain(tail.iter()).copied().collect();
+    Ok(Value::Array(alloc.alloc_array(elements)))
+}
+
+/// Reads an element of an array, which has been checked by `matches_array` before
+pub(super) fn get_element(array: Value, index: u32) -> Result<Value, VmError> {
+    match array {
+        Value::Array(array) => array
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| err("index out of bounds")),
+        _ => Err(err(
+            "tried to get an element of a value that is not an array",
+        )),
+    }
+}
+
+/// A new array with the elements from `start` on, which has been checked by `matches_array_min`
+/// before
+pub(super) fn slice_array(alloc: &mut RtAlloc, array: Value, start: u32) -> Result<Value, VmError> {
+    match array {
+        Value::Array(array) => {
+            let elements = array
+                .get(start as usize..)
+                .ok_or_else(|| err("index out of bounds"))?;
+            Ok(Value::Array(alloc.alloc_array(elements.to_vec())))
+        }
+        _ => Err(err("tried to slice a value that is not an array")),
+    }
+}
+
+/// The error of a value that doesn't have the shape that `let` or a parameter destructures
+pub(super) fn match_failed() -> VmError {
+    err("the value doesn't match the pattern")
+}
+
+/// Whether a conditional jump is taken, only booleans are allowed as conditions
+#[inline]
+pub(super) fn condition(value: Value) -> Result<bool, VmError> {
+    match value {
+        Value::Bool(bool) => Ok(bool),
+        _ => Err(err("bad type")),
+    }
+}
+
+pub(super) fn err(msg: &'static str) -> VmError {
     Box::new(VmErrorInner::Error(msg))
 }
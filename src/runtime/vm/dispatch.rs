@@ -0,0 +1,321 @@
+//! The instruction handlers of the VM
+//!
+//! Before execution, the code of every function is decoded into [`Op`]s, which hold a pointer to
+//! the handler of the instruction and its operand. Running an instruction is then just an indirect
+//! call, instead of matching on the `Instr` again every time it is executed.
+//!
+//! There are two loops calling the handlers, see `Vm::execute_function`: the fast one runs the
+//! handlers back to back, and the other one calls the debugger, profiler and coverage before every
+//! instruction.
+
+use crate::runtime::{
+    bytecode::{FnBlock, Instr},
+    vm::{self, err, Value, Vm, VmErrorInner, VmResult, FALSE, TRUE},
+};
+
+type Handler = fn(&mut Vm<'_, '_>, usize) -> VmResult;
+
+/// A decoded instruction
+#[derive(Clone, Copy)]
+pub(super) struct Op {
+    handler: Handler,
+    /// The operand of the instruction, jump offsets are stored as their bits
+    operand: usize,
+}
+
+/// Decodes the code of every function, in the same order as the blocks
+pub(super) fn decode(blocks: &[FnBlock<'_>]) -> Vec<Box<[Op]>> {
+    blocks
+        .iter()
+        .map(|block| {
+            block
+                .code
+                .iter()
+                .map(|instr| decode_instr(*instr))
+                .collect()
+        })
+        .collect()
+}
+
+fn decode_instr(instr: Instr) -> Op {
+    let (handler, operand): (Handler, usize) = match instr {
+        Instr::Nop => (nop, 0),
+        Instr::Store(offset) => (store, offset),
+        Instr::Load(offset) => (load, offset),
+        Instr::PushConst(index) => (push_const, index as usize),
+        Instr::PushNull => (push_null, 0),
+        Instr::PushTrue => (push_true, 0),
+        Instr::PushFalse => (push_false, 0),
+        Instr::Dup => (dup, 0),
+        Instr::Neg => (neg, 0),
+        Instr::NewObject => (new_object, 0),
+        Instr::NewArray(len) => (new_array, len as usize),
+        Instr::ExtendArray => (extend_array, 0),
+        Instr::GetField(site) => (get_field, site as usize),
+        Instr::SetField(site) => (set_field, site as usize),
+        Instr::MatchConst(index) => (match_const, index as usize),
+        Instr::MatchArray(len) => (match_array, len as usize),
+        Instr::MatchArrayMin(len) => (match_array_min, len as usize),
+        Instr::HasField(site) => (has_field, site as usize),
+        Instr::GetElement(index) => (get_element, index as usize),
+        Instr::SliceArray(start) => (slice_array, start as usize),
+        Instr::MatchFailed => (match_failed, 0),
+        Instr::BinAdd => (bin_add, 0),
+        Instr::BinSub => (bin_sub, 0),
+        Instr::BinMul => (bin_mul, 0),
+        Instr::BinDiv => (bin_div, 0),
+        Instr::BinIntDiv => (bin_int_div, 0),
+        Instr::BinMod => (bin_mod, 0),
+        Instr::BinAnd => (bin_and, 0),
+        Instr::BinOr => (bin_or, 0),
+        Instr::BinBitAnd => (bin_bit_and, 0),
+        Instr::BinBitOr => (bin_bit_or, 0),
+        Instr::BinBitXor => (bin_bit_xor, 0),
+        Instr::BinShl => (bin_shl, 0),
+        Instr::BinShr => (bin_shr, 0),
+        Instr::CmpGreater => (cmp_greater, 0),
+        Instr::CmpGreaterEq => (cmp_greater_eq, 0),
+        Instr::CmpLess => (cmp_less, 0),
+        Instr::CmpLessEq => (cmp_less_eq, 0),
+        Instr::CmpEq => (cmp_eq, 0),
+        Instr::CmpNotEq => (cmp_not_eq, 0),
+        Instr::Print => (print, 0),
+        Instr::JmpFalse(offset) => (jmp_false, offset as usize),
+        Instr::Jmp(offset) => (jmp, offset as usize),
+        Instr::JmpNull(offset) => (jmp_null, offset as usize),
+        Instr::Call(args) => (call, args as usize),
+        Instr::CallSpread => (call_spread, 0),
+        Instr::CallMethodSpread(site) => (call_method_spread, site as usize),
+        Instr::CallMethod { .. } => (call_method, 0),
+        Instr::Return => (ret, 0),
+        Instr::TailCall(args) => (tail_call, args as usize),
+        Instr::Exit => (exit, 0),
+        Instr::ShrinkStack(amount) => (shrink_stack, amount),
+    };
+    Op { handler, operand }
+}
+
+impl Op {
+    #[inline(always)]
+    pub(super) fn run(self, vm: &mut Vm<'_, '_>) -> VmResult {
+        (self.handler)(vm, self.operand)
+    }
+}
+
+fn nop(_: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    Ok(())
+}
+
+fn store(vm: &mut Vm<'_, '_>, offset: usize) -> VmResult {
+    let val = vm.stack.pop().unwrap();
+    vm.stack[vm.stack_frame_offset + offset] = val;
+    Ok(())
+}
+
+fn load(vm: &mut Vm<'_, '_>, offset: usize) -> VmResult {
+    vm.stack.push(vm.stack[vm.stack_frame_offset + offset]);
+    Ok(())
+}
+
+fn push_const(vm: &mut Vm<'_, '_>, index: usize) -> VmResult {
+    vm.push(vm.current.consts[index]);
+    Ok(())
+}
+
+fn push_null(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    vm.push(Value::Null);
+    Ok(())
+}
+
+fn push_true(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    vm.push(TRUE);
+    Ok(())
+}
+
+fn push_false(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    vm.push(FALSE);
+    Ok(())
+}
+
+fn dup(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    vm.stack.push(vm.stack[vm.stack.len() - 1]);
+    Ok(())
+}
+
+fn neg(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    let val = vm.pop();
+    vm.push(vm::neg(val)?);
+    Ok(())
+}
+
+fn new_object(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    let object = vm.alloc.alloc_obj([]);
+    vm.push(Value::Object(object));
+    Ok(())
+}
+
+fn new_array(vm: &mut Vm<'_, '_>, len: usize) -> VmResult {
+    vm.new_array(len as u32);
+    Ok(())
+}
+
+fn extend_array(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    vm.extend_array()
+}
+
+fn get_field(vm: &mut Vm<'_, '_>, site: usize) -> VmResult {
+    let object = vm.pop();
+    let value = vm::get_field(object, &vm.current.field_sites[site])?;
+    vm.push(value);
+    Ok(())
+}
+
+fn set_field(vm: &mut Vm<'_, '_>, site: usize) -> VmResult {
+    let value = vm.pop();
+    let object = vm.pop();
+    vm::set_field(&mut vm.alloc, object, &vm.current.field_sites[site], value)
+}
+
+fn match_const(vm: &mut Vm<'_, '_>, index: usize) -> VmResult {
+    let val = vm.pop();
+    vm.push(Value::Bool(vm::matches_literal(
+        val,
+        vm.current.consts[index],
+    )));
+    Ok(())
+}
+
+fn match_array(vm: &mut Vm<'_, '_>, len: usize) -> VmResult {
+    let val = vm.pop();
+    vm.push(Value::Bool(vm::matches_array(val, len as u32)));
+    Ok(())
+}
+
+fn match_array_min(vm: &mut Vm<'_, '_>, len: usize) -> VmResult {
+    let val = vm.pop();
+    vm.push(Value::Bool(vm::matches_array_min(val, len as u32)));
+    Ok(())
+}
+
+fn has_field(vm: &mut Vm<'_, '_>, site: usize) -> VmResult {
+    let val = vm.pop();
+    vm.push(Value::Bool(vm::has_field(
+        val,
+        &vm.current.field_sites[site],
+    )));
+    Ok(())
+}
+
+fn get_element(vm: &mut Vm<'_, '_>, index: usize) -> VmResult {
+    let array = vm.pop();
+    vm.push(vm::get_element(array, index as u32)?);
+    Ok(())
+}
+
+fn slice_array(vm: &mut Vm<'_, '_>, start: usize) -> VmResult {
+    let array = vm.pop();
+    let slice = vm::slice_array(&mut vm.alloc, array, start as u32)?;
+    vm.push(slice);
+    Ok(())
+}
+
+fn match_failed(_: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    Err(vm::match_failed())
+}
+
+/// Handlers for the binary operations, which all work the same
+macro_rules! binary_handlers {
+    ($($name:ident => $op:path),* $(,)?) => {$(
+        fn $name(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+            vm.bin_op($op)
+        }
+    )*};
+}
+
+binary_handlers! {
+    bin_add => vm::add,
+    bin_sub => vm::sub,
+    bin_mul => vm::mul,
+    bin_div => vm::div,
+    bin_int_div => vm::int_div,
+    bin_mod => vm::rem,
+    bin_and => vm::and,
+    bin_or => vm::or,
+    bin_bit_and => vm::bit_and,
+    bin_bit_or => vm::bit_or,
+    bin_bit_xor => vm::bit_xor,
+    bin_shl => vm::shl,
+    bin_shr => vm::shr,
+    cmp_greater => vm::greater,
+    cmp_greater_eq => vm::greater_eq,
+    cmp_less => vm::less,
+    cmp_less_eq => vm::less_eq,
+    cmp_eq => vm::eq,
+    cmp_not_eq => vm::not_eq,
+}
+
+fn print(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    let val = vm.pop();
+    writeln!(vm.stdout, "{}", val).map_err(|_| err("failed to write to stdout"))
+}
+
+fn jmp_false(vm: &mut Vm<'_, '_>, offset: usize) -> VmResult {
+    let val = vm.pop();
+    if !vm::condition(val)? {
+        vm.pc = (vm.pc as isize + offset as isize) as usize;
+    }
+    Ok(())
+}
+
+fn jmp(vm: &mut Vm<'_, '_>, offset: usize) -> VmResult {
+    vm.pc = (vm.pc as isize + offset as isize) as usize;
+    Ok(())
+}
+
+fn jmp_null(vm: &mut Vm<'_, '_>, offset: usize) -> VmResult {
+    if let Value::Null = vm.stack[vm.stack.len() - 1].unpack() {
+        vm.pc = (vm.pc as isize + offset as isize) as usize;
+    }
+    Ok(())
+}
+
+fn call(vm: &mut Vm<'_, '_>, args: usize) -> VmResult {
+    vm.call(args as u32)
+}
+
+fn call_spread(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    vm.call_spread()
+}
+
+fn call_method_spread(vm: &mut Vm<'_, '_>, site: usize) -> VmResult {
+    vm.call_method_spread(site as u32)
+}
+
+/// `CallMethod` has two operands, which don't fit into the operand of the `Op`, so they are read
+/// from the instruction itself
+fn call_method(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    match vm.current.code[vm.pc - 1] {
+        Instr::CallMethod { site, args } => vm.call_method(site, args),
+        _ => unreachable!("decoded from another instruction"),
+    }
+}
+
+fn ret(vm: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    vm.ret()
+}
+
+fn tail_call(vm: &mut Vm<'_, '_>, args: usize) -> VmResult {
+    vm.tail_call(args as u32)
+}
+
+fn exit(_: &mut Vm<'_, '_>, _: usize) -> VmResult {
+    Err(Box::new(VmErrorInner::Exit))
+}
+
+fn shrink_stack(vm: &mut Vm<'_, '_>, amount: usize) -> VmResult {
+    assert!(vm.stack.len() >= amount);
+    let new_len = vm.stack.len() - amount;
+    // SAFETY: We only ever shrink the vec, and we don't overflow. Value is copy so no leaks as a bonus
+    unsafe { vm.stack.set_len(new_len) }
+    Ok(())
+}
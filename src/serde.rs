@@ -0,0 +1,53 @@
+//!
+//! Conversions between Rust types and dilaria values using `serde`
+//!
+//! Serializing produces a [`Value`](crate::Value) that is allocated on the GC heap of the passed
+//! [`RtAlloc`](crate::RtAlloc).
+//! Structs and maps become objects, sequences and tuples become arrays, integers become ints and
+//! floats become floats. A `u64` that doesn't fit into an int becomes a float and loses precision.
+//! Enums are represented externally tagged, like `serde_json` does it.
+//!
+//! Deserializing reads such a value back into any type implementing `Deserialize`. Integer targets
+//! also accept floats without a fractional part.
+
+mod de;
+mod ser;
+
+use std::fmt::{Display, Formatter};
+
+pub use de::from_value;
+pub use ser::{to_value, Serializer};
+
+/// An error that happened while converting between Rust types and dilaria values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    message: String,
+}
+
+impl Error {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ::serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::new(msg.to_string())
+    }
+}
+
+impl ::serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::new(msg.to_string())
+    }
+}
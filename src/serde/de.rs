@@ -0,0 +1,265 @@
+//! Deserializing dilaria values into Rust values
+
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, Unexpected, Visitor},
+    forward_to_deserialize_any,
+};
+
+use crate::{
+    runtime::{
+        gc::{Array, Symbol},
+        vm::Value,
+    },
+    serde::Error,
+};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Deserializes a `T` from a dilaria [`Value`]
+pub fn from_value<T>(value: Value) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(value)
+}
+
+impl Value {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Value::Null => Unexpected::Unit,
+            Value::Bool(bool) => Unexpected::Bool(*bool),
+            Value::Num(num) => Unexpected::Float(*num),
+            Value::Int(int) => Unexpected::Signed(*int),
+            Value::String(str) => Unexpected::Str(str.as_str()),
+            Value::Array(_) => Unexpected::Seq,
+            Value::Object(_) => Unexpected::Map,
+            Value::Function(_) | Value::NativeFunction(_) => Unexpected::Other("function"),
+            Value::NativeU(_) => Unexpected::Other("native value"),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(bool) => visitor.visit_bool(bool),
+            // integer targets only accept integers, so give them one if it's lossless
+            Value::Num(num)
+                if num.fract() == 0.0 && num >= i64::MIN as f64 && num < i64::MAX as f64 =>
+            {
+                visitor.visit_i64(num as i64)
+            }
+            Value::Num(num) => visitor.visit_f64(num),
+            Value::Int(int) => visitor.visit_i64(int),
+            Value::String(str) => visitor.visit_str(str.as_str()),
+            Value::Array(array) => visitor.visit_seq(ArrayAccess { array, index: 0 }),
+            Value::Object(obj) => visitor.visit_map(ObjectAccess {
+                entries: obj.entries(),
+                value: None,
+            }),
+            Value::Function(_) | Value::NativeFunction(_) | Value::NativeU(_) => {
+                Err(de::Error::invalid_type(self.unexpected(), &visitor))
+            }
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::String(variant) => visitor.visit_enum(EnumAccess {
+                variant,
+                value: None,
+            }),
+            Value::Object(obj) if obj.len() == 1 => {
+                let (variant, value) = obj.entries()[0];
+                visitor.visit_enum(EnumAccess {
+                    variant,
+                    value: Some(value),
+                })
+            }
+            _ => Err(de::Error::invalid_type(
+                self.unexpected(),
+                &"a string or an object with a single key",
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct ArrayAccess {
+    array: Array,
+    index: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for ArrayAccess {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.array.get(self.index) {
+            Some(&value) => {
+                self.index += 1;
+                seed.deserialize(value).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.array.len() - self.index)
+    }
+}
+
+struct ObjectAccess {
+    entries: Vec<(Symbol, Value)>,
+    value: Option<Value>,
+}
+
+impl<'de> de::MapAccess<'de> for ObjectAccess {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.entries.pop() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Value::String(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let value = self
+            .value
+            .take()
+            .expect("next_value_seed called before next_key_seed");
+        seed.deserialize(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess {
+    variant: Symbol,
+    value: Option<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = Error;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(Value::String(self.variant))?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess {
+    value: Option<Value>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        match self.value {
+            None | Some(Value::Null) => Ok(()),
+            Some(value) => Err(de::Error::invalid_type(value.unexpected(), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.value {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Some(Value::Array(array)) => visitor.visit_seq(ArrayAccess { array, index: 0 }),
+            Some(value) => Err(de::Error::invalid_type(
+                value.unexpected(),
+                &"tuple variant",
+            )),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Some(value @ Value::Object(_)) => de::Deserializer::deserialize_any(value, visitor),
+            Some(value) => Err(de::Error::invalid_type(
+                value.unexpected(),
+                &"struct variant",
+            )),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"struct variant",
+            )),
+        }
+    }
+}
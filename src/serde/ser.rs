@@ -0,0 +1,400 @@
+//! Serializing Rust values into dilaria values
+
+use serde::{ser, Serialize};
+
+use crate::{
+    runtime::{
+        gc::{RtAlloc, Symbol},
+        vm::Value,
+    },
+    serde::Error,
+    HashMap,
+};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Serializes `value` into a dilaria [`Value`], allocating all strings, arrays and objects in `rt`
+pub fn to_value<T>(value: &T, rt: &mut RtAlloc) -> Result<Value>
+where
+    T: Serialize + ?Sized,
+{
+    value.serialize(Serializer::new(rt))
+}
+
+/// A `serde::Serializer` whose output is a dilaria [`Value`]
+pub struct Serializer<'rt> {
+    rt: &'rt mut RtAlloc,
+}
+
+impl<'rt> Serializer<'rt> {
+    pub fn new(rt: &'rt mut RtAlloc) -> Self {
+        Self { rt }
+    }
+}
+
+/// Wraps the value of an enum variant into an object `{ variant: value }`
+fn wrap_variant(rt: &mut RtAlloc, variant: &'static str, value: Value) -> Value {
+    let mut map = HashMap::default();
+    map.insert(rt.intern_string(variant), value);
+    Value::Object(rt.alloc_obj(map))
+}
+
+impl<'rt> ser::Serializer for Serializer<'rt> {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SerializeArray<'rt>;
+    type SerializeTuple = SerializeArray<'rt>;
+    type SerializeTupleStruct = SerializeArray<'rt>;
+    type SerializeTupleVariant = SerializeTupleVariant<'rt>;
+    type SerializeMap = SerializeObject<'rt>;
+    type SerializeStruct = SerializeObject<'rt>;
+    type SerializeStructVariant = SerializeStructVariant<'rt>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        match i64::try_from(v) {
+            Ok(int) => self.serialize_i64(int),
+            // too big for an int, so it loses precision as a float
+            Err(_) => self.serialize_f64(v as f64),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(Value::Num(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::String(self.rt.intern_string(v)))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        let elements = v.iter().map(|&byte| Value::Int(byte.into())).collect();
+        Ok(Value::Array(self.rt.alloc_array(elements)))
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Value>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = value.serialize(Serializer::new(self.rt))?;
+        Ok(wrap_variant(self.rt, variant, value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SerializeArray {
+            rt: self.rt,
+            elements: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Ok(SerializeTupleVariant {
+            variant,
+            array: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(SerializeObject {
+            rt: self.rt,
+            map: HashMap::default(),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Ok(SerializeStructVariant {
+            variant,
+            object: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+pub struct SerializeArray<'rt> {
+    rt: &'rt mut RtAlloc,
+    elements: Vec<Value>,
+}
+
+impl SerializeArray<'_> {
+    fn push<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = value.serialize(Serializer::new(self.rt))?;
+        self.elements.push(value);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Array(self.rt.alloc_array(self.elements)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeTupleVariant<'rt> {
+    variant: &'static str,
+    array: SerializeArray<'rt>,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.array.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        let SerializeArray { rt, elements } = self.array;
+        let array = Value::Array(rt.alloc_array(elements));
+        Ok(wrap_variant(rt, self.variant, array))
+    }
+}
+
+pub struct SerializeObject<'rt> {
+    rt: &'rt mut RtAlloc,
+    map: HashMap<Symbol, Value>,
+    next_key: Option<Symbol>,
+}
+
+impl SerializeObject<'_> {
+    fn key<T>(&mut self, key: &T) -> Result<Symbol>
+    where
+        T: Serialize + ?Sized,
+    {
+        match key.serialize(Serializer::new(self.rt))? {
+            Value::String(sym) => Ok(sym),
+            Value::Num(num) => Ok(self.rt.intern_string(&num.to_string())),
+            Value::Int(int) => Ok(self.rt.intern_string(&int.to_string())),
+            Value::Bool(bool) => Ok(self.rt.intern_string(&bool.to_string())),
+            _ => Err(Error::new("object keys must be strings")),
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeObject<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.next_key = Some(self.key(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        let value = value.serialize(Serializer::new(self.rt))?;
+        self.map.insert(key, value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Object(self.rt.alloc_obj(self.map)))
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self.rt.intern_string(key);
+        let value = value.serialize(Serializer::new(self.rt))?;
+        self.map.insert(key, value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+pub struct SerializeStructVariant<'rt> {
+    variant: &'static str,
+    object: SerializeObject<'rt>,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeStruct::serialize_field(&mut self.object, key, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        let SerializeObject { rt, map, .. } = self.object;
+        let object = Value::Object(rt.alloc_obj(map));
+        Ok(wrap_variant(rt, self.variant, object))
+    }
+}
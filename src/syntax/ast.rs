@@ -18,6 +18,8 @@ pub type Program<'ast> = Block<'ast>;
 #[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 pub struct Block<'ast> {
     pub stmts: &'ast [Stmt<'ast>],
+    /// The expression without a semicolon at the end of the block, which is the value of the block
+    pub value: Option<Expr<'ast>>,
     pub span: Span,
 }
 
@@ -30,6 +32,7 @@ pub enum Stmt<'ast> {
     If(IfStmt<'ast>),
     Loop(Block<'ast>, Span),
     While(WhileStmt<'ast>),
+    Match(MatchStmt<'ast>),
     Break(Span),
     Return(Option<Expr<'ast>>, Span),
     Block(Block<'ast>),
@@ -41,8 +44,11 @@ pub enum Stmt<'ast> {
 #[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 pub struct Declaration<'ast> {
     pub span: Span,
-    pub name: Ident,
+    /// A name, or an array or object pattern that destructures the value
+    pub pattern: Pattern<'ast>,
     pub init: Expr<'ast>,
+    /// Declared with `const` instead of `let`, the variables can't be assigned to
+    pub constant: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -51,17 +57,34 @@ pub struct Assignment<'ast> {
     pub span: Span,
     pub lhs: Expr<'ast>,
     pub rhs: Expr<'ast>,
+    /// For compound assignments like `x += 1`, the operation that combines the old value with `rhs`
+    pub op: Option<BinaryOpKind>,
 }
 
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 pub struct FnDecl<'ast> {
     pub span: Span,
+    /// For a method `fn Point.len(self)`, the object that the method is stored in
+    pub object: Option<Ident>,
     pub name: Ident,
-    pub params: &'ast [Ident],
+    pub params: &'ast [Param<'ast>],
     pub body: Block<'ast>,
 }
 
+/// A parameter of a function, `name`, `name = default` or `...name`. Instead of a name, the
+/// parameter can also be an array or object pattern that destructures the argument.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub struct Param<'ast> {
+    pub span: Span,
+    pub pattern: Pattern<'ast>,
+    /// Evaluated when the function is called without this argument, or with `null`
+    pub default: Option<Expr<'ast>>,
+    /// `...name` collects all remaining arguments into an array, it is always the last parameter
+    pub rest: bool,
+}
+
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 pub struct IfStmt<'ast> {
@@ -94,6 +117,95 @@ pub struct WhileStmt<'ast> {
     pub body: Block<'ast>,
 }
 
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub struct MatchStmt<'ast> {
+    pub span: Span,
+    pub scrutinee: Expr<'ast>,
+    pub arms: &'ast [MatchArm<'ast>],
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub struct MatchArm<'ast> {
+    pub span: Span,
+    pub pattern: Pattern<'ast>,
+    /// The arm is only taken if the guard in `pattern if guard => {}` is true
+    pub guard: Option<Expr<'ast>>,
+    pub body: Block<'ast>,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub enum Pattern<'ast> {
+    /// A string, number, boolean or `null`, which the value must be equal to
+    Literal(Literal<'ast>),
+    /// Matches every value and binds it to the name
+    Binding(Ident),
+    /// `_`, matches every value
+    Wildcard(Span),
+    /// Matches an array with exactly as many elements. With a `...rest` pattern at the end, the
+    /// array can have more elements, and the rest pattern matches the array of the remaining ones.
+    Array(&'ast [Pattern<'ast>], Option<&'ast Pattern<'ast>>, Span),
+    /// Matches an object that has all of the fields, other fields are ignored
+    Object(&'ast [FieldPattern<'ast>], Span),
+    /// `a | b`, matches if one of the patterns matches
+    Or(&'ast [Pattern<'ast>], Span),
+}
+
+impl Pattern<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Literal(lit) => lit.span(),
+            Pattern::Binding(Ident { span, .. }) => *span,
+            Pattern::Wildcard(span)
+            | Pattern::Array(_, _, span)
+            | Pattern::Object(_, span)
+            | Pattern::Or(_, span) => *span,
+        }
+    }
+
+    /// Whether the pattern matches every value
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Binding(_) | Pattern::Wildcard(_) => true,
+            Pattern::Or(patterns, _) => patterns.iter().any(Pattern::is_irrefutable),
+            Pattern::Literal(_) | Pattern::Array(..) | Pattern::Object(..) => false,
+        }
+    }
+
+    /// Adds the names bound by the pattern, in order
+    pub fn bindings(&self, bindings: &mut std::vec::Vec<Ident>) {
+        match self {
+            Pattern::Binding(ident) => bindings.push(*ident),
+            Pattern::Array(patterns, rest, _) => {
+                for pattern in patterns.iter().chain(*rest) {
+                    pattern.bindings(bindings);
+                }
+            }
+            Pattern::Or(patterns, _) => {
+                for pattern in patterns.iter() {
+                    pattern.bindings(bindings);
+                }
+            }
+            Pattern::Object(fields, _) => {
+                for field in fields.iter() {
+                    field.pattern.bindings(bindings);
+                }
+            }
+            Pattern::Literal(_) | Pattern::Wildcard(_) => {}
+        }
+    }
+}
+
+/// `name: pattern` in an object pattern, `name` alone is short for `name: name`
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub struct FieldPattern<'ast> {
+    pub name: Ident,
+    pub pattern: Pattern<'ast>,
+}
+
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 pub enum Expr<'ast> {
@@ -102,6 +214,15 @@ pub enum Expr<'ast> {
     UnaryOp(&'ast UnaryOp<'ast>),
     BinaryOp(&'ast BinaryOp<'ast>),
     Call(&'ast Call<'ast>),
+    /// `if` as an expression, without an `else` its value is `null` if the condition is false
+    If(&'ast IfStmt<'ast>),
+    /// A block as an expression, see [`Block::value`]
+    Block(&'ast Block<'ast>),
+    /// `match` as an expression, its value is `null` if no arm matches
+    Match(&'ast MatchStmt<'ast>),
+    /// `...array` passes the elements of the array as arguments, it only appears in the arguments
+    /// of a call
+    Spread(&'ast Spread<'ast>),
 }
 
 impl Expr<'_> {
@@ -112,15 +233,27 @@ impl Expr<'_> {
             Expr::BinaryOp(binary) => binary.span,
             Expr::Ident(Ident { span, .. }) => *span,
             Expr::Call(call) => call.span,
+            Expr::If(if_stmt) => if_stmt.span,
+            Expr::Block(block) => block.span,
+            Expr::Match(match_stmt) => match_stmt.span,
+            Expr::Spread(spread) => spread.span,
         }
     }
 }
 
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
+pub struct Spread<'ast> {
+    pub span: Span,
+    pub expr: Expr<'ast>,
+}
+
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 pub enum Literal<'ast> {
     String(Symbol, Span),
     Number(f64, Span),
+    Int(i64, Span),
     Array(&'ast [Expr<'ast>], Span),
     Object(Span),
     Boolean(bool, Span),
@@ -132,6 +265,7 @@ impl Literal<'_> {
         match self {
             Literal::String(_, span)
             | Literal::Number(_, span)
+            | Literal::Int(_, span)
             | Literal::Array(_, span)
             | Literal::Object(span)
             | Literal::Boolean(_, span)
@@ -164,7 +298,7 @@ pub struct BinaryOp<'ast> {
     pub kind: BinaryOpKind,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 #[cfg_attr(feature = "_debug", derive(dbg_pls::DebugPls))]
 pub enum BinaryOpKind {
     And,
@@ -179,7 +313,16 @@ pub enum BinaryOpKind {
     Sub,
     Mul,
     Div,
+    /// `a // b`, rounds towards negative infinity
+    IntDiv,
     Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    /// `a ?? b`, `b` is only evaluated if `a` is `null`
+    Coalesce,
 }
 
 #[derive(Debug, PartialEq)]
@@ -188,6 +331,9 @@ pub struct Call<'ast> {
     pub callee: Expr<'ast>,
     pub span: Span,
     pub kind: CallKind<'ast>,
+    /// `a?.b` and `a?.(args)`, if the callee is `null`, the whole chain of calls and fields
+    /// after it is skipped and evaluates to `null`
+    pub null_safe: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -196,3 +342,20 @@ pub enum CallKind<'ast> {
     Field(Ident),
     Fn(&'ast [Expr<'ast>]),
 }
+
+impl<'ast> Call<'ast> {
+    /// For a method call `receiver.method(args)`, returns the receiver, the method and the args
+    pub fn method_call(&self) -> Option<(&Expr<'ast>, Ident, &'ast [Expr<'ast>])> {
+        match (&self.kind, &self.callee) {
+            (
+                CallKind::Fn(args),
+                Expr::Call(Call {
+                    callee: receiver,
+                    kind: CallKind::Field(method),
+                    ..
+                }),
+            ) => Some((receiver, *method, args)),
+            _ => None,
+        }
+    }
+}
@@ -38,6 +38,7 @@ impl Token {
 pub enum TokenKind {
     // keywords
     Let,
+    Const,
     Print,
     Fn,
     If,
@@ -47,6 +48,7 @@ pub enum TokenKind {
     For,
     Break,
     Return,
+    Match,
     True,
     False,
     Null,
@@ -56,6 +58,7 @@ pub enum TokenKind {
     // literals
     String(Symbol),
     Number(f64),
+    Int(i64),
     // ident
     Ident(Symbol),
     // punctuation
@@ -69,6 +72,8 @@ pub enum TokenKind {
     Asterisk,
     /// /
     Slash,
+    /// //
+    SlashSlash,
     /// %
     Percent,
     /// {
@@ -87,8 +92,32 @@ pub enum TokenKind {
     Dot,
     /// ,
     Comma,
+    /// :
+    Colon,
     // =
     Equal,
+    /// +=
+    PlusEqual,
+    /// -=
+    MinusEqual,
+    /// *=
+    AsteriskEqual,
+    /// /=
+    SlashEqual,
+    /// %=
+    PercentEqual,
+    /// //=
+    SlashSlashEqual,
+    /// &=
+    AmpersandEqual,
+    /// |=
+    PipeEqual,
+    /// ^=
+    CaretEqual,
+    /// <<=
+    LessLessEqual,
+    /// >>=
+    GreaterGreaterEqual,
     /// ==
     EqualEqual,
     /// !=
@@ -101,6 +130,24 @@ pub enum TokenKind {
     GreaterEqual,
     /// <=
     LessEqual,
+    /// =>
+    FatArrow,
+    /// |
+    Pipe,
+    /// &
+    Ampersand,
+    /// ^
+    Caret,
+    /// <<
+    LessLess,
+    /// >>
+    GreaterGreater,
+    /// ?.
+    QuestionDot,
+    /// ??
+    QuestionQuestion,
+    /// ...
+    DotDotDot,
 
     /// An error occurred. It's boxed to save space, since `CompilerError` is > 6 `usize` big
     Error(Box<CompilerError>),
@@ -123,9 +170,7 @@ impl<'code, 'gc> Lexer<'code, 'gc> {
     }
 
     fn expect(&mut self, expected: char) -> bool {
-        self.code
-            .peek()
-            .map_or(false, |(_, char)| *char == expected)
+        self.code.peek().is_some_and(|(_, char)| *char == expected)
     }
 
     fn maybe_next_char(
@@ -149,10 +194,22 @@ impl<'code, 'gc> Lexer<'code, 'gc> {
         }
     }
 
+    /// The operator whose second character was just consumed, like `<<`, or its compound
+    /// assignment like `<<=` if an `=` follows
+    fn double_char_op(&mut self, op: TokenKind, assign_op: TokenKind, start: usize) -> Token {
+        if self.expect('=') {
+            let _ = self.code.next(); // consume =
+            Token::new(Span::start_len(start, 3), assign_op)
+        } else {
+            Token::new(Span::start_len(start, 2), op)
+        }
+    }
+
     fn keyword_or_ident(&mut self, name: &str) -> TokenKind {
         match name {
             "loop" => TokenKind::Loop,
             "let" => TokenKind::Let,
+            "const" => TokenKind::Const,
             "fn" => TokenKind::Fn,
             "for" => TokenKind::For,
             "false" => TokenKind::False,
@@ -161,6 +218,7 @@ impl<'code, 'gc> Lexer<'code, 'gc> {
             "while" => TokenKind::While,
             "break" => TokenKind::Break,
             "return" => TokenKind::Return,
+            "match" => TokenKind::Match,
             "true" => TokenKind::True,
             "null" => TokenKind::Null,
             "not" => TokenKind::Not,
@@ -200,20 +258,99 @@ impl<'code, 'gc> Iterator for Lexer<'code, 'gc> {
                     }
                 }
                 ';' => break Token::single_span(start, TokenKind::Semi),
-                '+' => break Token::single_span(start, TokenKind::Plus),
-                '-' => break Token::single_span(start, TokenKind::Minus),
-                '*' => break Token::single_span(start, TokenKind::Asterisk),
-                '/' => break Token::single_span(start, TokenKind::Slash),
-                '%' => break Token::single_span(start, TokenKind::Percent),
+                '+' => {
+                    break self.maybe_next_char('=', TokenKind::PlusEqual, TokenKind::Plus, start);
+                }
+                '-' => {
+                    break self.maybe_next_char(
+                        '=',
+                        TokenKind::MinusEqual,
+                        TokenKind::Minus,
+                        start,
+                    );
+                }
+                '*' => {
+                    break self.maybe_next_char(
+                        '=',
+                        TokenKind::AsteriskEqual,
+                        TokenKind::Asterisk,
+                        start,
+                    );
+                }
+                '/' => {
+                    if self.expect('/') {
+                        let _ = self.code.next(); // consume the second /
+                        break self.double_char_op(
+                            TokenKind::SlashSlash,
+                            TokenKind::SlashSlashEqual,
+                            start,
+                        );
+                    }
+                    break self.maybe_next_char(
+                        '=',
+                        TokenKind::SlashEqual,
+                        TokenKind::Slash,
+                        start,
+                    );
+                }
+                '%' => {
+                    break self.maybe_next_char(
+                        '=',
+                        TokenKind::PercentEqual,
+                        TokenKind::Percent,
+                        start,
+                    );
+                }
                 '{' => break Token::single_span(start, TokenKind::BraceO),
                 '}' => break Token::single_span(start, TokenKind::BraceC),
                 '[' => break Token::single_span(start, TokenKind::BracketO),
                 ']' => break Token::single_span(start, TokenKind::BracketC),
                 '(' => break Token::single_span(start, TokenKind::ParenO),
                 ')' => break Token::single_span(start, TokenKind::ParenC),
-                '.' => break Token::single_span(start, TokenKind::Dot),
+                '.' => {
+                    if !self.expect('.') {
+                        break Token::single_span(start, TokenKind::Dot);
+                    }
+                    let _ = self.code.next(); // consume the second .
+                    if !self.expect('.') {
+                        break Token::new(
+                            Span::start_len(start, 2),
+                            TokenKind::Error(Box::new(CompilerError::with_note(
+                                Span::start_len(start, 2),
+                                "Expected '...'".to_string(),
+                                "Use `...` for rest parameters and spread arguments".to_string(),
+                            ))),
+                        );
+                    }
+                    let _ = self.code.next(); // consume the third .
+                    break Token::new(Span::start_len(start, 3), TokenKind::DotDotDot);
+                }
                 ',' => break Token::single_span(start, TokenKind::Comma),
+                '|' => {
+                    break self.maybe_next_char('=', TokenKind::PipeEqual, TokenKind::Pipe, start);
+                }
+                '&' => {
+                    break self.maybe_next_char(
+                        '=',
+                        TokenKind::AmpersandEqual,
+                        TokenKind::Ampersand,
+                        start,
+                    );
+                }
+                '^' => {
+                    break self.maybe_next_char(
+                        '=',
+                        TokenKind::CaretEqual,
+                        TokenKind::Caret,
+                        start,
+                    );
+                }
+                ':' => break Token::single_span(start, TokenKind::Colon),
                 '=' => {
+                    if self.expect('>') {
+                        let _ = self.code.next(); // consume >
+                        break Token::new(Span::start_len(start, 2), TokenKind::FatArrow);
+                    }
                     break self.maybe_next_char(
                         '=',
                         TokenKind::EqualEqual,
@@ -221,6 +358,25 @@ impl<'code, 'gc> Iterator for Lexer<'code, 'gc> {
                         start,
                     );
                 }
+                '?' => {
+                    let kind = if self.expect('.') {
+                        TokenKind::QuestionDot
+                    } else if self.expect('?') {
+                        TokenKind::QuestionQuestion
+                    } else {
+                        break Token::new(
+                            Span::single(start),
+                            TokenKind::Error(Box::new(CompilerError::with_note(
+                                Span::single(start),
+                                "Expected '.' or '?' after '?'".to_string(),
+                                "Use `?.` for fields that may be null and `??` for defaults"
+                                    .to_string(),
+                            ))),
+                        );
+                    };
+                    let _ = self.code.next(); // consume . or ?
+                    break Token::new(Span::start_len(start, 2), kind);
+                }
                 '!' => {
                     break if self.expect('=') {
                         let _ = self.code.next(); // consume =;
@@ -237,6 +393,14 @@ impl<'code, 'gc> Iterator for Lexer<'code, 'gc> {
                     };
                 }
                 '>' => {
+                    if self.expect('>') {
+                        let _ = self.code.next(); // consume the second >
+                        break self.double_char_op(
+                            TokenKind::GreaterGreater,
+                            TokenKind::GreaterGreaterEqual,
+                            start,
+                        );
+                    }
                     break self.maybe_next_char(
                         '=',
                         TokenKind::GreaterEqual,
@@ -245,6 +409,14 @@ impl<'code, 'gc> Iterator for Lexer<'code, 'gc> {
                     );
                 }
                 '<' => {
+                    if self.expect('<') {
+                        let _ = self.code.next(); // consume the second <
+                        break self.double_char_op(
+                            TokenKind::LessLess,
+                            TokenKind::LessLessEqual,
+                            start,
+                        );
+                    }
                     break self.maybe_next_char('=', TokenKind::LessEqual, TokenKind::Less, start);
                 }
                 '"' => {
@@ -300,6 +472,17 @@ impl<'code, 'gc> Iterator for Lexer<'code, 'gc> {
                         };
                         let number_str = &num_buffer;
                         let span = Span::start_end(start, end);
+                        if !had_dot {
+                            break match number_str.parse::<i64>() {
+                                Ok(int) => Token::new(span, TokenKind::Int(int)),
+                                Err(_) => Token::new(span, TokenKind::Error(Box::new(CompilerError::with_note(
+                                    span,
+                                    "Integer literal too large".to_string(),
+                                    "An integer literal cannot be larger than a 64 bit integer, add `.0` to make it a float"
+                                        .to_string(),
+                                )))),
+                            };
+                        }
                         let number = number_str.parse::<f64>();
                         break match number {
                             Ok(number) if number.is_infinite() => {
@@ -406,6 +589,31 @@ mod test {
         );
     }
 
+    #[test]
+    fn compound_assignment() {
+        lex_test!("+= -= *= /= %= + = - =");
+    }
+
+    #[test]
+    fn integer_compound_assignment() {
+        lex_test!("//= &= |= ^= <<= >>= // / = >> >= << <=");
+    }
+
+    #[test]
+    fn match_arms() {
+        lex_test!("match x { 1 | 2 => {} { a: _ } => {} } ==> = >");
+    }
+
+    #[test]
+    fn null_safe_operators() {
+        lex_test!("a?.b?.(c) ?? d ??? ?");
+    }
+
+    #[test]
+    fn spread_dots() {
+        lex_test!("f(...args) .. a.b ....");
+    }
+
     #[test]
     fn fancy_stuff() {
         lex_test!(". ,- * -, .");
@@ -472,6 +680,16 @@ pls :) o(*￣▽￣*)ブ
         lex_test!("123456789, 123456789.1234, 64785903");
     }
 
+    #[test]
+    fn int_and_float_literals() {
+        lex_test!("1 1.0 2. 0.5 9223372036854775807 9223372036854775808 9223372036854775808.0");
+    }
+
+    #[test]
+    fn integer_operators() {
+        lex_test!("a // b / c & d | e ^ f << g >> h <<= >>=");
+    }
+
     #[test]
     fn string() {
         lex_test!(r#""uwu""#);
@@ -484,7 +702,7 @@ pls :) o(*￣▽￣*)ブ
 
     #[test]
     fn keywords() {
-        lex_test!("let fn if else loop while break for true false null and not or print");
+        lex_test!("let fn if else loop while break for true false null and not or print const");
     }
 
     #[test]
@@ -26,11 +26,14 @@ where
     inside_fn_depth: usize,
     inside_loop_depth: usize,
     bump: &'ast Bump,
+    warnings: std::vec::Vec<CompilerError>,
 }
 
+/// Parses the program. Warnings are added to `warnings`, even if parsing fails later.
 pub fn parse<'lexer, 'ast>(
     tokens: impl Iterator<Item = Token> + 'lexer,
     ast_bump: &'ast Bump,
+    warnings: &mut std::vec::Vec<CompilerError>,
 ) -> Result<Program<'ast>, CompilerError> {
     let mut parser = Parser {
         tokens: tokens.peekable(),
@@ -38,13 +41,23 @@ pub fn parse<'lexer, 'ast>(
         inside_fn_depth: 0,
         inside_loop_depth: 0,
         bump: ast_bump,
+        warnings: std::vec::Vec::new(),
     };
-    let program = parser.program()?;
-    Ok(program)
+    let program = parser.program();
+    warnings.append(&mut parser.warnings);
+    program
 }
 
 type ParseResult<T> = Result<T, CompilerError>;
 
+/// What a block consists of
+#[derive(Debug)]
+enum BlockItem<'ast> {
+    Stmt(Stmt<'ast>),
+    /// An expression without a semicolon at the end of the block, which is its value
+    Value(Expr<'ast>),
+}
+
 macro_rules! parse_bin_op {
     ($self: ident, $lhs: ident, $kind: expr, $function: ident) => {{
         let _ = $self.next();
@@ -81,8 +94,14 @@ where
     const MAX_DEPTH: usize = 100;
 
     fn program(&mut self) -> ParseResult<Program<'ast>> {
+        let (stmts, value) = self.statement_list()?;
+        if value.is_some() {
+            // only blocks have a value, the expression is followed by a stray `}`
+            let _ = self.expect(TokenKind::Semi)?;
+        }
         Ok(Block {
-            stmts: self.statement_list()?,
+            stmts,
+            value: None,
             span: Span::dummy(),
         })
     }
@@ -100,14 +119,33 @@ where
         }
     }
 
-    fn statement_list(&mut self) -> ParseResult<&'ast [Stmt<'ast>]> {
+    /// The statements until the next `}`, and the value of the block they are in
+    fn statement_list(&mut self) -> ParseResult<(&'ast [Stmt<'ast>], Option<Expr<'ast>>)> {
         enter_parse!(self);
         let mut stmts = Vec::new_in(self.bump);
         let return_stmts = loop {
             if let Some(TokenKind::BraceC) | None = self.peek_kind() {
-                break Ok(stmts.into_bump_slice());
+                break Ok((stmts.into_bump_slice(), None));
+            }
+            let stmt = match self.statement()? {
+                BlockItem::Stmt(stmt) => stmt,
+                BlockItem::Value(expr) => break Ok((stmts.into_bump_slice(), Some(expr))),
+            };
+
+            // like in Rust, an `if`, `match` or block at the end is the value of the block
+            if let Some(TokenKind::BraceC) = self.peek_kind() {
+                let value = match stmt {
+                    Stmt::If(if_stmt) => Expr::If(self.bump.alloc(if_stmt)),
+                    Stmt::Match(match_stmt) => Expr::Match(self.bump.alloc(match_stmt)),
+                    Stmt::Block(block) => Expr::Block(self.bump.alloc(block)),
+                    stmt => {
+                        stmts.push(stmt);
+                        continue;
+                    }
+                };
+                break Ok((stmts.into_bump_slice(), Some(value)));
             }
-            let stmt = self.statement()?;
+
             stmts.push(stmt);
         };
         exit_parse!(self);
@@ -118,47 +156,70 @@ where
         enter_parse!(self);
 
         let start_span = self.expect(TokenKind::BraceO)?.span;
-        let stmts = self.statement_list()?;
+        let block = self.block_rest(start_span)?;
+
+        exit_parse!(self);
+
+        Ok(block)
+    }
+
+    /// The rest of a block after the `{`
+    fn block_rest(&mut self, start_span: Span) -> ParseResult<Block<'ast>> {
+        enter_parse!(self);
+
+        let (stmts, value) = self.statement_list()?;
         let end_span = self.expect(TokenKind::BraceC)?.span;
 
         exit_parse!(self);
 
         Ok(Block {
             stmts,
+            value,
             span: start_span.extend(end_span),
         })
     }
 
-    fn statement(&mut self) -> ParseResult<Stmt<'ast>> {
+    fn statement(&mut self) -> ParseResult<BlockItem<'ast>> {
         enter_parse!(self);
 
         let stmt = match *self
             .peek_kind()
             .ok_or_else(|| CompilerError::eof("statement"))?
         {
-            TokenKind::Let => self.declaration(),
+            TokenKind::Let | TokenKind::Const => self.declaration(),
             TokenKind::Fn => self.fn_decl(),
             TokenKind::If => Ok(Stmt::If(self.if_stmt()?)),
             TokenKind::Loop => self.loop_stmt(),
             TokenKind::While => self.while_stmt(),
+            TokenKind::Match => Ok(Stmt::Match(self.match_stmt()?)),
             TokenKind::Break => self.break_stmt(),
             TokenKind::Return => self.return_stmt(),
             TokenKind::Print => self.print_stmt(),
             TokenKind::BraceO => Ok(Stmt::Block(self.block()?)),
             _ => {
-                let stmt = self.assignment()?;
-                Ok(stmt)
+                let item = self.assignment()?;
+                exit_parse!(self);
+                return Ok(item);
             }
         };
         exit_parse!(self);
-        stmt
+        stmt.map(BlockItem::Stmt)
     }
 
     fn declaration(&mut self) -> ParseResult<Stmt<'ast>> {
         enter_parse!(self);
 
-        let keyword_span = self.expect(TokenKind::Let)?.span;
-        let name = self.ident()?;
+        // the statement starts with `let` or `const`
+        let keyword = self
+            .next()
+            .ok_or_else(|| CompilerError::eof("declaration"))?;
+        let constant = keyword.kind == TokenKind::Const;
+        let pattern = self.destructuring_pattern()?;
+
+        let mut bindings = std::vec::Vec::new();
+        pattern.bindings(&mut bindings);
+        check_unique_bindings(&bindings, "the pattern")?;
+
         self.expect(TokenKind::Equal)?;
         let init = self.expression()?;
         self.expect(TokenKind::Semi)?;
@@ -166,9 +227,10 @@ where
         exit_parse!(self);
 
         Ok(Stmt::Declaration(Declaration {
-            span: keyword_span.extend(init.span()),
-            name,
+            span: keyword.span.extend(init.span()),
+            pattern,
             init,
+            constant,
         }))
     }
 
@@ -176,9 +238,26 @@ where
         enter_parse!(self);
 
         let keyword_span = self.expect(TokenKind::Fn)?.span;
-        let name = self.ident()?;
+        let mut name = self.ident()?;
+        let mut object = None;
+        if let Some(TokenKind::Dot) = self.peek_kind() {
+            let _ = self.expect(TokenKind::Dot)?;
+            object = Some(name);
+            name = self.ident()?;
+        }
         let args = self.fn_args()?;
 
+        let starts_with_self = matches!(
+            args.first(),
+            Some(Param { pattern: Pattern::Binding(name), rest: false, .. }) if name.sym.as_str() == "self"
+        );
+        if object.is_some() && !starts_with_self {
+            return Err(CompilerError::new(
+                keyword_span.extend(name.span),
+                "the first parameter of a method must be `self`".to_string(),
+            ));
+        }
+
         self.inside_fn_depth += 1;
         let body = self.block()?;
         self.inside_fn_depth -= 1;
@@ -187,24 +266,87 @@ where
 
         Ok(Stmt::FnDecl(FnDecl {
             span: keyword_span.extend(body.span),
+            object,
             name,
             params: args,
             body,
         }))
     }
 
-    fn fn_args(&mut self) -> ParseResult<&'ast [Ident]> {
+    fn fn_args(&mut self) -> ParseResult<&'ast [Param<'ast>]> {
         enter_parse!(self);
 
         self.expect(TokenKind::ParenO)?;
-        let params = self.parse_list(TokenKind::ParenC, Self::ident)?;
+        let params = self.parse_list(TokenKind::ParenC, Self::param)?;
         self.expect(TokenKind::ParenC)?;
 
+        // a missing argument takes the default, so the arguments after it must have one as well
+        let mut has_default = false;
+        for (i, param) in params.iter().enumerate() {
+            if param.rest && i != params.len() - 1 {
+                return Err(CompilerError::new(
+                    param.span,
+                    "the rest parameter must be the last parameter".to_string(),
+                ));
+            }
+            if param.default.is_some() {
+                has_default = true;
+            } else if has_default && !param.rest {
+                return Err(CompilerError::with_note(
+                    param.span,
+                    "parameters after a parameter with a default need a default as well"
+                        .to_string(),
+                    "give it a default, like `= null`".to_string(),
+                ));
+            }
+        }
+
+        let mut bindings = std::vec::Vec::new();
+        for param in params.iter() {
+            param.pattern.bindings(&mut bindings);
+        }
+        check_unique_bindings(&bindings, "the parameters")?;
+
         exit_parse!(self);
 
         Ok(params)
     }
 
+    fn param(&mut self) -> ParseResult<Param<'ast>> {
+        enter_parse!(self);
+
+        let param = if let Some(TokenKind::DotDotDot) = self.peek_kind() {
+            let dots_span = self.expect(TokenKind::DotDotDot)?.span;
+            let pattern = self.destructuring_pattern()?;
+            Param {
+                span: dots_span.extend(pattern.span()),
+                pattern,
+                default: None,
+                rest: true,
+            }
+        } else {
+            let pattern = self.destructuring_pattern()?;
+            let default = if let Some(TokenKind::Equal) = self.peek_kind() {
+                let _ = self.expect(TokenKind::Equal)?;
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            Param {
+                span: pattern
+                    .span()
+                    .option_extend(default.as_ref().map(Expr::span)),
+                pattern,
+                default,
+                rest: false,
+            }
+        };
+
+        exit_parse!(self);
+
+        Ok(param)
+    }
+
     fn if_stmt(&mut self) -> ParseResult<IfStmt<'ast>> {
         enter_parse!(self);
 
@@ -285,6 +427,262 @@ where
         }))
     }
 
+    fn match_stmt(&mut self) -> ParseResult<MatchStmt<'ast>> {
+        enter_parse!(self);
+
+        let keyword_span = self.expect(TokenKind::Match)?.span;
+        let scrutinee = self.expression()?;
+        self.expect(TokenKind::BraceO)?;
+
+        let mut arms = Vec::new_in(self.bump);
+        // the first arm that matches every value, all arms after it are never taken
+        let mut catch_all = false;
+        while !matches!(self.peek_kind(), Some(TokenKind::BraceC) | None) {
+            let arm = self.match_arm()?;
+
+            if catch_all {
+                self.warnings.push(CompilerError::with_note(
+                    arm.pattern.span(),
+                    "unreachable match arm".to_string(),
+                    "an earlier arm without a guard already matches every value".to_string(),
+                ));
+            }
+            catch_all |= arm.guard.is_none() && arm.pattern.is_irrefutable();
+
+            arms.push(arm);
+
+            // the comma after a block is optional, `match_arm` makes sure expressions end with one
+            if let Some(TokenKind::Comma) = self.peek_kind() {
+                let _ = self.next();
+            }
+        }
+
+        let close_span = self.expect(TokenKind::BraceC)?.span;
+
+        exit_parse!(self);
+
+        Ok(MatchStmt {
+            span: keyword_span.extend(close_span),
+            scrutinee,
+            arms: arms.into_bump_slice(),
+        })
+    }
+
+    fn match_arm(&mut self) -> ParseResult<MatchArm<'ast>> {
+        enter_parse!(self);
+
+        let pattern = self.pattern()?;
+
+        let mut bindings = std::vec::Vec::new();
+        pattern.bindings(&mut bindings);
+        check_unique_bindings(&bindings, "the pattern")?;
+
+        let guard = if let Some(TokenKind::If) = self.peek_kind() {
+            let _ = self.next();
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.expect(TokenKind::FatArrow)?;
+        let body = if let Some(TokenKind::BraceO) = self.peek_kind() {
+            self.block()?
+        } else {
+            // `pattern => expr` is the same as `pattern => { expr }`
+            let value = self.expression()?;
+            if !matches!(self.peek_kind(), Some(TokenKind::Comma | TokenKind::BraceC)) {
+                self.expect(TokenKind::Comma)?;
+            }
+            Block {
+                stmts: &[],
+                span: value.span(),
+                value: Some(value),
+            }
+        };
+
+        exit_parse!(self);
+
+        Ok(MatchArm {
+            span: pattern.span().extend(body.span),
+            pattern,
+            guard,
+            body,
+        })
+    }
+
+    fn pattern(&mut self) -> ParseResult<Pattern<'ast>> {
+        enter_parse!(self);
+
+        let first = self.single_pattern()?;
+
+        let pattern = if let Some(TokenKind::Pipe) = self.peek_kind() {
+            let mut patterns = Vec::new_in(self.bump);
+            patterns.push(first);
+            while let Some(TokenKind::Pipe) = self.peek_kind() {
+                let _ = self.next();
+                patterns.push(self.single_pattern()?);
+            }
+
+            // every alternative would have to bind the same names, so it's easier to forbid it
+            let mut bindings = std::vec::Vec::new();
+            for pattern in patterns.iter() {
+                pattern.bindings(&mut bindings);
+            }
+            if let Some(binding) = bindings.first() {
+                return Err(CompilerError::with_note(
+                    binding.span,
+                    "`|` patterns can't bind variables".to_string(),
+                    "use a separate arm for every alternative instead".to_string(),
+                ));
+            }
+
+            let span = patterns[0].span().extend(patterns.last().unwrap().span());
+            Pattern::Or(patterns.into_bump_slice(), span)
+        } else {
+            first
+        };
+
+        exit_parse!(self);
+        Ok(pattern)
+    }
+
+    /// A pattern of `let` or a parameter, which has to match every value of the right shape, since
+    /// there is no other arm to take when it doesn't
+    fn destructuring_pattern(&mut self) -> ParseResult<Pattern<'ast>> {
+        enter_parse!(self);
+
+        let pattern = self.pattern()?;
+        check_destructuring(&pattern)?;
+
+        exit_parse!(self);
+        Ok(pattern)
+    }
+
+    /// A pattern without `|`
+    fn single_pattern(&mut self) -> ParseResult<Pattern<'ast>> {
+        enter_parse!(self);
+
+        let next = self.next().ok_or_else(|| CompilerError::eof("pattern"))?;
+        let pattern = match next.kind {
+            TokenKind::String(literal) => Pattern::Literal(Literal::String(literal, next.span)),
+            TokenKind::Number(literal) => Pattern::Literal(Literal::Number(literal, next.span)),
+            TokenKind::Int(literal) => Pattern::Literal(Literal::Int(literal, next.span)),
+            TokenKind::Minus => {
+                let number = self.next();
+                match number {
+                    Some(Token {
+                        kind: TokenKind::Number(literal),
+                        span,
+                    }) => Pattern::Literal(Literal::Number(-literal, next.span.extend(span))),
+                    Some(Token {
+                        kind: TokenKind::Int(literal),
+                        span,
+                    }) => Pattern::Literal(Literal::Int(-literal, next.span.extend(span))),
+                    _ => {
+                        return Err(CompilerError::new(
+                            next.span,
+                            "expected a number after `-` in pattern".to_string(),
+                        ))
+                    }
+                }
+            }
+            TokenKind::False => Pattern::Literal(Literal::Boolean(false, next.span)),
+            TokenKind::True => Pattern::Literal(Literal::Boolean(true, next.span)),
+            TokenKind::Null => Pattern::Literal(Literal::Null(next.span)),
+            TokenKind::Ident(sym) if sym.as_str() == "_" => Pattern::Wildcard(next.span),
+            TokenKind::Ident(sym) => Pattern::Binding(Ident {
+                sym,
+                span: next.span,
+            }),
+            TokenKind::BracketO => self.array_pattern(next.span)?,
+            TokenKind::BraceO => {
+                let fields = self.parse_list(TokenKind::BraceC, Self::field_pattern)?;
+                let close_span = self.expect(TokenKind::BraceC)?.span;
+                let span = next.span.extend(close_span);
+                // the fields are what tells objects apart from other values
+                if fields.is_empty() {
+                    return Err(CompilerError::new(
+                        span,
+                        "object patterns need at least one field".to_string(),
+                    ));
+                }
+                Pattern::Object(fields, span)
+            }
+            TokenKind::Error(error) => return Err(*error),
+            _ => {
+                return Err(CompilerError::new(
+                    next.span,
+                    format!("invalid token in pattern: `{:?}`", next.kind),
+                ))
+            }
+        };
+
+        exit_parse!(self);
+        Ok(pattern)
+    }
+
+    /// The rest of an array pattern after the `[`
+    fn array_pattern(&mut self, open_span: Span) -> ParseResult<Pattern<'ast>> {
+        enter_parse!(self);
+
+        let mut elements = Vec::new_in(self.bump);
+        let mut rest = None;
+
+        loop {
+            match self.peek_kind() {
+                Some(TokenKind::BracketC) | None => break,
+                Some(TokenKind::DotDotDot) => {
+                    let dots_span = self.expect(TokenKind::DotDotDot)?.span;
+                    let pattern = self.single_pattern()?;
+                    if let Some(TokenKind::Comma) = self.peek_kind() {
+                        let _ = self.next();
+                    }
+                    // the rest pattern takes all remaining elements
+                    if !matches!(self.peek_kind(), Some(TokenKind::BracketC) | None) {
+                        return Err(CompilerError::new(
+                            dots_span.extend(pattern.span()),
+                            "the rest pattern must be the last element".to_string(),
+                        ));
+                    }
+                    rest = Some(&*self.bump.alloc(pattern));
+                }
+                Some(_) => {
+                    elements.push(self.pattern()?);
+                    match self.peek_kind() {
+                        Some(TokenKind::Comma) => {
+                            let _ = self.next();
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        }
+
+        let close_span = self.expect(TokenKind::BracketC)?.span;
+
+        exit_parse!(self);
+        Ok(Pattern::Array(
+            elements.into_bump_slice(),
+            rest,
+            open_span.extend(close_span),
+        ))
+    }
+
+    fn field_pattern(&mut self) -> ParseResult<FieldPattern<'ast>> {
+        enter_parse!(self);
+
+        let name = self.ident()?;
+        let pattern = if let Some(TokenKind::Colon) = self.peek_kind() {
+            let _ = self.next();
+            self.pattern()?
+        } else {
+            Pattern::Binding(name)
+        };
+
+        exit_parse!(self);
+        Ok(FieldPattern { name, pattern })
+    }
+
     fn break_stmt(&mut self) -> ParseResult<Stmt<'ast>> {
         enter_parse!(self);
 
@@ -342,27 +740,48 @@ where
         Ok(Stmt::Print(expr, print_span.extend(semi_span)))
     }
 
-    fn assignment(&mut self) -> ParseResult<Stmt<'ast>> {
+    fn assignment(&mut self) -> ParseResult<BlockItem<'ast>> {
         enter_parse!(self);
 
         let expr = self.expression()?;
 
-        let stmt = if let Some(TokenKind::Equal) = self.peek_kind() {
-            let _ = self.expect(TokenKind::Equal)?;
-            let init = self.expression()?;
-            let semi_span = self.expect(TokenKind::Semi)?.span;
-            Ok(Stmt::Assignment(Assignment {
-                span: expr.span().extend(semi_span),
-                lhs: expr,
-                rhs: init,
-            }))
-        } else {
-            let _ = self.expect(TokenKind::Semi)?;
-            Ok(Stmt::Expr(expr))
+        // `x += y` keeps the operation, the compiler evaluates `x` only once
+        let op = match self.peek_kind() {
+            Some(TokenKind::Equal) => None,
+            Some(TokenKind::PlusEqual) => Some(BinaryOpKind::Add),
+            Some(TokenKind::MinusEqual) => Some(BinaryOpKind::Sub),
+            Some(TokenKind::AsteriskEqual) => Some(BinaryOpKind::Mul),
+            Some(TokenKind::SlashEqual) => Some(BinaryOpKind::Div),
+            Some(TokenKind::PercentEqual) => Some(BinaryOpKind::Mod),
+            Some(TokenKind::SlashSlashEqual) => Some(BinaryOpKind::IntDiv),
+            Some(TokenKind::AmpersandEqual) => Some(BinaryOpKind::BitAnd),
+            Some(TokenKind::PipeEqual) => Some(BinaryOpKind::BitOr),
+            Some(TokenKind::CaretEqual) => Some(BinaryOpKind::BitXor),
+            Some(TokenKind::LessLessEqual) => Some(BinaryOpKind::Shl),
+            Some(TokenKind::GreaterGreaterEqual) => Some(BinaryOpKind::Shr),
+            Some(TokenKind::BraceC) => {
+                exit_parse!(self);
+                return Ok(BlockItem::Value(expr));
+            }
+            _ => {
+                let _ = self.expect(TokenKind::Semi)?;
+                exit_parse!(self);
+                return Ok(BlockItem::Stmt(Stmt::Expr(expr)));
+            }
         };
 
+        let _ = self.next();
+        let init = self.expression()?;
+        let semi_span = self.expect(TokenKind::Semi)?.span;
+
         exit_parse!(self);
-        stmt
+
+        Ok(BlockItem::Stmt(Stmt::Assignment(Assignment {
+            span: expr.span().extend(semi_span),
+            lhs: expr,
+            rhs: init,
+            op,
+        })))
     }
 
     fn expression(&mut self) -> ParseResult<Expr<'ast>> {
@@ -378,6 +797,9 @@ where
         let lhs = self.logical_and()?;
         let return_expr = match self.peek_kind() {
             Some(TokenKind::Or) => parse_bin_op!(self, lhs, BinaryOpKind::Or, logical_or),
+            Some(TokenKind::QuestionQuestion) => {
+                parse_bin_op!(self, lhs, BinaryOpKind::Coalesce, logical_or)
+            }
             _ => Ok(lhs),
         };
 
@@ -418,15 +840,15 @@ where
     fn comparison(&mut self) -> ParseResult<Expr<'ast>> {
         enter_parse!(self);
 
-        let lhs = self.term()?;
+        let lhs = self.bit_or()?;
         let return_expr = match self.peek_kind() {
-            Some(TokenKind::Greater) => parse_bin_op!(self, lhs, BinaryOpKind::Greater, term),
+            Some(TokenKind::Greater) => parse_bin_op!(self, lhs, BinaryOpKind::Greater, bit_or),
             Some(TokenKind::GreaterEqual) => {
-                parse_bin_op!(self, lhs, BinaryOpKind::GreaterEqual, term)
+                parse_bin_op!(self, lhs, BinaryOpKind::GreaterEqual, bit_or)
             }
-            Some(TokenKind::Less) => parse_bin_op!(self, lhs, BinaryOpKind::Less, term),
+            Some(TokenKind::Less) => parse_bin_op!(self, lhs, BinaryOpKind::Less, bit_or),
             Some(TokenKind::LessEqual) => {
-                parse_bin_op!(self, lhs, BinaryOpKind::LessEqual, term)
+                parse_bin_op!(self, lhs, BinaryOpKind::LessEqual, bit_or)
             }
             _ => Ok(lhs),
         };
@@ -434,15 +856,59 @@ where
         return_expr
     }
 
+    fn bit_or(&mut self) -> ParseResult<Expr<'ast>> {
+        enter_parse!(self);
+
+        let return_expr = self.left_assoc_bin_op(Self::bit_xor, |kind| match kind {
+            TokenKind::Pipe => Some(BinaryOpKind::BitOr),
+            _ => None,
+        });
+        exit_parse!(self);
+        return_expr
+    }
+
+    fn bit_xor(&mut self) -> ParseResult<Expr<'ast>> {
+        enter_parse!(self);
+
+        let return_expr = self.left_assoc_bin_op(Self::bit_and, |kind| match kind {
+            TokenKind::Caret => Some(BinaryOpKind::BitXor),
+            _ => None,
+        });
+        exit_parse!(self);
+        return_expr
+    }
+
+    fn bit_and(&mut self) -> ParseResult<Expr<'ast>> {
+        enter_parse!(self);
+
+        let return_expr = self.left_assoc_bin_op(Self::shift, |kind| match kind {
+            TokenKind::Ampersand => Some(BinaryOpKind::BitAnd),
+            _ => None,
+        });
+        exit_parse!(self);
+        return_expr
+    }
+
+    fn shift(&mut self) -> ParseResult<Expr<'ast>> {
+        enter_parse!(self);
+
+        let return_expr = self.left_assoc_bin_op(Self::term, |kind| match kind {
+            TokenKind::LessLess => Some(BinaryOpKind::Shl),
+            TokenKind::GreaterGreater => Some(BinaryOpKind::Shr),
+            _ => None,
+        });
+        exit_parse!(self);
+        return_expr
+    }
+
     fn term(&mut self) -> ParseResult<Expr<'ast>> {
         enter_parse!(self);
 
-        let lhs = self.factor()?;
-        let return_expr = match self.peek_kind() {
-            Some(TokenKind::Plus) => parse_bin_op!(self, lhs, BinaryOpKind::Add, term),
-            Some(TokenKind::Minus) => parse_bin_op!(self, lhs, BinaryOpKind::Sub, term),
-            _ => Ok(lhs),
-        };
+        let return_expr = self.left_assoc_bin_op(Self::factor, |kind| match kind {
+            TokenKind::Plus => Some(BinaryOpKind::Add),
+            TokenKind::Minus => Some(BinaryOpKind::Sub),
+            _ => None,
+        });
         exit_parse!(self);
         return_expr
     }
@@ -450,17 +916,40 @@ where
     fn factor(&mut self) -> ParseResult<Expr<'ast>> {
         enter_parse!(self);
 
-        let lhs = self.unary()?;
-        let return_expr = match self.peek_kind() {
-            Some(TokenKind::Asterisk) => parse_bin_op!(self, lhs, BinaryOpKind::Mul, factor),
-            Some(TokenKind::Slash) => parse_bin_op!(self, lhs, BinaryOpKind::Div, factor),
-            Some(TokenKind::Percent) => parse_bin_op!(self, lhs, BinaryOpKind::Mod, factor),
-            _ => Ok(lhs),
-        };
+        let return_expr = self.left_assoc_bin_op(Self::unary, |kind| match kind {
+            TokenKind::Asterisk => Some(BinaryOpKind::Mul),
+            TokenKind::Slash => Some(BinaryOpKind::Div),
+            TokenKind::SlashSlash => Some(BinaryOpKind::IntDiv),
+            TokenKind::Percent => Some(BinaryOpKind::Mod),
+            _ => None,
+        });
         exit_parse!(self);
         return_expr
     }
 
+    /// Parses a chain of operands with the operators of one precedence level, so that
+    /// `8 // 2 // 2` is `(8 // 2) // 2`
+    fn left_assoc_bin_op(
+        &mut self,
+        operand: fn(&mut Self) -> ParseResult<Expr<'ast>>,
+        operator: fn(&TokenKind) -> Option<BinaryOpKind>,
+    ) -> ParseResult<Expr<'ast>> {
+        let mut lhs = operand(self)?;
+
+        while let Some(kind) = self.peek_kind().and_then(operator) {
+            let _ = self.next();
+            let rhs = operand(self)?;
+            lhs = Expr::BinaryOp(self.bump.alloc(BinaryOp {
+                span: lhs.span().extend(rhs.span()),
+                lhs,
+                rhs,
+                kind,
+            }));
+        }
+
+        Ok(lhs)
+    }
+
     fn unary(&mut self) -> ParseResult<Expr<'ast>> {
         enter_parse!(self);
 
@@ -495,19 +984,28 @@ where
         let mut expr = self.primary()?;
 
         loop {
+            // the span of the `?.`
+            let null_safe = match self.peek_kind() {
+                Some(TokenKind::QuestionDot) => Some(self.expect(TokenKind::QuestionDot)?.span),
+                Some(TokenKind::ParenO | TokenKind::Dot) => None,
+                _ => break,
+            };
+
             expr = match self.peek_kind() {
                 Some(TokenKind::ParenO) => {
                     let open_span = self.expect(TokenKind::ParenO)?.span;
-                    let args = self.parse_list(TokenKind::ParenC, Self::expression)?;
+                    let args = self.parse_list(TokenKind::ParenC, Self::argument)?;
                     let close_span = self.expect(TokenKind::ParenC)?.span;
 
                     Expr::Call(self.bump.alloc(Call {
                         callee: expr,
                         span: open_span.extend(close_span),
                         kind: CallKind::Fn(args),
+                        null_safe: null_safe.is_some(),
                     }))
                 }
-                Some(TokenKind::Dot) => {
+                // `?.` is directly followed by the name of the field
+                Some(TokenKind::Dot) if null_safe.is_none() => {
                     let dot_span = self.expect(TokenKind::Dot)?.span;
                     let field = self.ident()?;
 
@@ -515,10 +1013,20 @@ where
                         callee: expr,
                         span: dot_span.extend(field.span),
                         kind: CallKind::Field(field),
+                        null_safe: false,
                     }))
                 }
-                _ => break,
-            }
+                _ => {
+                    let field = self.ident()?;
+
+                    Expr::Call(self.bump.alloc(Call {
+                        callee: expr,
+                        span: null_safe.unwrap_or(field.span).extend(field.span),
+                        kind: CallKind::Field(field),
+                        null_safe: true,
+                    }))
+                }
+            };
         }
 
         exit_parse!(self);
@@ -526,17 +1034,60 @@ where
         Ok(expr)
     }
 
+    /// An argument of a call, which can spread an array with `...array`
+    fn argument(&mut self) -> ParseResult<Expr<'ast>> {
+        enter_parse!(self);
+
+        let arg = if let Some(TokenKind::DotDotDot) = self.peek_kind() {
+            let dots_span = self.expect(TokenKind::DotDotDot)?.span;
+            let expr = self.expression()?;
+            Expr::Spread(self.bump.alloc(Spread {
+                span: dots_span.extend(expr.span()),
+                expr,
+            }))
+        } else {
+            self.expression()?
+        };
+
+        exit_parse!(self);
+
+        Ok(arg)
+    }
+
     fn primary(&mut self) -> ParseResult<Expr<'ast>> {
         enter_parse!(self);
 
+        // these parse their keyword themselves
+        match self.peek_kind() {
+            Some(TokenKind::If) => {
+                let if_stmt = self.if_stmt()?;
+                exit_parse!(self);
+                return Ok(Expr::If(self.bump.alloc(if_stmt)));
+            }
+            Some(TokenKind::Match) => {
+                let match_stmt = self.match_stmt()?;
+                exit_parse!(self);
+                return Ok(Expr::Match(self.bump.alloc(match_stmt)));
+            }
+            _ => {}
+        }
+
         let next = self.next().ok_or_else(|| CompilerError::eof("primary"))?;
         let return_expr = match next.kind {
             TokenKind::String(literal) => Ok(Expr::Literal(Literal::String(literal, next.span))),
             TokenKind::Number(literal) => Ok(Expr::Literal(Literal::Number(literal, next.span))),
+            TokenKind::Int(literal) => Ok(Expr::Literal(Literal::Int(literal, next.span))),
             TokenKind::False => Ok(Expr::Literal(Literal::Boolean(false, next.span))),
             TokenKind::True => Ok(Expr::Literal(Literal::Boolean(true, next.span))),
             TokenKind::Null => Ok(Expr::Literal(Literal::Null(next.span))),
-            TokenKind::BraceO => self.object_literal(next.span),
+            // `{}` is an empty object, not an empty block
+            TokenKind::BraceO if self.peek_kind() == Some(&TokenKind::BraceC) => {
+                self.object_literal(next.span)
+            }
+            TokenKind::BraceO => {
+                let block = self.block_rest(next.span)?;
+                Ok(Expr::Block(self.bump.alloc(block)))
+            }
             TokenKind::BracketO => self.array_literal(next.span),
             TokenKind::ParenO => {
                 let expr = self.expression()?;
@@ -674,6 +1225,43 @@ where
     }
 }
 
+/// Errors if a name is bound more than once in `place`
+fn check_unique_bindings(bindings: &[Ident], place: &str) -> ParseResult<()> {
+    for (i, binding) in bindings.iter().enumerate() {
+        if bindings[..i].iter().any(|other| other.sym == binding.sym) {
+            return Err(CompilerError::new(
+                binding.span,
+                format!(
+                    "`{}` is bound more than once in {}",
+                    binding.sym.as_str(),
+                    place
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Errors on the tests of a pattern that only `match` can use, since `let` and parameters only
+/// destructure values
+fn check_destructuring(pattern: &Pattern) -> ParseResult<()> {
+    match pattern {
+        Pattern::Binding(_) | Pattern::Wildcard(_) => Ok(()),
+        Pattern::Array(elements, rest, _) => elements
+            .iter()
+            .chain(*rest)
+            .try_for_each(check_destructuring),
+        Pattern::Object(fields, _) => fields
+            .iter()
+            .try_for_each(|field| check_destructuring(&field.pattern)),
+        Pattern::Literal(_) | Pattern::Or(..) => Err(CompilerError::with_note(
+            pattern.span(),
+            "this pattern can only be used in `match`".to_string(),
+            "`let` and parameters can only destructure arrays and objects".to_string(),
+        )),
+    }
+}
+
 impl CompilerError {
     fn eof(message: &str) -> Self {
         Self {
@@ -681,6 +1269,7 @@ impl CompilerError {
             span: Span::dummy(),
             message: format!("reached EOF while parsing `{}`", message),
             note: None,
+            note_span: None,
         }
     }
 }
@@ -6,10 +6,14 @@
 use bumpalo::Bump;
 use prelude::*;
 
-use crate::{errors::Span, parse::Parser, Lexer, RtAlloc};
+use crate::{
+    errors::Span,
+    parse::{BlockItem, Parser},
+    Lexer, RtAlloc,
+};
 
 mod prelude {
-    pub(super) use super::{parser, rt, test_literal_bin_op, test_number_literal, token};
+    pub(super) use super::{parser, rt, token, BlockItem};
     pub(super) use crate::{
         lex::TokenKind::*,
         syntax::ast::{Expr, Stmt},
@@ -31,7 +35,7 @@ fn rt() -> RtAlloc {
     unsafe { RtAlloc::new() }
 }
 
-fn parser(tokens: Vec<Token>, alloc: &Bump) -> Parser<std::vec::IntoIter<Token>>
+fn parser(tokens: Vec<Token>, alloc: &Bump) -> Parser<'_, std::vec::IntoIter<Token>>
 where {
     Parser {
         tokens: tokens.into_iter().peekable(),
@@ -39,6 +43,7 @@ where {
         inside_fn_depth: 0,
         inside_loop_depth: 0,
         bump: alloc,
+        warnings: Vec::new(),
     }
 }
 
@@ -52,7 +57,6 @@ macro_rules! test_literal_bin_op {
         insta::assert_debug_snapshot!(ast);
     };
 }
-use test_literal_bin_op;
 
 macro_rules! test_number_literal {
     ($parser:expr) => {
@@ -63,7 +67,6 @@ macro_rules! test_number_literal {
         insta::assert_debug_snapshot!(ast);
     };
 }
-use test_number_literal;
 
 mod assignment {
     use bumpalo::Bump;
@@ -71,9 +74,12 @@ mod assignment {
     use super::prelude::*;
     use crate::parse::test::rt;
 
-    fn parse_assignment(tokens: Vec<Token>, alloc: &Bump) -> Stmt {
+    fn parse_assignment(tokens: Vec<Token>, alloc: &Bump) -> Stmt<'_> {
         let mut parser = parser(tokens, alloc);
-        parser.assignment().unwrap()
+        match parser.assignment().unwrap() {
+            BlockItem::Stmt(stmt) => stmt,
+            BlockItem::Value(expr) => panic!("expected a statement, got the block value {expr:?}"),
+        }
     }
 
     #[test]
@@ -108,12 +114,119 @@ mod assignment {
         let ast = parse_assignment(tokens, &alloc);
         insta::assert_debug_snapshot!(ast);
     }
+
+    #[test]
+    fn compound() {
+        let mut rt = rt();
+        let tokens = [
+            Ident(rt.intern_string("hugo")),
+            Dot,
+            Ident(rt.intern_string("age")),
+            PlusEqual,
+            Number(1.0),
+            Semi,
+        ]
+        .map(token)
+        .into();
+
+        let alloc = Bump::new();
+        let ast = parse_assignment(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+}
+
+mod declaration {
+    use super::prelude::*;
+
+    fn parse_declaration(tokens: Vec<Token>, alloc: &Bump) -> Stmt<'_> {
+        let mut parser = parser(tokens, alloc);
+        parser.declaration().unwrap()
+    }
+
+    #[test]
+    fn simple() {
+        let mut rt = rt();
+        let tokens = [
+            Let,
+            Ident(rt.intern_string("hugo")),
+            Equal,
+            Number(10.0),
+            Semi,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_declaration(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn constant() {
+        let mut rt = rt();
+        let tokens = [
+            Const,
+            Ident(rt.intern_string("LIMIT")),
+            Equal,
+            Number(3.0),
+            Semi,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_declaration(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn array_with_rest() {
+        let mut rt = rt();
+        let tokens = [
+            Let,
+            BracketO,
+            Ident(rt.intern_string("a")),
+            Comma,
+            DotDotDot,
+            Ident(rt.intern_string("rest")),
+            BracketC,
+            Equal,
+            Ident(rt.intern_string("array")),
+            Semi,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_declaration(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn object() {
+        let mut rt = rt();
+        let tokens = [
+            Let,
+            BraceO,
+            Ident(rt.intern_string("name")),
+            Comma,
+            Ident(rt.intern_string("age")),
+            Colon,
+            Ident(rt.intern_string("years")),
+            BraceC,
+            Equal,
+            Ident(rt.intern_string("hugo")),
+            Semi,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_declaration(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
 }
 
 mod r#fn {
     use super::prelude::*;
 
-    fn parse_fn(tokens: Vec<Token>, alloc: &Bump) -> Stmt {
+    fn parse_fn(tokens: Vec<Token>, alloc: &Bump) -> Stmt<'_> {
         let mut parser = parser(tokens, alloc);
         parser.fn_decl().unwrap()
     }
@@ -161,13 +274,60 @@ mod r#fn {
         let ast = parse_fn(tokens, &alloc);
         insta::assert_debug_snapshot!(ast);
     }
+
+    #[test]
+    fn method() {
+        let mut rt = rt();
+        let tokens = [
+            Fn,
+            Ident(rt.intern_string("Point")),
+            Dot,
+            Ident(rt.intern_string("len")),
+            ParenO,
+            Ident(rt.intern_string("self")),
+            ParenC,
+            BraceO,
+            BraceC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_fn(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn default_and_rest_params() {
+        let mut rt = rt();
+        let tokens = [
+            Fn,
+            Ident(rt.intern_string("f")),
+            ParenO,
+            Ident(rt.intern_string("a")),
+            Comma,
+            Ident(rt.intern_string("b")),
+            Equal,
+            Number(2.0),
+            Comma,
+            DotDotDot,
+            Ident(rt.intern_string("rest")),
+            ParenC,
+            BraceO,
+            BraceC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_fn(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
 }
 
 mod r#if {
     use super::prelude::*;
     use crate::syntax::ast::IfStmt;
 
-    fn parse_if(tokens: Vec<Token>, alloc: &Bump) -> IfStmt {
+    fn parse_if(tokens: Vec<Token>, alloc: &Bump) -> IfStmt<'_> {
         let mut parser = parser(tokens, alloc);
         parser.if_stmt().unwrap()
     }
@@ -216,7 +376,7 @@ mod r#if {
 mod print {
     use super::prelude::*;
 
-    fn parse_print(tokens: Vec<Token>, alloc: &Bump) -> Stmt {
+    fn parse_print(tokens: Vec<Token>, alloc: &Bump) -> Stmt<'_> {
         let mut parser = parser(tokens, alloc);
         parser.print_stmt().unwrap()
     }
@@ -233,7 +393,7 @@ mod print {
 mod r#while {
     use super::prelude::*;
 
-    fn parse_while(tokens: Vec<Token>, alloc: &Bump) -> Stmt {
+    fn parse_while(tokens: Vec<Token>, alloc: &Bump) -> Stmt<'_> {
         let mut parser = parser(tokens, alloc);
         parser.while_stmt().unwrap()
     }
@@ -257,10 +417,156 @@ mod r#while {
     }
 }
 
+mod r#match {
+    use super::prelude::*;
+
+    fn parse_match(tokens: Vec<Token>, alloc: &Bump) -> (Stmt<'_>, usize) {
+        let mut parser = parser(tokens, alloc);
+        let stmt = parser.match_stmt().unwrap();
+        (Stmt::Match(stmt), parser.warnings.len())
+    }
+
+    #[test]
+    fn literal_or_and_wildcard() {
+        let mut rt = rt();
+        let tokens = [
+            Match,
+            Ident(rt.intern_string("x")),
+            BraceO,
+            Number(1.0),
+            Pipe,
+            Minus,
+            Number(2.0),
+            FatArrow,
+            BraceO,
+            BraceC,
+            Comma,
+            Ident(rt.intern_string("_")),
+            FatArrow,
+            BraceO,
+            BraceC,
+            BraceC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let (ast, warnings) = parse_match(tokens, &alloc);
+        assert_eq!(warnings, 0);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn nested_patterns_and_guard() {
+        let mut rt = rt();
+        let tokens = [
+            Match,
+            Ident(rt.intern_string("x")),
+            BraceO,
+            BraceO,
+            Ident(rt.intern_string("kind")),
+            Colon,
+            String(rt.intern_string("pair")),
+            Comma,
+            Ident(rt.intern_string("items")),
+            Colon,
+            BracketO,
+            Ident(rt.intern_string("a")),
+            Comma,
+            Ident(rt.intern_string("_")),
+            BracketC,
+            Comma,
+            Ident(rt.intern_string("size")),
+            BraceC,
+            If,
+            Ident(rt.intern_string("a")),
+            FatArrow,
+            BraceO,
+            BraceC,
+            BraceC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let (ast, warnings) = parse_match(tokens, &alloc);
+        assert_eq!(warnings, 0);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn expression_arms() {
+        let mut rt = rt();
+        let tokens = [
+            Match,
+            Ident(rt.intern_string("x")),
+            BraceO,
+            Number(0.0),
+            FatArrow,
+            Number(1.0),
+            Comma,
+            Ident(rt.intern_string("_")),
+            FatArrow,
+            Number(2.0),
+            BraceC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let (ast, warnings) = parse_match(tokens, &alloc);
+        assert_eq!(warnings, 0);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn expression_arm_without_comma() {
+        let mut rt = rt();
+        let tokens = [
+            Match,
+            Ident(rt.intern_string("x")),
+            BraceO,
+            Number(0.0),
+            FatArrow,
+            Number(1.0),
+            Ident(rt.intern_string("_")),
+            FatArrow,
+            Number(2.0),
+            BraceC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let mut parser = parser(tokens, &alloc);
+        assert!(parser.match_stmt().is_err());
+    }
+
+    #[test]
+    fn unreachable_arm() {
+        let mut rt = rt();
+        let tokens = [
+            Match,
+            Ident(rt.intern_string("x")),
+            BraceO,
+            Ident(rt.intern_string("y")),
+            FatArrow,
+            BraceO,
+            BraceC,
+            Null,
+            FatArrow,
+            BraceO,
+            BraceC,
+            BraceC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let (_, warnings) = parse_match(tokens, &alloc);
+        assert_eq!(warnings, 1);
+    }
+}
+
 mod r#loop {
     use super::prelude::*;
 
-    fn parse_loop(tokens: Vec<Token>, alloc: &Bump) -> Stmt {
+    fn parse_loop(tokens: Vec<Token>, alloc: &Bump) -> Stmt<'_> {
         let mut parser = parser(tokens, alloc);
         parser.loop_stmt().unwrap()
     }
@@ -296,7 +602,7 @@ mod block {
     use super::prelude::*;
     use crate::syntax::ast::Block;
 
-    fn parse_block(tokens: Vec<Token>, alloc: &Bump) -> Block {
+    fn parse_block(tokens: Vec<Token>, alloc: &Bump) -> Block<'_> {
         let mut parser = parser(tokens, alloc);
         parser.block().unwrap()
     }
@@ -319,6 +625,38 @@ mod block {
         insta::assert_debug_snapshot!(ast);
     }
 
+    #[test]
+    fn value() {
+        let tokens = [BraceO, Number(10.0), Semi, Number(20.0), BraceC]
+            .map(token)
+            .into();
+        let alloc = Bump::new();
+        let ast = parse_block(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn trailing_if_is_the_value() {
+        let tokens = [
+            BraceO,
+            If,
+            True,
+            BraceO,
+            Number(1.0),
+            BraceC,
+            Else,
+            BraceO,
+            Number(2.0),
+            BraceC,
+            BraceC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_block(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
     #[test]
     fn nested() {
         let tokens = [BraceO, BraceO, BraceC, BraceC].map(token).into();
@@ -331,7 +669,7 @@ mod block {
 mod expr {
     use super::prelude::*;
 
-    fn parse_expr(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_expr(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.expression().unwrap()
     }
@@ -395,7 +733,7 @@ mod expr {
 mod logical_or {
     use super::prelude::*;
 
-    fn parse_logical_or(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_logical_or(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.logical_or().unwrap()
     }
@@ -409,12 +747,17 @@ mod logical_or {
     fn or() {
         test_literal_bin_op!(Or, parse_logical_or);
     }
+
+    #[test]
+    fn coalesce() {
+        test_literal_bin_op!(QuestionQuestion, parse_logical_or);
+    }
 }
 
 mod logical_and {
     use super::prelude::*;
 
-    fn parse_logical_and(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_logical_and(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.logical_and().unwrap()
     }
@@ -433,7 +776,7 @@ mod logical_and {
 mod equality {
     use super::prelude::*;
 
-    fn parse_equality(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_equality(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.equality().unwrap()
     }
@@ -457,7 +800,7 @@ mod equality {
 mod comparison {
     use super::prelude::*;
 
-    fn parse_comparison(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_comparison(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.comparison().unwrap()
     }
@@ -491,7 +834,7 @@ mod comparison {
 mod term {
     use super::prelude::*;
 
-    fn parse_term(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_term(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.term().unwrap()
     }
@@ -515,7 +858,7 @@ mod term {
 mod factor {
     use super::prelude::*;
 
-    fn parse_factor(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_factor(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.factor().unwrap()
     }
@@ -539,12 +882,77 @@ mod factor {
     fn modulo() {
         test_literal_bin_op!(Percent, parse_factor);
     }
+
+    #[test]
+    fn int_divide() {
+        test_literal_bin_op!(SlashSlash, parse_factor);
+    }
+}
+
+mod bitwise {
+    use super::prelude::*;
+
+    fn parse_bit_or(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
+        let mut parser = parser(tokens, alloc);
+        parser.bit_or().unwrap()
+    }
+
+    #[test]
+    fn bit_or() {
+        test_literal_bin_op!(Pipe, parse_bit_or);
+    }
+
+    #[test]
+    fn bit_xor() {
+        test_literal_bin_op!(Caret, parse_bit_or);
+    }
+
+    #[test]
+    fn bit_and() {
+        test_literal_bin_op!(Ampersand, parse_bit_or);
+    }
+
+    #[test]
+    fn shift_left() {
+        test_literal_bin_op!(LessLess, parse_bit_or);
+    }
+
+    #[test]
+    fn shift_right() {
+        test_literal_bin_op!(GreaterGreater, parse_bit_or);
+    }
+
+    #[test]
+    fn precedence() {
+        // 1 | 2 ^ 3 & 4 << 5 + 6 < 7
+        let tokens = [
+            Int(1),
+            Pipe,
+            Int(2),
+            Caret,
+            Int(3),
+            Ampersand,
+            Int(4),
+            LessLess,
+            Int(5),
+            Plus,
+            Int(6),
+            Less,
+            Int(7),
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let mut parser = parser(tokens, &alloc);
+        let ast = parser.expression().unwrap();
+        insta::assert_debug_snapshot!(ast);
+    }
 }
 
 mod unary {
     use super::prelude::*;
 
-    fn parse_unary(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_unary(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.unary().unwrap()
     }
@@ -574,7 +982,7 @@ mod unary {
 mod call {
     use super::prelude::*;
 
-    fn parse_call(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_call(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.call().unwrap()
     }
@@ -624,6 +1032,25 @@ mod call {
         insta::assert_debug_snapshot!(ast);
     }
 
+    #[test]
+    fn spread_args() {
+        let mut rt = rt();
+        let tokens = [
+            Ident(rt.intern_string("print")),
+            ParenO,
+            Number(10.0),
+            Comma,
+            DotDotDot,
+            Ident(rt.intern_string("args")),
+            ParenC,
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_call(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
     #[test]
     fn nested() {
         let mut rt = rt();
@@ -667,12 +1094,34 @@ mod call {
         let ast = parse_call(tokens, &alloc);
         insta::assert_debug_snapshot!(ast);
     }
+
+    #[test]
+    fn null_safe() {
+        let mut rt = rt();
+        // hugo?.name?.(5).age
+        let tokens = [
+            Ident(rt.intern_string("hugo")),
+            QuestionDot,
+            Ident(rt.intern_string("name")),
+            QuestionDot,
+            ParenO,
+            Number(5.0),
+            ParenC,
+            Dot,
+            Ident(rt.intern_string("age")),
+        ]
+        .map(token)
+        .into();
+        let alloc = Bump::new();
+        let ast = parse_call(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
 }
 
 mod primary {
     use super::prelude::*;
 
-    fn parse_primary(tokens: Vec<Token>, alloc: &Bump) -> Expr {
+    fn parse_primary(tokens: Vec<Token>, alloc: &Bump) -> Expr<'_> {
         let mut parser = parser(tokens, alloc);
         parser.primary().unwrap()
     }
@@ -711,6 +1160,22 @@ mod primary {
         insta::assert_debug_snapshot!(ast);
     }
 
+    #[test]
+    fn block() {
+        let tokens = [BraceO, Number(1.0), BraceC].map(token).into();
+        let alloc = Bump::new();
+        let ast = parse_primary(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
+    #[test]
+    fn if_expr() {
+        let tokens = [If, True, BraceO, Number(1.0), BraceC].map(token).into();
+        let alloc = Bump::new();
+        let ast = parse_primary(tokens, &alloc);
+        insta::assert_debug_snapshot!(ast);
+    }
+
     #[test]
     fn empty_array() {
         let tokens = [BracketO, BracketC].map(token).into();
@@ -806,6 +1271,6 @@ fn benchfile_parses() {
     let mut rt_alloc = unsafe { RtAlloc::new() };
     let alloc = Bump::new();
     let lexer = Lexer::new(code, &mut rt_alloc);
-    let program = super::parse(lexer, &alloc);
+    let program = super::parse(lexer, &alloc, &mut Vec::new());
     insta::assert_debug_snapshot!(program);
 }
@@ -6,16 +6,16 @@ macro_rules! assert_size {
     };
 }
 
-use std::fmt::Display;
+use std::{fmt::Display, io::Write};
 
 pub(crate) use assert_size;
 
 #[cfg(feature = "_debug")]
-pub fn dbg(prefix: impl Display, x: impl dbg_pls::DebugPls) {
-    eprintln!("{prefix}{}", dbg_pls::color(&x))
+pub fn dbg(w: &mut dyn Write, prefix: impl Display, x: impl dbg_pls::DebugPls) {
+    let _ = writeln!(w, "{prefix}{}", dbg_pls::color(&x));
 }
 
 #[cfg(not(feature = "_debug"))]
-pub fn dbg(prefix: impl Display, x: impl std::fmt::Debug) {
-    eprintln!("{prefix}{x:#?}");
+pub fn dbg(w: &mut dyn Write, prefix: impl Display, x: impl std::fmt::Debug) {
+    let _ = writeln!(w, "{prefix}{x:#?}");
 }
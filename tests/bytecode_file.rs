@@ -0,0 +1,297 @@
+mod common;
+
+use common::{
+    instr::{push_const, EXIT},
+    value, Output,
+};
+
+fn with_cfg<R>(stdin: &str, f: impl FnOnce(&mut dilaria::Config<'_>) -> R) -> (R, Output) {
+    let mut stdout = Vec::<u8>::new();
+    let mut stderr = Vec::<u8>::new();
+    let mut stdin = stdin.as_bytes();
+    let mut cfg = dilaria::Config {
+        filename: "test.dilc",
+        debug: false,
+        step: false,
+        profile: false,
+        profile_folded: None,
+        coverage: false,
+        coverage_lcov: None,
+        stdout: &mut stdout,
+        stdin: &mut stdin,
+        stderr: &mut stderr,
+        parse_only: false,
+        optimize: false,
+        register: false,
+    };
+
+    let result = f(&mut cfg);
+
+    let output = Output {
+        stdout: String::from_utf8(stdout).unwrap(),
+        stderr: String::from_utf8(stderr).unwrap(),
+    };
+    (result, output)
+}
+
+fn compile(code: &str) -> Vec<u8> {
+    let (bytecode, output) = with_cfg("", |cfg| dilaria::compile_program(code, cfg));
+    assert_eq!(output.stderr, "");
+    bytecode.expect("compilation failed")
+}
+
+fn run(bytecode: &[u8], stdin: &str) -> Output {
+    with_cfg(stdin, |cfg| dilaria::run_bytecode(bytecode, cfg)).1
+}
+
+/// Runs the program from source and from the compiled file, which must behave the same
+macro_rules! round_trip_test {
+    ($name:ident, $code:expr) => {
+        #[test]
+        fn $name() {
+            let code = $code;
+            let bytecode = compile(code);
+            assert!(dilaria::is_bytecode(&bytecode));
+
+            let from_file = run(&bytecode, "");
+            let from_source = common::_run_test_io(code, "");
+            assert_eq!(from_file.stdout, from_source.stdout);
+            assert_eq!(from_file.stderr, from_source.stderr);
+            insta::assert_debug_snapshot!(from_file);
+        }
+    };
+}
+
+/// Loads the broken bytecode, which must be rejected
+macro_rules! invalid_test {
+    ($name:ident, $bytecode:expr) => {
+        #[test]
+        fn $name() {
+            let bytecode: Vec<u8> = $bytecode;
+            let output = run(&bytecode, "");
+            insta::assert_debug_snapshot!(output);
+        }
+    };
+}
+
+const PROGRAM: &str = r#"
+fn add(a, b) {
+    return a + b;
+}
+
+let obj = {};
+let arr = [];
+let i = 0;
+while i < 15 {
+    i = add(i, 1);
+    if i % 15 == 0 {
+        print "FizzBuzz";
+    } else if i % 5 == 0 {
+        print "Buzz";
+    } else if i % 3 == 0 {
+        print "Fizz";
+    } else {
+        print i;
+    }
+}
+print json_stringify(null);
+print -0.5 < 1 and not false;
+"#;
+
+round_trip_test!(fizzbuzz, PROGRAM);
+
+round_trip_test!(
+    fields,
+    r#"
+fn make(x) {
+    let object = {};
+    object.x = x;
+    return object;
+}
+
+let a = make(1);
+let b = make(2);
+print a.x + b.x;
+"#
+);
+
+round_trip_test!(
+    methods,
+    r#"
+let Point = {};
+fn Point.sum(self, z) {
+    return self.x + self.y + z;
+}
+
+let point = create(Point);
+point.x = 1;
+point.y = 2;
+print point.sum(3);
+"#
+);
+
+round_trip_test!(
+    match_patterns,
+    r#"
+fn describe(value) {
+    match value {
+        "a" | "b" => { print "letter"; }
+        [x, _] => { print x; }
+        { name } => { print name; }
+        _ => { print "other"; }
+    }
+}
+
+describe("b");
+describe(json_parse("[1, 2]"));
+describe(json_parse("{\"name\": \"c\"}"));
+describe(3);
+"#
+);
+
+round_trip_test!(
+    block_values,
+    r#"
+fn abs(x) {
+    if x < 0 { -x } else { x }
+}
+
+let y = {
+    let a = abs(-2);
+    a * 3
+};
+print y;
+print match y { 6 => { "six" } };
+"#
+);
+
+round_trip_test!(
+    null_safety,
+    r#"
+let data = json_parse("{\"user\": null}");
+print data?.user?.name ?? "nobody";
+print data.user?.name;
+"#
+);
+
+round_trip_test!(
+    variadic_functions,
+    r#"
+fn f(a, b = 2, ...rest) {
+    print a + b;
+    print json_stringify(rest);
+}
+f(1);
+f(...json_parse("[1, 5, 6]"));
+"#
+);
+
+round_trip_test!(
+    destructuring,
+    r#"
+let [first, ...rest] = json_parse("[1, 2, 3]");
+print first;
+print json_stringify(rest);
+let [a, b] = rest;
+print a + b;
+let [c] = rest;
+"#
+);
+
+round_trip_test!(
+    integers,
+    r#"
+let id = 9007199254740993;
+print id + 1;
+print id // 2 + 0.5;
+print -7 % 3;
+print 9223372036854775807 + 1;
+"#
+);
+
+round_trip_test!(
+    runtime_error,
+    r#"
+print "before";
+print 1 + "string";
+"#
+);
+
+#[test]
+fn compile_error_is_reported() {
+    let (bytecode, output) = with_cfg("", |cfg| dilaria::compile_program("print y;", cfg));
+    assert!(bytecode.is_none());
+    insta::assert_debug_snapshot!(output);
+}
+
+#[test]
+fn source_code_is_not_bytecode() {
+    assert!(!dilaria::is_bytecode(PROGRAM.as_bytes()));
+}
+
+/// A file with a single main function with the raw constants and instructions
+fn raw_file(consts: &[&[u8]], instrs: &[&[u8]]) -> Vec<u8> {
+    common::_bytecode_file(&[("main", 0, consts, instrs)])
+}
+
+#[test]
+fn raw_file_is_valid() {
+    let output = run(&raw_file(&[], &[EXIT]), "");
+    assert_eq!(output.stderr, "");
+}
+
+invalid_test!(wrong_magic, {
+    let mut file = compile(PROGRAM);
+    file[0] = b'X';
+    file
+});
+
+invalid_test!(wrong_version, {
+    let mut file = compile(PROGRAM);
+    file[4] = 99;
+    file
+});
+
+invalid_test!(truncated, {
+    let mut file = compile(PROGRAM);
+    file.pop();
+    file
+});
+
+invalid_test!(trailing_bytes, {
+    let mut file = compile(PROGRAM);
+    file.push(0);
+    file
+});
+
+invalid_test!(unknown_instruction, raw_file(&[], &[&[200], EXIT]));
+
+invalid_test!(
+    jump_out_of_function,
+    raw_file(&[], &[&[36, 5, 0, 0, 0, 0, 0, 0, 0], EXIT])
+);
+
+invalid_test!(
+    unknown_native_function,
+    // the native function called `main`
+    raw_file(&[&value::native(1)], &[&push_const(0), EXIT])
+);
+
+invalid_test!(
+    function_out_of_bounds,
+    raw_file(&[&value::function(1)], &[&push_const(0), EXIT])
+);
+
+invalid_test!(
+    string_out_of_bounds,
+    raw_file(&[&value::string(9)], &[&push_const(0), EXIT])
+);
+
+invalid_test!(invalid_bool, raw_file(&[&[1, 2]], &[&push_const(0), EXIT]));
+
+invalid_test!(huge_length, {
+    let mut file = raw_file(&[], &[EXIT]);
+    // the length of the string table
+    file[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    file
+});
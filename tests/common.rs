@@ -11,16 +11,243 @@ macro_rules! run_test {
     };
 }
 
+/// Like `run_test!`, but feeds `$stdin` to the program and snapshots stderr as well
+#[macro_export]
+macro_rules! run_test_io {
+    ($(#[$attr:tt])* $name:ident, $code:expr, $stdin:expr) => {
+        $(#[$attr])*
+        #[test]
+        fn $name() {
+            let code = $code;
+            let output = $crate::common::_run_test_io(code, $stdin);
+            insta::assert_debug_snapshot!(output);
+        }
+    };
+}
+
+#[allow(dead_code)]
 pub fn _run_test(code: &str) -> String {
+    _run_test_io(code, "").stdout
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+pub fn _run_test_io(code: &str, stdin: &str) -> Output {
+    _run_test_with_cfg(code, stdin, false, false)
+}
+
+pub fn _run_test_with_cfg(code: &str, stdin: &str, step: bool, optimize: bool) -> Output {
+    _run_test_with(code, stdin, |cfg| {
+        cfg.step = step;
+        cfg.optimize = optimize;
+    })
+}
+
+/// Runs the code with the default config, after `configure` has changed it
+pub fn _run_test_with(
+    code: &str,
+    stdin: &str,
+    configure: impl FnOnce(&mut dilaria::Config<'_>),
+) -> Output {
     let mut stdout = Vec::<u8>::new();
+    let mut stderr = Vec::<u8>::new();
+    let mut stdin = stdin.as_bytes();
     let mut cfg = dilaria::Config {
+        filename: "test.dil",
         debug: false,
         step: false,
+        profile: false,
+        profile_folded: None,
+        coverage: false,
+        coverage_lcov: None,
         stdout: &mut stdout,
+        stdin: &mut stdin,
+        stderr: &mut stderr,
         parse_only: false,
+        optimize: false,
+        register: false,
     };
+    configure(&mut cfg);
 
     dilaria::run_program(code, &mut cfg);
 
-    String::from_utf8(stdout).unwrap()
+    Output {
+        stdout: String::from_utf8(stdout).unwrap(),
+        stderr: String::from_utf8(stderr).unwrap(),
+    }
+}
+
+/// Runs serialized bytecode, see [`_bytecode_file`]
+#[allow(dead_code)]
+pub fn _run_bytecode(bytecode: &[u8]) -> Output {
+    let mut stdout = Vec::<u8>::new();
+    let mut stderr = Vec::<u8>::new();
+    let mut stdin = "".as_bytes();
+    let mut cfg = dilaria::Config {
+        filename: "test.dilc",
+        debug: false,
+        step: false,
+        profile: false,
+        profile_folded: None,
+        coverage: false,
+        coverage_lcov: None,
+        stdout: &mut stdout,
+        stdin: &mut stdin,
+        stderr: &mut stderr,
+        parse_only: false,
+        optimize: false,
+        register: false,
+    };
+
+    dilaria::run_bytecode(bytecode, &mut cfg);
+
+    Output {
+        stdout: String::from_utf8(stdout).unwrap(),
+        stderr: String::from_utf8(stderr).unwrap(),
+    }
+}
+
+/// A function for [`_bytecode_file`]: `(name, arity, constants, instructions)`, with the constants
+/// and instructions already encoded
+#[allow(dead_code)]
+pub type RawFunction<'a> = (&'a str, u32, &'a [&'a [u8]], &'a [&'a [u8]]);
+
+/// Builds a bytecode file by hand. The source code is empty, and so are all spans.
+#[allow(dead_code)]
+pub fn _bytecode_file(functions: &[RawFunction<'_>]) -> Vec<u8> {
+    let mut file = b"DILC".to_vec();
+    file.extend_from_slice(&12u32.to_le_bytes());
+
+    // the string table, with the empty source first and then the function names
+    file.extend_from_slice(&(functions.len() as u32 + 1).to_le_bytes());
+    file.extend_from_slice(&0u32.to_le_bytes());
+    for (name, _, _, _) in functions {
+        file.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        file.extend_from_slice(name.as_bytes());
+    }
+    file.extend_from_slice(&0u32.to_le_bytes());
+
+    file.extend_from_slice(&(functions.len() as u32).to_le_bytes());
+    for (i, (_, arity, consts, instrs)) in functions.iter().enumerate() {
+        file.extend_from_slice(&(i as u32 + 1).to_le_bytes());
+        // all parameters are required, there is no rest parameter and it's not a method
+        file.extend_from_slice(&arity.to_le_bytes());
+        file.extend_from_slice(&arity.to_le_bytes());
+        file.push(0);
+        file.push(0);
+        file.extend_from_slice(&(consts.len() as u32).to_le_bytes());
+        for value in *consts {
+            file.extend_from_slice(value);
+        }
+        // no field sites
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&(instrs.len() as u32).to_le_bytes());
+        for instr in *instrs {
+            file.extend_from_slice(instr);
+        }
+        for _ in *instrs {
+            file.extend_from_slice(&[0; 16]);
+        }
+        // no locals
+        file.extend_from_slice(&0u32.to_le_bytes());
+    }
+
+    file
+}
+
+/// The encoded instructions for [`_bytecode_file`]
+#[allow(dead_code)]
+pub mod instr {
+    pub const NOP: &[u8] = &[0];
+    pub const BIN_ADD: &[u8] = &[2];
+    pub const PRINT: &[u8] = &[15];
+    pub const RETURN: &[u8] = &[16];
+    pub const EXIT: &[u8] = &[17];
+    pub const PUSH_NULL: &[u8] = &[18];
+    pub const NEW_OBJECT: &[u8] = &[21];
+
+    fn with_u64(tag: u8, value: u64) -> Vec<u8> {
+        let mut bytes = vec![tag];
+        bytes.extend_from_slice(&value.to_le_bytes());
+        bytes
+    }
+
+    pub fn store(offset: u64) -> Vec<u8> {
+        with_u64(32, offset)
+    }
+
+    pub fn load(offset: u64) -> Vec<u8> {
+        with_u64(33, offset)
+    }
+
+    pub fn push_const(index: u32) -> Vec<u8> {
+        let mut bytes = vec![34];
+        bytes.extend_from_slice(&index.to_le_bytes());
+        bytes
+    }
+
+    pub fn jmp_false(offset: i64) -> Vec<u8> {
+        with_u64(35, offset as u64)
+    }
+
+    pub fn jmp(offset: i64) -> Vec<u8> {
+        with_u64(36, offset as u64)
+    }
+
+    pub fn call(args: u32) -> Vec<u8> {
+        let mut bytes = vec![37];
+        bytes.extend_from_slice(&args.to_le_bytes());
+        bytes
+    }
+
+    pub fn shrink_stack(amount: u64) -> Vec<u8> {
+        with_u64(38, amount)
+    }
+
+    pub fn tail_call(args: u32) -> Vec<u8> {
+        let mut bytes = vec![39];
+        bytes.extend_from_slice(&args.to_le_bytes());
+        bytes
+    }
+
+    pub fn get_field(site: u32) -> Vec<u8> {
+        let mut bytes = vec![40];
+        bytes.extend_from_slice(&site.to_le_bytes());
+        bytes
+    }
+}
+
+/// The encoded constants for [`_bytecode_file`]
+#[allow(dead_code)]
+pub mod value {
+    pub fn num(num: f64) -> Vec<u8> {
+        let mut bytes = vec![2];
+        bytes.extend_from_slice(&num.to_bits().to_le_bytes());
+        bytes
+    }
+
+    /// A string from the string table, `0` is the empty source and the function names follow
+    pub fn string(index: u32) -> Vec<u8> {
+        let mut bytes = vec![3];
+        bytes.extend_from_slice(&index.to_le_bytes());
+        bytes
+    }
+
+    pub fn function(function: u32) -> Vec<u8> {
+        let mut bytes = vec![6];
+        bytes.extend_from_slice(&function.to_le_bytes());
+        bytes
+    }
+
+    /// A native function with the name at the index in the string table
+    pub fn native(name: u32) -> Vec<u8> {
+        let mut bytes = vec![7];
+        bytes.extend_from_slice(&name.to_le_bytes());
+        bytes
+    }
 }
@@ -0,0 +1,128 @@
+mod common;
+
+run_test!(
+    constants,
+    r#"
+const LIMIT = 3;
+const GREETING = "hello";
+const NOTHING = null;
+const DOWN = -1;
+print LIMIT;
+print GREETING;
+print NOTHING;
+print DOWN;
+"#
+);
+
+run_test!(
+    literal_constants_are_visible_in_functions,
+    r#"
+const FACTOR = 2;
+const OFFSET = -1;
+
+fn scale(x) {
+    return x * FACTOR + OFFSET;
+}
+
+print scale(5);
+"#
+);
+
+run_test!(
+    constant_objects_can_be_changed,
+    r#"
+const config = {};
+config.name = "dilaria";
+print config.name;
+"#
+);
+
+run_test!(
+    constants_in_blocks_and_functions,
+    r#"
+fn twice(x) {
+    const result = x * 2;
+    return result;
+}
+
+{
+    const inner = twice(4);
+    print inner;
+}
+"#
+);
+
+run_test!(
+    destructured_constants,
+    r#"
+const [first, ...rest] = json_parse("[1, 2, 3]");
+const { name } = json_parse("{\"name\": \"hugo\"}");
+print first;
+print json_stringify(rest);
+print name;
+"#
+);
+
+run_test!(
+    shadowing_a_constant,
+    r#"
+const LIMIT = 3;
+{
+    let LIMIT = 10;
+    LIMIT += 1;
+    print LIMIT;
+}
+print LIMIT;
+
+let x = 1;
+const x = 2;
+print x;
+
+const y = 1;
+let y = 2;
+y = 3;
+print y;
+"#
+);
+
+run_test_io!(
+    assign_to_constant,
+    r#"
+const LIMIT = 3;
+
+LIMIT = 4;
+"#,
+    ""
+);
+
+run_test_io!(
+    compound_assign_to_constant,
+    r#"
+const count = json_parse("0");
+count += 1;
+"#,
+    ""
+);
+
+run_test_io!(
+    assign_to_constant_in_function,
+    r#"
+const LIMIT = 3;
+
+fn raise() {
+    LIMIT = 5;
+}
+"#,
+    ""
+);
+
+run_test_io!(
+    assign_to_destructured_constant,
+    r#"
+const [a, b] = json_parse("[1, 2]");
+{
+    b = 3;
+}
+"#,
+    ""
+);
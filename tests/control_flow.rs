@@ -210,3 +210,154 @@ loop {
 print "End";
 "#
 );
+
+run_test!(
+    locals_in_if_body,
+    r#"
+let x = 1;
+if x == 1 {
+    let a = 2;
+    let b = 3;
+    print a + b;
+}
+let y = 4;
+print x;
+print y;
+"#
+);
+
+run_test!(
+    if_expression,
+    r#"
+let x = 5;
+let size = if x > 3 { "big" } else { "small" };
+print size;
+print if x > 10 { "huge" } else if x > 4 { "medium" } else { "tiny" };
+print if false { 1 };
+"#
+);
+
+run_test!(
+    block_expression,
+    r#"
+let a = 1;
+let b = {
+    let two = 2;
+    let three = 3;
+    a + two * three
+};
+print b;
+print { 5 };
+print { let unused = 1; };
+let c = 10;
+print c;
+"#
+);
+
+run_test!(
+    nested_expressions_with_locals,
+    r#"
+let x = 2;
+let y = if x == 2 {
+    let half = x / 2;
+    if half == 1 {
+        let one = { let tmp = half; tmp };
+        one + 10
+    } else {
+        0
+    }
+} else {
+    -1
+};
+print y;
+print x;
+"#
+);
+
+run_test!(
+    match_expression,
+    r#"
+fn name(n) {
+    let result = match n {
+        0 => { "zero" }
+        1 | 2 => { "small" }
+        x if x < 0 => { "negative" }
+        _ => {
+            let prefix = "big";
+            prefix
+        }
+    };
+    return result;
+}
+
+print name(0);
+print name(2);
+print name(-3);
+print name(100);
+print match 1 { 2 => { "two" } };
+"#
+);
+
+run_test!(
+    function_body_value,
+    r#"
+fn double(x) {
+    x * 2
+}
+
+fn sign(x) {
+    if x < 0 { -1 } else if x > 0 { 1 } else { 0 }
+}
+
+fn count_down(n) {
+    match n {
+        0 => { "done" }
+        n => { count_down(n - 1) }
+    }
+}
+
+fn nothing() {
+    let x = 1;
+}
+
+print double(21);
+print sign(-5);
+print sign(0);
+print count_down(100000);
+print nothing();
+"#
+);
+
+run_test!(
+    trailing_statement_in_loop,
+    r#"
+let i = 0;
+while i < 3 {
+    let doubled = i * 2;
+    if doubled == 2 {
+        print "two";
+    } else {
+        print doubled;
+    }
+    i += 1;
+}
+"#
+);
+
+run_test!(
+    empty_braces_are_an_object,
+    r#"
+let object = {};
+object.x = 1;
+print object.x;
+"#
+);
+
+run_test_io!(
+    value_at_the_top_level,
+    r#"
+print 1;
+2 }
+"#,
+    ""
+);
@@ -0,0 +1,89 @@
+mod common;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+struct CoverageOutput {
+    stdout: String,
+    summary: String,
+    lcov: String,
+}
+
+/// Runs the program with coverage and snapshots the summary and the lcov report
+macro_rules! coverage_test {
+    ($name:ident, $code:expr) => {
+        #[test]
+        fn $name() {
+            let output = coverage($code);
+            insta::assert_debug_snapshot!(output);
+        }
+    };
+}
+
+fn coverage(code: &str) -> CoverageOutput {
+    let mut stdout = Vec::<u8>::new();
+    let mut stderr = Vec::<u8>::new();
+    let mut lcov = Vec::<u8>::new();
+    let mut stdin = "".as_bytes();
+    let mut cfg = dilaria::Config {
+        filename: "test.dil",
+        debug: false,
+        step: false,
+        profile: false,
+        profile_folded: None,
+        coverage: true,
+        coverage_lcov: Some(&mut lcov),
+        stdout: &mut stdout,
+        stdin: &mut stdin,
+        stderr: &mut stderr,
+        parse_only: false,
+        optimize: false,
+        register: false,
+    };
+
+    dilaria::run_program(code, &mut cfg);
+
+    CoverageOutput {
+        stdout: String::from_utf8(stdout).unwrap(),
+        summary: String::from_utf8(stderr).unwrap(),
+        lcov: String::from_utf8(lcov).unwrap(),
+    }
+}
+
+coverage_test!(
+    untaken_branches,
+    r#"fn used(x) {
+    return x * 2;
+}
+
+fn unused() {
+    print "never";
+}
+
+let i = 0;
+while i < 3 {
+    if i == 5 {
+        print "never";
+        print "not either";
+    } else {
+        i = used(i) + 1;
+    }
+}
+"#
+);
+
+coverage_test!(
+    everything_covered,
+    r#"let x = 1;
+if x == 1 {
+    print "one";
+}
+"#
+);
+
+coverage_test!(
+    stops_at_error,
+    r#"print "before";
+print 1 + "string";
+print "after";
+"#
+);
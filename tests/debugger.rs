@@ -0,0 +1,117 @@
+mod common;
+
+/// Runs the program in the debugger with the commands as its input
+macro_rules! debugger_test {
+    ($name:ident, $code:expr, $commands:expr) => {
+        #[test]
+        fn $name() {
+            let output = common::_run_test_with_cfg($code, $commands, true, false);
+            insta::assert_debug_snapshot!(output);
+        }
+    };
+}
+
+const PROGRAM: &str = r#"fn add(a, b) {
+    let sum = a + b;
+    return sum;
+}
+
+fn twice(x) {
+    return x * 2;
+}
+
+let x = 1;
+let y = add(x, 2);
+let z = twice(y);
+print z;
+"#;
+
+debugger_test!(
+    step_into_and_out,
+    PROGRAM,
+    "step
+step
+step
+step
+step
+locals
+out
+locals
+continue
+"
+);
+
+debugger_test!(
+    step_over_calls,
+    PROGRAM,
+    "next
+next
+next
+next
+p y
+next
+next
+"
+);
+
+debugger_test!(
+    breakpoint_backtrace_locals,
+    PROGRAM,
+    "break 3
+continue
+backtrace
+locals
+locals 1
+print sum
+print nope
+locals 5
+continue
+"
+);
+
+debugger_test!(
+    empty_line_repeats_last_command,
+    PROGRAM,
+    "next
+
+
+"
+);
+
+debugger_test!(
+    block_scopes,
+    r#"let a = 1;
+{
+    let a = 2;
+    let b = 3;
+    print a;
+}
+print a;
+"#,
+    "break 5
+break 7
+continue
+locals
+continue
+locals
+continue
+"
+);
+
+debugger_test!(
+    quit_stops_program,
+    PROGRAM,
+    "quit
+"
+);
+
+debugger_test!(
+    invalid_commands,
+    "print 1;\n",
+    "break 100
+break
+frobnicate
+help
+instr
+"
+);
@@ -0,0 +1,187 @@
+mod common;
+
+run_test!(
+    array_destructuring,
+    r#"
+let [a, b] = json_parse("[1, 2]");
+print a;
+print b;
+
+let [first, ...rest] = json_parse("[1, 2, 3]");
+print first;
+print json_stringify(rest);
+
+let [x, [y, _], ..._] = json_parse("[1, [2, 3], 4, 5]");
+print x + y;
+"#
+);
+
+run_test!(
+    object_destructuring,
+    r#"
+let { name, age: years } = json_parse("{\"name\": \"hugo\", \"age\": 21, \"likes\": \"rust\"}");
+print name;
+print years;
+
+let { inner: { values: [one, two] } } = json_parse("{\"inner\": {\"values\": [1, 2]}}");
+print one + two;
+"#
+);
+
+run_test!(
+    object_destructuring_sees_prototype_fields,
+    r#"
+let Animal = {};
+Animal.legs = 4;
+let dog = create(Animal);
+dog.name = "rex";
+
+let { name, legs } = dog;
+print name;
+print legs;
+"#
+);
+
+run_test!(
+    destructured_parameters,
+    r#"
+fn distance({ x, y }, [dx, dy] = json_parse("[0, 0]")) {
+    return x + dx + y + dy;
+}
+
+fn head_and_tail([head, ...tail]) {
+    print head;
+    print json_stringify(tail);
+}
+
+fn count(...[first, ...others]) {
+    print first;
+    print json_stringify(others);
+}
+
+let point = json_parse("{\"x\": 1, \"y\": 2}");
+print distance(point);
+print distance(point, json_parse("[10, 20]"));
+head_and_tail(json_parse("[1, 2, 3]"));
+count(1, 2, 3);
+"#
+);
+
+run_test!(
+    destructuring_shadows_functions,
+    r#"
+fn double(x) {
+    return x * 2;
+}
+
+let [double] = json_parse("[5]");
+print double;
+"#
+);
+
+run_test!(
+    wildcard_declaration,
+    r#"
+fn effect() {
+    print "called";
+    return 1;
+}
+
+let _ = effect();
+let [_, second] = json_parse("[1, 2]");
+print second;
+"#
+);
+
+run_test!(
+    array_literals,
+    r#"
+fn pack(a, b, ...rest) {
+    return json_stringify([a, b, rest]);
+}
+
+print pack(1, 2, 3, 4);
+let [x, [y, z]] = [1, [2 + 3, "five"]];
+print x + y;
+print z;
+
+let i = 0;
+while i < 2 {
+    let pair = [i, i * 10];
+    print json_stringify(pair);
+    i += 1;
+}
+"#
+);
+
+run_test_io!(
+    array_too_short,
+    r#"
+let [a, b] = json_parse("[1]");
+print "unreachable";
+"#,
+    ""
+);
+
+run_test_io!(
+    not_an_array,
+    r#"
+let [a, ...rest] = json_parse("{\"a\": 1}");
+print "unreachable";
+"#,
+    ""
+);
+
+run_test_io!(
+    missing_field,
+    r#"
+let { name, age } = json_parse("{\"name\": \"hugo\"}");
+print "unreachable";
+"#,
+    ""
+);
+
+run_test_io!(
+    parameter_does_not_match,
+    r#"
+fn f({ x }) {
+    return x;
+}
+
+print f(json_parse("{\"x\": 1}"));
+f(1);
+"#,
+    ""
+);
+
+run_test_io!(
+    duplicate_binding,
+    r#"
+let [a, { b: a }] = json_parse("[1, {\"b\": 2}]");
+"#,
+    ""
+);
+
+run_test_io!(
+    duplicate_parameter,
+    r#"
+fn f(a, [b, a]) {}
+"#,
+    ""
+);
+
+run_test_io!(
+    literal_pattern_in_let,
+    r#"
+let [1, a] = json_parse("[1, 2]");
+"#,
+    ""
+);
+
+run_test_io!(
+    or_pattern_in_parameter,
+    r#"
+fn f(_ | _) {}
+"#,
+    ""
+);
@@ -83,7 +83,6 @@ if added == 6 {
 );
 
 run_test!(
-    #[ignore]
     nested_calls,
     r#"
 fn cooler_add(a, b) {
@@ -105,7 +104,6 @@ if added == 6 {
 );
 
 run_test!(
-    #[ignore]
     fib5,
     r#"
 fn fib(n) {
@@ -120,3 +118,259 @@ let fib5 = fib(5);
 print fib5;
 "#
 );
+
+run_test!(
+    call_outer_function,
+    r#"
+fn double(x) {
+    return x * 2;
+}
+
+fn quadruple(x) {
+    return double(double(x));
+}
+
+print quadruple(3);
+"#
+);
+
+run_test_io!(
+    outer_variable_shadows_function,
+    r#"
+fn f() {
+    return 1;
+}
+
+let f = 2;
+
+fn g() {
+    return f();
+}
+"#,
+    ""
+);
+
+run_test!(
+    deep_tail_recursion,
+    r#"
+fn sum(n, acc) {
+    if n == 0 {
+        return acc;
+    }
+    let next = n - 1;
+    return sum(next, acc + n);
+}
+
+print sum(1000000, 0);
+"#
+);
+
+run_test!(
+    tail_call_with_other_arity,
+    r#"
+fn add(a, b, c) {
+    return a + b + c;
+}
+
+fn twice(x) {
+    let doubled = x * 2;
+    return add(doubled, doubled, 0);
+}
+
+print twice(3) + 1;
+"#
+);
+
+run_test!(
+    tail_call_native_function,
+    r#"
+fn stringify(value) {
+    return json_stringify(value);
+}
+
+print stringify(5);
+print stringify(null);
+"#
+);
+
+run_test_io!(
+    tail_call_wrong_number_of_arguments,
+    r#"
+fn f(a) {
+    return a;
+}
+
+fn g() {
+    return f(1, 2);
+}
+
+g();
+"#,
+    ""
+);
+
+run_test!(
+    default_parameters,
+    r#"
+fn greet(name, greeting = "hello", punctuation = greeting) {
+    print greeting;
+    print name;
+    print punctuation;
+}
+
+greet("world");
+greet("world", "hi");
+greet("world", "hi", "!");
+"#
+);
+
+run_test!(
+    default_is_evaluated_at_call_time,
+    r#"
+fn counter(object) {
+    object.count = object.count + 1;
+    return object.count;
+}
+
+fn next(object, value = counter(object)) {
+    return value;
+}
+
+let object = {};
+object.count = 0;
+print next(object);
+print next(object);
+print next(object, 10);
+print next(object, null);
+"#
+);
+
+run_test!(
+    rest_parameter,
+    r#"
+fn count(first, ...rest) {
+    print first;
+    print json_stringify(rest);
+}
+
+count(1);
+count(1, 2);
+count(1, 2, 3);
+"#
+);
+
+run_test!(
+    defaults_and_rest,
+    r#"
+fn f(a, b = 2, ...rest) {
+    print a + b;
+    print json_stringify(rest);
+}
+
+f(1);
+f(1, 5);
+f(1, 5, 6, 7);
+"#
+);
+
+run_test!(
+    spread_arguments,
+    r#"
+fn add(a, b, c) {
+    return a + b + c;
+}
+
+let two = json_parse("[1, 2]");
+let empty = json_parse("[]");
+print add(...two, 10);
+print add(10, ...two);
+print add(...empty, 1, ...two, ...empty);
+"#
+);
+
+run_test!(
+    spread_into_rest,
+    r#"
+fn collect(...all) {
+    return json_stringify(all);
+}
+
+let array = json_parse("[1, 2, 3]");
+print collect(...array);
+print collect(0, ...array, 4, ...array);
+"#
+);
+
+run_test!(
+    spread_method_call,
+    r#"
+let Point = {};
+fn Point.move(self, dx, dy) {
+    self.x = self.x + dx;
+    self.y = self.y + dy;
+    return self;
+}
+
+let point = create(Point);
+point.x = 1;
+point.y = 2;
+let delta = json_parse("[3, 4]");
+print point.move(...delta).x;
+print point.y;
+"#
+);
+
+run_test!(
+    tail_call_with_rest_parameter,
+    r#"
+fn count_down(n, ...seen) {
+    if n == 0 {
+        return json_stringify(seen);
+    }
+    return count_down(n - 1, n, n);
+}
+
+print count_down(100000);
+"#
+);
+
+run_test_io!(
+    missing_required_argument,
+    r#"
+fn f(a, b = 1) {
+    return a + b;
+}
+
+print f(1);
+f();
+"#,
+    ""
+);
+
+run_test_io!(
+    spread_of_a_non_array,
+    r#"
+fn f(a) {
+    return a;
+}
+
+f(...5);
+"#,
+    ""
+);
+
+run_test_io!(
+    rest_parameter_must_be_last,
+    r#"
+fn f(...rest, a) {}
+"#,
+    ""
+);
+
+run_test_io!(
+    required_parameter_after_default,
+    r#"
+fn f(a = 1, b) {}
+"#,
+    ""
+);
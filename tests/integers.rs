@@ -0,0 +1,216 @@
+mod common;
+
+run_test!(
+    int_and_float_literals,
+    r#"
+print 9007199254740993;
+print 9007199254740993.0;
+print 1_000_000;
+print 0.5;
+"#
+);
+
+run_test!(
+    int_arithmetic,
+    r#"
+let id = 9007199254740993;
+print id + 1;
+print id - 2;
+print 3037000499 * 3037000499;
+print -7 + 3;
+"#
+);
+
+run_test!(
+    division,
+    r#"
+print 7 / 2;
+print 7 // 2;
+print -7 // 2;
+print 7 // -2;
+print 7.5 // 2;
+print -7.5 // 2;
+"#
+);
+
+run_test!(
+    remainder_has_the_sign_of_the_divisor,
+    r#"
+print 7 % 3;
+print -7 % 3;
+print 7 % -3;
+print -7 % -3;
+print -7.5 % 2;
+let min = -9223372036854775807 - 1;
+print min % -1;
+let a = -17;
+let b = 5;
+print (a // b) * b + a % b == a;
+"#
+);
+
+run_test!(
+    bitwise_operators,
+    r#"
+print 12 & 10;
+print 12 | 10;
+print 12 ^ 10;
+print 1 << 62;
+print -16 >> 2;
+print 1 | 2 == 3;
+print 1 + 1 << 2;
+"#
+);
+
+run_test!(
+    chained_operators_associate_to_the_left,
+    r#"
+print 8 // 2 // 2;
+print 10 - 2 - 3;
+print 1 - 2 + 3;
+print 1 << 2 << 3;
+print 100 >> 2 >> 1;
+print 1 << 4 >> 2;
+print 7 & 6 & 3;
+print 1 | 2 | 4;
+print 6 ^ 3 ^ 1;
+print 64 // 4 * 2 % 5;
+print 8 / 2 / 2;
+"#
+);
+
+run_test!(
+    compound_assignment,
+    r#"
+let x = 17;
+x //= 2;
+print x;
+let flags = 1;
+flags |= 4;
+print flags;
+flags &= 6;
+print flags;
+flags ^= 3;
+print flags;
+flags <<= 3;
+print flags;
+flags >>= 2;
+print flags;
+let counter = {};
+counter.count = -7;
+counter.count //= 2;
+print counter.count;
+"#
+);
+
+run_test!(
+    mixing_with_floats,
+    r#"
+print 1 + 0.5;
+print 2 * 1.5;
+print 1 == 1.0;
+print 1 != 1.0;
+print 2 > 1.5;
+print 9007199254740993 > 9007199254740992.0;
+print 9007199254740993 == 9007199254740992.0;
+print 9223372036854775807 < 9223372036854775808.0;
+"#
+);
+
+run_test!(
+    match_int_and_float_literals,
+    r#"
+fn describe(x) {
+    match x {
+        0 => { return "zero"; }
+        -1 => { return "minus one"; }
+        1.5 => { return "one and a half"; }
+        _ => { return "something else"; }
+    }
+}
+
+print describe(0);
+print describe(0.0);
+print describe(-1);
+print describe(1.5);
+print describe(2);
+"#
+);
+
+run_test!(
+    json_keeps_integers,
+    r#"
+let value = json_parse("{\"id\": 9007199254740993, \"ratio\": 1.0, \"big\": 1e3}");
+print value.id + 1;
+print json_stringify(value);
+"#
+);
+
+run_test_io!(
+    addition_overflows,
+    r#"
+let max = 9223372036854775807;
+print max;
+print max + 1;
+"#,
+    ""
+);
+
+run_test_io!(
+    multiplication_overflows,
+    r#"
+print 4294967296 * 4294967296;
+"#,
+    ""
+);
+
+run_test_io!(
+    negation_overflows,
+    r#"
+let min = -9223372036854775807 - 1;
+print min;
+print -min;
+"#,
+    ""
+);
+
+run_test_io!(
+    shift_overflows,
+    r#"
+print 1 << 63;
+"#,
+    ""
+);
+
+run_test_io!(
+    shift_out_of_range,
+    r#"
+print 1 >> 64;
+"#,
+    ""
+);
+
+run_test_io!(
+    integer_division_by_zero,
+    r#"
+print 1.0 // 0;
+print 1 // 0;
+"#,
+    ""
+);
+
+run_test_io!(
+    bitwise_operators_need_ints,
+    r#"
+print 1.0 & 1;
+"#,
+    ""
+);
+
+run_test_io!(
+    integer_literal_too_large,
+    r#"
+print 9223372036854775808;
+"#,
+    ""
+);
@@ -0,0 +1,40 @@
+mod common;
+
+run_test_io!(
+    input_lines,
+    r#"
+let name = input("name: ");
+print name;
+let second = input();
+print second;
+print input();
+"#,
+    "nils\r\nsecond line\n"
+);
+
+run_test_io!(
+    runtime_error,
+    r#"
+print "before";
+print 1 + "string";
+print "WRONG";
+"#,
+    ""
+);
+
+run_test_io!(
+    compile_error,
+    r#"
+let x = 5;
+print y;
+"#,
+    ""
+);
+
+run_test_io!(
+    parse_error,
+    r#"
+let x = ;
+"#,
+    ""
+);
@@ -0,0 +1,94 @@
+mod common;
+
+run_test!(
+    parse_primitives,
+    r#"
+print json_parse("1.5");
+print json_parse("-20e-1");
+print json_parse(" true ");
+print json_parse("false");
+print json_parse("null");
+print json_parse("\"hi\\n\\u00e4\\ud83d\\ude00\"");
+"#
+);
+
+run_test!(
+    round_trip,
+    r#"
+let json = "{ \"name\": \"dilaria\", \"tags\": [\"small\", \"script\"], \"version\": 0.1, \"stable\": false, \"parent\": null, \"nested\": { \"empty\": {}, \"list\": [] } }";
+print json_stringify(json_parse(json));
+"#
+);
+
+run_test!(
+    stringify_indent,
+    r#"
+let value = json_parse("{\"b\": [1, 2, {\"c\": null}], \"a\": {}, \"d\": []}");
+print json_stringify(value, 2);
+print json_stringify(value, "--");
+"#
+);
+
+run_test!(
+    stringify_escapes,
+    r#"
+print json_stringify("quote \" backslash \\");
+print json_stringify(json_parse("\"new\\nline\\ttab\""));
+print json_stringify(json_parse("\"\\u0001\""));
+"#
+);
+
+run_test!(
+    stringify_function_is_error,
+    r#"
+fn test() {}
+
+print "before";
+print json_stringify(test);
+print "WRONG";
+"#
+);
+
+run_test!(
+    parse_invalid_is_error,
+    r#"
+print "before";
+print json_parse("[1, 2,]");
+print "WRONG";
+"#
+);
+
+run_test!(
+    parse_trailing_characters_is_error,
+    r#"
+print "before";
+print json_parse("{} {}");
+print "WRONG";
+"#
+);
+
+run_test!(
+    local_shadows_native,
+    r#"
+fn json_parse(str) {
+    return "shadowed";
+}
+
+print json_parse("1");
+"#
+);
+
+run_test!(
+    print_parsed_values,
+    r#"
+fn callback() {}
+
+print json_parse("[1, 2]");
+let obj = json_parse("{\"b\": [true, null, \"quoted \\\"text\\\"\"], \"a\": 1.5}");
+print obj;
+obj.callback = callback;
+obj.self = obj;
+print obj;
+print json_parse("{}");
+"#
+);
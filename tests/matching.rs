@@ -0,0 +1,305 @@
+mod common;
+
+run_test!(
+    literals,
+    r#"
+fn describe(x) {
+    match x {
+        0 => { print "zero"; }
+        -1 => { print "minus one"; }
+        "hello" => { print "a greeting"; }
+        true => { print "yes"; }
+        null => { print "nothing"; }
+        _ => { print "something else"; }
+    }
+}
+
+describe(0);
+describe(-1);
+describe("hello");
+describe(true);
+describe(false);
+describe(null);
+describe("0");
+"#
+);
+
+run_test!(
+    or_patterns,
+    r#"
+let i = 0;
+while i < 6 {
+    match i {
+        0 | 2 | 4 => { print "even"; }
+        1 | 3 => { print "odd"; }
+        _ => { print "too big"; }
+    }
+    i += 1;
+}
+"#
+);
+
+run_test!(
+    bindings,
+    r#"
+let x = 5;
+match x * 2 {
+    1 => { print "one"; }
+    n => {
+        let m = n + 1;
+        print n;
+        print m;
+    }
+}
+print x;
+"#
+);
+
+run_test!(
+    expression_arms,
+    r#"
+fn sign(x) {
+    match x {
+        0 => "zero",
+        n if n < 0 => "negative",
+        _ => { "positive" }
+    }
+}
+
+print sign(0);
+print sign(-3);
+print sign(7);
+print match 3 { 0 => 1, _ => 2 };
+print match [1, 2] { [a, b] => a + b, };
+"#
+);
+
+run_test!(
+    assigning_to_a_binding,
+    r#"
+let x = 1;
+match x {
+    y => {
+        y = 2;
+        print y;
+    }
+}
+match x {
+    1 => { print "still one"; }
+}
+print x;
+"#
+);
+
+run_test!(
+    guards,
+    r#"
+fn sign(x) {
+    match x {
+        0 => { print "zero"; }
+        n if n < 0 => { print "negative"; }
+        n => { print "positive"; }
+    }
+}
+
+sign(0);
+sign(-3);
+sign(3);
+"#
+);
+
+run_test!(
+    no_arm_matches,
+    r#"
+match 3 {
+    1 => { print "one"; }
+    2 => { print "two"; }
+}
+print "done";
+"#
+);
+
+run_test!(
+    array_patterns,
+    r#"
+fn describe(value) {
+    match value {
+        [] => { print "empty"; }
+        [x] => { print x; }
+        [0, _] => { print "starts with zero"; }
+        [a, [b, c]] => {
+            print a;
+            print b;
+            print c;
+        }
+        [_, _] => { print "a pair"; }
+        _ => { print "no match"; }
+    }
+}
+
+describe(json_parse("[]"));
+describe(json_parse("[1]"));
+describe(json_parse("[0, 5]"));
+describe(json_parse("[1, [2, 3]]"));
+describe(json_parse("[1, 2]"));
+describe(json_parse("[1, 2, 3]"));
+describe("not an array");
+"#
+);
+
+run_test!(
+    rest_patterns,
+    r#"
+fn describe(value) {
+    match value {
+        [] => { print "empty"; }
+        [first, ..._] if first == 0 => { print "starts with zero"; }
+        [first, ...rest] => {
+            print first;
+            print json_stringify(rest);
+        }
+        _ => { print "no match"; }
+    }
+}
+
+describe(json_parse("[]"));
+describe(json_parse("[0, 1, 2]"));
+describe(json_parse("[1]"));
+describe(json_parse("[1, 2, 3]"));
+describe("not an array");
+"#
+);
+
+run_test!(
+    object_patterns,
+    r#"
+fn describe(value) {
+    match value {
+        { kind: "circle", radius } => {
+            print "circle";
+            print radius;
+        }
+        { kind: "rect", size: [w, h] } => {
+            print "rect";
+            print w * h;
+        }
+        { kind } => {
+            print "unknown kind";
+            print kind;
+        }
+        _ => { print "no kind"; }
+    }
+}
+
+describe(json_parse("{\"kind\": \"circle\", \"radius\": 2}"));
+describe(json_parse("{\"kind\": \"rect\", \"size\": [2, 3]}"));
+describe(json_parse("{\"kind\": \"rect\", \"size\": 4}"));
+describe(json_parse("{\"other\": 1}"));
+describe(5);
+"#
+);
+
+run_test!(
+    object_patterns_see_prototype_fields,
+    r#"
+let Animal = {};
+Animal.legs = 4;
+let dog = create(Animal);
+dog.name = "rex";
+
+match dog {
+    { name, legs } => {
+        print name;
+        print legs;
+    }
+}
+"#
+);
+
+run_test!(
+    match_in_loop,
+    r#"
+let i = 0;
+loop {
+    match i {
+        3 => { break; }
+        n => { print n; }
+    }
+    i += 1;
+}
+print "after";
+"#
+);
+
+run_test_io!(
+    unreachable_arm_warning,
+    r#"
+match 1 {
+    x => { print x; }
+    2 => { print "two"; }
+}
+"#,
+    ""
+);
+
+run_test_io!(
+    guarded_catch_all_is_not_a_warning,
+    r#"
+match 1 {
+    x if x > 5 => { print "big"; }
+    _ => { print "small"; }
+}
+"#,
+    ""
+);
+
+run_test_io!(
+    duplicate_binding,
+    r#"
+match json_parse("[1, 2]") {
+    [a, a] => {}
+}
+"#,
+    ""
+);
+
+run_test_io!(
+    binding_in_or_pattern,
+    r#"
+match 1 {
+    1 | x => {}
+}
+"#,
+    ""
+);
+
+run_test_io!(
+    empty_object_pattern,
+    r#"
+match 1 {
+    {} => {}
+}
+"#,
+    ""
+);
+
+run_test_io!(
+    rest_pattern_must_be_last,
+    r#"
+match json_parse("[1, 2]") {
+    [...rest, last] => {}
+}
+"#,
+    ""
+);
+
+run_test_io!(
+    expression_arm_without_comma,
+    r#"
+match 1 {
+    0 => 1
+    _ => 2
+}
+"#,
+    ""
+);
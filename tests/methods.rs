@@ -0,0 +1,169 @@
+mod common;
+
+run_test!(
+    method_gets_self,
+    r#"
+let Point = {};
+
+fn Point.new(self, x, y) {
+    let point = create(self);
+    point.x = x;
+    point.y = y;
+    return point;
+}
+
+fn Point.len_squared(self) {
+    return self.x * self.x + self.y * self.y;
+}
+
+fn Point.scaled(self, factor) {
+    return self.new(self.x * factor, self.y * factor);
+}
+
+let point = Point.new(3, 4);
+print point.len_squared();
+print point.scaled(2).len_squared();
+"#
+);
+
+run_test!(
+    prototype_chain,
+    r#"
+let Animal = {};
+fn Animal.name(self) {
+    return "animal";
+}
+fn Animal.greet(self) {
+    print "hello";
+    print self.name();
+}
+
+let Dog = create(Animal);
+fn Dog.name(self) {
+    return "dog";
+}
+
+let animal = create(Animal);
+let dog = create(Dog);
+animal.greet();
+dog.greet();
+"#
+);
+
+run_test!(
+    fields_shadow_the_prototype,
+    r#"
+let Counter = {};
+Counter.count = 0;
+fn Counter.increment(self) {
+    self.count = self.count + 1;
+}
+
+let a = create(Counter);
+let b = create(Counter);
+a.increment();
+a.increment();
+b.increment();
+print a.count;
+print b.count;
+print Counter.count;
+"#
+);
+
+run_test!(
+    prototype_changes_are_visible,
+    r#"
+let Proto = {};
+let object = create(Proto);
+Proto.value = 1;
+print object.value;
+Proto.value = 2;
+print object.value;
+"#
+);
+
+run_test!(
+    method_calls_in_return_position,
+    r#"
+let Counter = {};
+fn Counter.sum(self, n, acc) {
+    if n == 0 {
+        return acc;
+    }
+    return self.sum(n - 1, acc + n);
+}
+
+let counter = create(Counter);
+print counter.sum(10, 0);
+"#
+);
+
+run_test!(
+    functions_in_fields_get_no_self,
+    r#"
+fn add(a, b) {
+    return a + b;
+}
+
+fn greet(name, greeting = "hello") {
+    return [greeting, name];
+}
+
+let Point = {};
+fn Point.x(self) {
+    return self.x;
+}
+
+let math = {};
+math.add = add;
+math.greet = greet;
+math.parse = json_parse;
+math.x = 5;
+math.get_x = Point.x;
+print math.add(1, 2);
+print math.add(...[3, 4]);
+print math.greet("nils");
+print math.parse("[1]");
+print math.get_x();
+"#
+);
+
+run_test_io!(
+    methods_are_not_variables,
+    r#"
+let Point = {};
+fn Point.len(self) {
+    return 0;
+}
+print len;
+"#,
+    ""
+);
+
+run_test_io!(
+    method_without_self,
+    r#"
+let Point = {};
+fn Point.len() {
+    return 0;
+}
+"#,
+    ""
+);
+
+run_test_io!(
+    missing_method,
+    r#"
+let object = {};
+object.missing();
+"#,
+    ""
+);
+
+run_test_io!(
+    create_needs_an_object,
+    r#"
+create(1);
+"#,
+    ""
+);
@@ -0,0 +1,126 @@
+mod common;
+
+run_test!(
+    field_chains,
+    r#"
+let data = json_parse("{\"user\": {\"name\": \"hugo\", \"address\": null}}");
+print data?.user?.name;
+print data.user.address?.street;
+print data.user.address?.street.number;
+let nothing = null;
+print nothing?.field;
+"#
+);
+
+run_test!(
+    coalesce,
+    r#"
+let nothing = null;
+print nothing ?? 5;
+print 3 ?? 5;
+print false ?? 5;
+print nothing ?? nothing ?? "last";
+print nothing?.a.b ?? "default";
+"#
+);
+
+run_test!(
+    coalesce_only_evaluates_the_default_for_null,
+    r#"
+fn fallback() {
+    print "evaluated";
+    return 1;
+}
+
+print 2 ?? fallback();
+print null ?? fallback();
+"#
+);
+
+run_test!(
+    method_calls,
+    r#"
+let Greeter = {};
+fn Greeter.greet(self, name) {
+    print "greeting";
+    return name;
+}
+
+fn find(prototype, found) {
+    if found {
+        return create(prototype);
+    }
+    return null;
+}
+
+print find(Greeter, true)?.greet("world");
+print find(Greeter, false)?.greet("world");
+print find(Greeter, false)?.greet("world").missing.fields;
+"#
+);
+
+run_test!(
+    calls,
+    r#"
+fn double(x) {
+    return x * 2;
+}
+fn pick(which) {
+    if which {
+        return double;
+    }
+    return null;
+}
+
+let none = null;
+print double?.(4);
+print none?.(4);
+print pick(true)?.(5);
+print pick(false)?.(5) ?? "no function";
+"#
+);
+
+run_test!(
+    chains_in_functions,
+    r#"
+fn street(person) {
+    let prefix = "at ";
+    return person?.address?.street ?? prefix;
+}
+
+fn call(f, x) {
+    return f?.(x);
+}
+
+print street(json_parse("{\"address\": {\"street\": \"main\"}}"));
+print street(json_parse("{\"address\": null}"));
+print street(null);
+print call(null, 1);
+"#
+);
+
+run_test_io!(
+    assigning_after_null_safe_field,
+    r#"
+let object = {};
+object?.field = 1;
+"#,
+    ""
+);
+
+run_test_io!(
+    null_safe_method_call,
+    r#"
+let object = {};
+object.method?.();
+"#,
+    ""
+);
+
+run_test_io!(
+    single_question_mark,
+    r#"
+let x = null ? 1;
+"#,
+    ""
+);
@@ -0,0 +1,231 @@
+mod common;
+
+run_test!(
+    get_and_set_fields,
+    r#"
+let point = {};
+point.x = 1;
+point.y = 2;
+print point.x + point.y;
+point.x = 10;
+print point.x;
+print point.y;
+"#
+);
+
+run_test!(
+    object_literals_create_new_objects,
+    r#"
+fn make() {
+    let object = {};
+    return object;
+}
+
+let a = make();
+let b = make();
+a.value = 1;
+b.value = 2;
+print a.value;
+print b.value;
+"#
+);
+
+run_test!(
+    same_site_different_shapes,
+    r#"
+fn sum(point) {
+    return point.x + point.y;
+}
+
+let xy = {};
+xy.x = 1;
+xy.y = 2;
+
+let yx = {};
+yx.y = 30;
+yx.x = 40;
+
+let xyz = {};
+xyz.x = 500;
+xyz.y = 600;
+xyz.z = 700;
+
+let i = 0;
+while i < 3 {
+    print sum(xy);
+    print sum(yx);
+    print sum(xyz);
+    i = i + 1;
+}
+"#
+);
+
+run_test!(
+    same_site_adds_and_updates_fields,
+    r#"
+fn set_x(object, x) {
+    object.x = x;
+}
+
+let a = {};
+set_x(a, 1);
+let b = {};
+set_x(b, 2);
+set_x(b, 3);
+let c = {};
+c.y = 4;
+set_x(c, 5);
+
+print a.x;
+print b.x;
+print c.x;
+print c.y;
+print json_stringify(c);
+"#
+);
+
+run_test!(
+    functions_in_fields,
+    r#"
+fn double(x) {
+    return x * 2;
+}
+
+let math = {};
+math.double = double;
+math.inner = {};
+math.inner.value = 21;
+print math.double(math.inner.value);
+"#
+);
+
+run_test!(
+    objects_with_many_fields,
+    r#"
+let big = {};
+big.f0 = 0;
+big.f1 = 1;
+big.f2 = 2;
+big.f3 = 3;
+big.f4 = 4;
+big.f5 = 5;
+big.f6 = 6;
+big.f7 = 7;
+big.f8 = 8;
+big.f9 = 9;
+big.f10 = 10;
+big.f11 = 11;
+big.f12 = 12;
+big.f13 = 13;
+big.f14 = 14;
+big.f15 = 15;
+big.f16 = 16;
+big.f17 = 17;
+big.f18 = 18;
+big.f19 = 19;
+big.f20 = 20;
+big.f21 = 21;
+big.f22 = 22;
+big.f23 = 23;
+big.f24 = 24;
+big.f25 = 25;
+big.f26 = 26;
+big.f27 = 27;
+big.f28 = 28;
+big.f29 = 29;
+big.f30 = 30;
+big.f31 = 31;
+big.f32 = 32;
+big.f33 = 33;
+big.f34 = 34;
+big.f35 = 35;
+big.f36 = 36;
+big.f37 = 37;
+big.f38 = 38;
+big.f39 = 39;
+print big.f0 + big.f31 + big.f32 + big.f39;
+big.f39 = 100;
+big.extra = 1;
+print big.f39 + big.extra;
+
+let parsed = json_parse(json_stringify(big));
+print parsed.f39;
+"#
+);
+
+run_test!(
+    json_objects_share_shapes,
+    r#"
+fn name(person) {
+    return person.name;
+}
+
+let i = 0;
+while i < 3 {
+    let person = json_parse("{\"name\": \"dilaria\", \"age\": 1}");
+    print name(person);
+    i = i + 1;
+}
+"#
+);
+
+run_test_io!(
+    missing_field,
+    r#"
+let object = {};
+object.x = 1;
+print object.y;
+"#,
+    ""
+);
+
+run_test_io!(
+    field_of_non_object,
+    r#"
+let number = 5;
+print number.x;
+"#,
+    ""
+);
+
+run_test_io!(
+    set_field_of_non_object,
+    r#"
+let string = "string";
+string.x = 1;
+"#,
+    ""
+);
+
+run_test!(
+    compound_assignment_to_fields,
+    r#"
+fn get(object) {
+    print "evaluated";
+    return object;
+}
+
+let counter = {};
+counter.count = 1;
+get(counter).count += 10;
+print counter.count;
+counter.count *= 3;
+print counter.count;
+"#
+);
+
+run_test!(
+    equality_compares_identity,
+    r#"
+let a = {};
+a.x = 1;
+let b = a;
+let c = {};
+c.x = 1;
+print a == b;
+print a == c;
+print a != c;
+print a != b;
+print [1] == [1];
+"#
+);
@@ -0,0 +1,171 @@
+mod common;
+
+/// Runs the program with and without optimizations, which must behave the same
+macro_rules! optimize_test {
+    ($name:ident, $code:expr) => {
+        #[test]
+        fn $name() {
+            let code = $code;
+            let unoptimized = common::_run_test_with_cfg(code, "", false, false);
+            let optimized = common::_run_test_with_cfg(code, "", false, true);
+            assert_eq!(unoptimized.stdout, optimized.stdout);
+            assert_eq!(unoptimized.stderr, optimized.stderr);
+            insta::assert_debug_snapshot!(optimized);
+        }
+    };
+}
+
+/// The amount of executed instructions, from the profiler
+fn executed_instructions(code: &str, optimize: bool) -> u64 {
+    let mut stdout = Vec::<u8>::new();
+    let mut stderr = Vec::<u8>::new();
+    let mut stdin = "".as_bytes();
+    let mut cfg = dilaria::Config {
+        filename: "test.dil",
+        debug: false,
+        step: false,
+        profile: true,
+        profile_folded: None,
+        coverage: false,
+        coverage_lcov: None,
+        stdout: &mut stdout,
+        stdin: &mut stdin,
+        stderr: &mut stderr,
+        parse_only: false,
+        optimize,
+        register: false,
+    };
+
+    dilaria::run_program(code, &mut cfg);
+
+    let report = String::from_utf8(stderr).unwrap();
+    report
+        .strip_prefix("profile: ")
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|count| count.parse().ok())
+        .expect("no profile report")
+}
+
+optimize_test!(
+    arithmetic,
+    r#"
+print 1 + 2 * 3;
+print (10 - 4) / 4;
+print -(2 * 3);
+print 1 / 0;
+print 0 / 0 == 0 / 0;
+"#
+);
+
+optimize_test!(
+    comparisons,
+    r#"
+print 1 < 2;
+print 2 <= 1;
+print "abc" > "abd";
+print "a" == "a" and not (null != null);
+print not true or false;
+"#
+);
+
+optimize_test!(
+    integers,
+    r#"
+print 9007199254740993 + 1;
+print (7 // 2 + (1 << 4)) ^ 3;
+print -7 % 3;
+print 1 == 1.0;
+print 1 + 0.5;
+print 9223372036854775807 + 1;
+"#
+);
+
+optimize_test!(
+    partially_constant,
+    r#"
+let x = 5;
+print x + 2 * 3;
+print 2 * 3 + x;
+print -x;
+"#
+);
+
+optimize_test!(
+    failing_operations_are_kept,
+    r#"
+print "before";
+print 1 + "string";
+"#
+);
+
+optimize_test!(
+    loops_and_breaks,
+    r#"
+let i = 0;
+loop {
+    let a = 1;
+    let b = 2;
+    while true {
+        break;
+    }
+    {
+        let c = 3;
+        let d = 4;
+    }
+    i = i + a + b * 2;
+    if i > 10 {
+        break;
+    }
+}
+print i;
+"#
+);
+
+optimize_test!(
+    fizzbuzz,
+    r#"
+let i = 1;
+while i < 16 {
+    if i % 15 == 0 {
+        print "FizzBuzz";
+    } else if i % 5 == 0 {
+        print "Buzz";
+    } else if i % 3 == 0 {
+        print "Fizz";
+    } else {
+        print i;
+    }
+    i = i + 1;
+}
+"#
+);
+
+optimize_test!(
+    functions,
+    r#"
+fn scale(x) {
+    if not false {
+        return x * (60 * 60);
+    }
+}
+
+print scale(2);
+"#
+);
+
+#[test]
+fn executes_fewer_instructions() {
+    let code = r#"
+let i = 0;
+while i < 10 {
+    i = i + 2 * 3 - 5;
+}
+print i;
+"#;
+    let unoptimized = executed_instructions(code, false);
+    let optimized = executed_instructions(code, true);
+    assert!(
+        optimized < unoptimized,
+        "{optimized} is not less than {unoptimized}"
+    );
+}
@@ -0,0 +1,94 @@
+mod common;
+
+/// The profile of a program, without the timings because they differ on every run
+#[derive(Debug)]
+#[allow(dead_code)]
+struct Profile {
+    stdout: String,
+    functions: Vec<String>,
+    lines: Vec<String>,
+    folded: String,
+}
+
+fn profile(code: &str) -> Profile {
+    let mut stdout = Vec::<u8>::new();
+    let mut stderr = Vec::<u8>::new();
+    let mut folded = Vec::<u8>::new();
+    let mut stdin = "".as_bytes();
+    let mut cfg = dilaria::Config {
+        filename: "test.dil",
+        debug: false,
+        step: false,
+        profile: true,
+        profile_folded: Some(&mut folded),
+        coverage: false,
+        coverage_lcov: None,
+        stdout: &mut stdout,
+        stdin: &mut stdin,
+        stderr: &mut stderr,
+        parse_only: false,
+        optimize: false,
+        register: false,
+    };
+
+    dilaria::run_program(code, &mut cfg);
+
+    let report = String::from_utf8(stderr).unwrap();
+    let (functions, lines) = report.split_once("\n\n").unwrap();
+
+    // the function table is sorted by time, so sort it by name instead
+    let mut functions = functions
+        .lines()
+        .skip(2)
+        .map(|line| {
+            let columns = line.split_whitespace().collect::<Vec<_>>();
+            columns[..4].join(" ")
+        })
+        .collect::<Vec<_>>();
+    functions.sort();
+
+    Profile {
+        stdout: String::from_utf8(stdout).unwrap(),
+        functions,
+        lines: std::iter::once(report.lines().next().unwrap())
+            .chain(lines.lines())
+            .map(str::to_owned)
+            .collect(),
+        folded: String::from_utf8(folded).unwrap(),
+    }
+}
+
+#[test]
+fn calls_and_loops() {
+    let output = profile(
+        r#"fn add(a, b) {
+    return a + b;
+}
+
+fn greet() {
+    print "hello";
+}
+
+let i = 0;
+while i < 3 {
+    i = add(i, 1);
+}
+greet();
+"#,
+    );
+    insta::assert_debug_snapshot!(output);
+}
+
+#[test]
+fn runtime_error_still_reports() {
+    let output = profile(
+        r#"fn fail() {
+    return 1 + "string";
+}
+
+print "before";
+fail();
+"#,
+    );
+    insta::assert_debug_snapshot!(output);
+}
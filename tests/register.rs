@@ -0,0 +1,579 @@
+mod common;
+
+use common::Output;
+
+fn run_register(code: &str) -> Output {
+    common::_run_test_with(code, "", |cfg| cfg.register = true)
+}
+
+/// Runs the program in the stack VM and the register VM, which must behave the same
+macro_rules! register_test {
+    ($name:ident, $code:expr) => {
+        #[test]
+        fn $name() {
+            let code = $code;
+            let stack = common::_run_test_io(code, "");
+            let register = run_register(code);
+            assert_eq!(stack.stdout, register.stdout);
+            assert_eq!(stack.stderr, register.stderr);
+            insta::assert_debug_snapshot!(register);
+        }
+    };
+}
+
+register_test!(
+    arithmetic,
+    r#"
+let x = 5;
+print 1 + 2 * 3;
+print (x - 4) / 4;
+print -(x * 3);
+print not (x > 3);
+print "abc" < "abd";
+print null == null;
+"#
+);
+
+register_test!(
+    assign_to_itself,
+    r#"
+let x = 2;
+x = x * 3 + x;
+print x;
+x = x;
+print x;
+let y = x;
+x = 1;
+print y;
+"#
+);
+
+register_test!(
+    scopes,
+    r#"
+let a = 1;
+{
+    let a = 2;
+    let b = a + 1;
+    print b;
+}
+let c = 4;
+print a + c;
+"#
+);
+
+register_test!(
+    fizzbuzz,
+    r#"
+let i = 1;
+while i < 16 {
+    if i % 15 == 0 {
+        print "FizzBuzz";
+    } else if i % 5 == 0 {
+        print "Buzz";
+    } else if i % 3 == 0 {
+        print "Fizz";
+    } else {
+        print i;
+    }
+    i = i + 1;
+}
+"#
+);
+
+register_test!(
+    loops,
+    r#"
+let i = 0;
+loop {
+    if i > 5 {
+        break;
+    }
+    i = i + 1;
+}
+while true {
+    break;
+}
+print i;
+"#
+);
+
+register_test!(
+    fib,
+    r#"
+fn fib(n) {
+    if n < 2 {
+        return n;
+    } else {
+        return fib(n - 1) + fib(n - 2);
+    }
+}
+
+print fib(15);
+"#
+);
+
+register_test!(
+    nested_calls,
+    r#"
+fn add(a, b) {
+    return a + b;
+}
+
+fn add3(a, b, c) {
+    let ab = add(a, b);
+    return add(ab, c);
+}
+
+print add(add(1, 2), add3(3, 4, 5));
+print add3(1, 1, 1) * add(2, 2);
+"#
+);
+
+register_test!(
+    implicit_return,
+    r#"
+fn nothing() {
+    let x = 1;
+}
+
+print nothing();
+"#
+);
+
+register_test!(
+    native_functions,
+    r#"
+print json_stringify(json_parse("[1, 2]"), 0);
+"#
+);
+
+register_test!(
+    bad_type,
+    r#"
+print "before";
+print 1 + "a";
+print "after";
+"#
+);
+
+register_test!(
+    wrong_number_of_arguments,
+    r#"
+fn f(a) {}
+f(1, 2);
+"#
+);
+
+register_test!(
+    call_non_function,
+    r#"
+let x = 1;
+x();
+"#
+);
+
+#[test]
+fn debugger_is_unsupported() {
+    let output = common::_run_test_with("print 1;", "", |cfg| {
+        cfg.register = true;
+        cfg.step = true;
+    });
+    insta::assert_debug_snapshot!(output);
+}
+
+register_test!(
+    tail_calls,
+    r#"
+fn sum(n, acc) {
+    if n == 0 {
+        return acc;
+    }
+    return sum(n - 1, acc + n);
+}
+
+fn add(a, b, c) {
+    return a + b + c;
+}
+
+fn twice(x) {
+    let doubled = x * 2;
+    return add(doubled, doubled, sum(3, 0));
+}
+
+fn stringify(value) {
+    return json_stringify(value);
+}
+
+print sum(100000, 0);
+print twice(3);
+print stringify(null);
+"#
+);
+
+register_test!(
+    fields,
+    r#"
+fn make(x, y) {
+    let point = {};
+    point.x = x;
+    point.y = y;
+    return point;
+}
+
+fn sum(point) {
+    return point.x + point.y;
+}
+
+let other = {};
+other.y = 1;
+other.x = 2;
+
+let i = 0;
+while i < 3 {
+    let point = make(i, 10);
+    point.x = point.x * 2;
+    print sum(point);
+    print sum(other);
+    i = i + 1;
+}
+
+print other.z;
+"#
+);
+
+register_test!(
+    methods,
+    r#"
+let Point = {};
+
+fn Point.new(self, x, y) {
+    let point = create(self);
+    point.x = x;
+    point.y = y;
+    return point;
+}
+
+fn Point.sum(self, extra) {
+    return self.x + self.y + extra;
+}
+
+fn Point.scaled(self, factor) {
+    return self.new(self.x * factor, self.y * factor);
+}
+
+let point = Point.new(1, 2);
+let i = 0;
+while i < 3 {
+    print point.scaled(i).sum(i);
+    i = i + 1;
+}
+
+Point.missing();
+"#
+);
+
+register_test!(
+    compound_assignment,
+    r#"
+fn get(object) {
+    print "evaluated";
+    return object;
+}
+
+let x = 3;
+x += x * 2;
+print x;
+x %= 4;
+print x;
+
+let point = {};
+point.x = 1;
+get(point).x -= 5;
+print point.x;
+"#
+);
+
+register_test!(
+    match_patterns,
+    r#"
+fn describe(value) {
+    match value {
+        0 | 1 => { print "small"; }
+        [a, [_, b]] => {
+            print a;
+            print b;
+        }
+        { kind: "circle", radius } if radius > 1 => { print radius; }
+        { kind } => { print kind; }
+        n => {
+            n = "rebound";
+            print n;
+        }
+    }
+}
+
+let x = 1;
+describe(x);
+describe(json_parse("[2, [3, 4]]"));
+describe(json_parse("{\"kind\": \"circle\", \"radius\": 2}"));
+describe(json_parse("{\"kind\": \"circle\", \"radius\": 1}"));
+describe(7);
+print x;
+"#
+);
+
+register_test!(
+    block_values,
+    r#"
+fn sign(x) {
+    if x < 0 { -1 } else if x > 0 { 1 } else { 0 }
+}
+
+let x = 3;
+let y = {
+    let doubled = x * 2;
+    doubled + 1
+};
+print y;
+print sign(-x);
+print if x == 3 { "three" };
+print match x {
+    1 => { "one" }
+    n if n > 2 => {
+        let big = "big";
+        big
+    }
+};
+x = match x { 3 => { x + 1 } };
+print x;
+"#
+);
+
+register_test!(
+    null_safety,
+    r#"
+fn pick(f, which) {
+    if which {
+        return f;
+    }
+    return null;
+}
+fn double(x) {
+    return x * 2;
+}
+
+let data = json_parse("{\"user\": {\"name\": \"hugo\", \"address\": null}}");
+let name = data?.user?.name;
+print name;
+print data.user.address?.street.number ?? "no street";
+print pick(double, true)?.(5);
+print pick(double, false)?.(5);
+let x = null;
+x = x?.field ?? x;
+print x;
+x = x ?? 1;
+print x;
+"#
+);
+
+register_test!(
+    variadic_functions,
+    r#"
+fn f(a, b = a * 2, ...rest) {
+    print a + b;
+    return json_stringify(rest);
+}
+fn add(a, b, c) {
+    return a + b + c;
+}
+
+let Point = {};
+fn Point.shift(self, dx, dy) {
+    return self.x + dx + dy;
+}
+let point = create(Point);
+point.x = 1;
+
+let numbers = json_parse("[1, 2]");
+print f(1, 2, 3);
+print f(4);
+print add(10, ...numbers);
+print add(...numbers, ...json_parse("[3]"));
+print point.shift(...numbers);
+print point?.shift(5, ...json_parse("[6]"));
+"#
+);
+
+register_test!(
+    destructuring,
+    r#"
+fn sum([a, b], { c } = json_parse("{\"c\": 3}"), ...[d, ...rest]) {
+    print json_stringify(rest);
+    return a + b + c + d;
+}
+
+let [x, ...xs] = json_parse("[1, 2, 3]");
+let { name, nested: { value } } = json_parse("{\"name\": \"hugo\", \"nested\": {\"value\": 5}}");
+print x;
+print json_stringify(xs);
+print name;
+print value;
+print sum(xs, null, 4, 5, 6);
+match xs {
+    [2, ...tail] => { print json_stringify(tail); }
+}
+let [missing] = xs;
+"#
+);
+
+register_test!(
+    constants,
+    r#"
+const FACTOR = 2;
+const NAME = "scaled";
+const [first, ...rest] = json_parse("[1, 2, 3]");
+
+fn scale(x) {
+    return x * FACTOR;
+}
+
+{
+    let FACTOR = 5;
+    FACTOR += 1;
+    print FACTOR;
+}
+print NAME;
+print scale(first);
+print json_stringify(rest);
+"#
+);
+
+register_test!(
+    integers,
+    r#"
+let id = 9007199254740993;
+print id + 1;
+print id // 2;
+print -7 % 3;
+print 7 / 2;
+print (12 & 10) | (1 << 4) ^ 1;
+print -16 >> 2;
+print id > 9007199254740992.0;
+print 1 == 1.0;
+print id * id;
+"#
+);
+
+register_test!(
+    print_arrays_and_objects,
+    r#"
+let obj = json_parse("{\"list\": [1, \"two\", null], \"nested\": {}}");
+obj.self = obj;
+print obj;
+print obj.list;
+"#
+);
+
+register_test!(
+    array_literals,
+    r#"
+fn pack(a, b, ...rest) {
+    return [a, b, rest];
+}
+
+let [x, [y, z]] = [1, [2 + 3, "five"]];
+print x + y;
+print z;
+print pack(1, 2, 3, 4);
+print pack(...[x, y]);
+"#
+);
+
+register_test!(
+    invalid_assignment_target,
+    r#"
+fn f() {}
+f() += 2;
+"#
+);
+
+register_test!(
+    object_equality,
+    r#"
+let a = {};
+let b = a;
+print a == b;
+print a != {};
+"#
+);
+
+register_test!(
+    match_expression_arms,
+    r#"
+fn sign(x) {
+    match x {
+        0 => "zero",
+        n if n < 0 => "negative",
+        _ => { "positive" }
+    }
+}
+
+print sign(-3);
+print match 3 { 0 => 1, _ => 2 };
+"#
+);
+
+register_test!(
+    chained_operators,
+    r#"
+print 8 // 2 // 2;
+print 10 - 2 - 3;
+print 1 - 2 + 3;
+print 1 << 2 << 3;
+print 7 & 6 & 3;
+print 6 ^ 3 ^ 1;
+"#
+);
+
+register_test!(
+    functions_in_fields,
+    r#"
+fn add(a, b) {
+    return a + b;
+}
+
+fn greet(name, greeting = "hello") {
+    return [greeting, name];
+}
+
+let math = {};
+math.add = add;
+math.greet = greet;
+math.parse = json_parse;
+print math.add(1, 2);
+print math.add(...[3, 4]);
+print math.greet("nils");
+print math.parse("[1]");
+"#
+);
+
+register_test!(
+    integer_compound_assignment,
+    r#"
+let x = 17;
+x //= 2;
+let flags = 1;
+flags |= 4;
+flags &= 6;
+flags ^= 3;
+flags <<= 3;
+flags >>= 2;
+print x;
+print flags;
+let counter = {};
+counter.count = -7;
+counter.count //= 2;
+print counter.count;
+"#
+);
@@ -0,0 +1,176 @@
+#![cfg(feature = "serde")]
+
+use std::collections::BTreeMap;
+
+use dilaria::{serde::from_value, RtAlloc, Value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    retries: u32,
+    ratio: f64,
+    enabled: bool,
+    tags: Vec<String>,
+    parent: Option<Box<Config>>,
+    mode: Mode,
+    limits: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Mode {
+    Off,
+    Fixed(u8),
+    Range(i32, i32),
+    Custom { label: String },
+}
+
+fn rt() -> RtAlloc {
+    // SAFETY: the values never outlive the test
+    unsafe { RtAlloc::new() }
+}
+
+fn to_value<T: Serialize>(value: &T, rt: &mut RtAlloc) -> Value {
+    dilaria::serde::to_value(value, rt).unwrap()
+}
+
+fn round_trip<T>(value: T)
+where
+    T: Serialize + for<'de> Deserialize<'de> + PartialEq + std::fmt::Debug,
+{
+    let mut rt = rt();
+    let dilaria_value = to_value(&value, &mut rt);
+    let back = from_value::<T>(dilaria_value).unwrap();
+    assert_eq!(value, back);
+}
+
+#[test]
+fn primitives() {
+    round_trip(true);
+    round_trip(5u8);
+    round_trip(-1234i64);
+    round_trip(0.5f64);
+    round_trip('x');
+    round_trip("hello".to_string());
+    round_trip(());
+    round_trip(Some(3u32));
+    round_trip(None::<u32>);
+}
+
+#[test]
+fn large_integers() {
+    // 2^53 + 1 can't be represented as a float
+    round_trip(9_007_199_254_740_993i64);
+    round_trip(i64::MIN);
+    round_trip(i64::MAX as u64);
+
+    let mut rt = rt();
+    match to_value(&9_007_199_254_740_993u64, &mut rt) {
+        Value::Int(int) => assert_eq!(int, 9_007_199_254_740_993),
+        other => panic!("expected int, got {other:?}"),
+    }
+}
+
+#[test]
+fn collections() {
+    round_trip(vec![1, 2, 3]);
+    round_trip((1, "two".to_string(), 3.0));
+    round_trip(Vec::<String>::new());
+
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), vec![true]);
+    map.insert("b".to_string(), vec![false, true]);
+    round_trip(map);
+}
+
+#[test]
+fn enums() {
+    round_trip(Mode::Off);
+    round_trip(Mode::Fixed(4));
+    round_trip(Mode::Range(-3, 9));
+    round_trip(Mode::Custom {
+        label: "custom".to_string(),
+    });
+}
+
+#[test]
+fn nested_struct() {
+    let mut limits = BTreeMap::new();
+    limits.insert("cpu".to_string(), 4);
+    limits.insert("memory".to_string(), -1);
+
+    round_trip(Config {
+        name: "outer".to_string(),
+        retries: 3,
+        ratio: 0.25,
+        enabled: true,
+        tags: vec!["a".to_string(), "b".to_string()],
+        parent: Some(Box::new(Config {
+            name: "inner".to_string(),
+            retries: 0,
+            ratio: 1.0,
+            enabled: false,
+            tags: vec![],
+            parent: None,
+            mode: Mode::Off,
+            limits: BTreeMap::new(),
+        })),
+        mode: Mode::Range(1, 2),
+        limits,
+    });
+}
+
+#[test]
+fn produces_dilaria_values() {
+    #[derive(Serialize)]
+    struct Point {
+        x: f64,
+        label: &'static str,
+    }
+
+    let mut rt = rt();
+    let value = to_value(
+        &Point {
+            x: 1.5,
+            label: "origin",
+        },
+        &mut rt,
+    );
+
+    let obj = match value {
+        Value::Object(obj) => obj,
+        other => panic!("expected object, got {other:?}"),
+    };
+    assert_eq!(obj.len(), 2);
+
+    match obj.get(rt.intern_string("x")) {
+        Some(Value::Num(num)) => assert_eq!(num, 1.5),
+        other => panic!("expected number, got {other:?}"),
+    }
+    match obj.get(rt.intern_string("label")) {
+        Some(Value::String(str)) => assert_eq!(str, rt.intern_string("origin")),
+        other => panic!("expected string, got {other:?}"),
+    }
+
+    match to_value(&[1, 2], &mut rt) {
+        Value::Array(array) => assert_eq!(array.len(), 2),
+        other => panic!("expected array, got {other:?}"),
+    }
+}
+
+#[test]
+fn type_errors() {
+    let mut rt = rt();
+
+    let value = to_value(&"not a number", &mut rt);
+    assert!(from_value::<u32>(value).is_err());
+
+    let value = to_value(&1.5, &mut rt);
+    assert!(from_value::<u32>(value).is_err());
+
+    let value = to_value(&-1, &mut rt);
+    assert!(from_value::<u8>(value).is_err());
+
+    let value = Value::Function(0);
+    assert!(from_value::<String>(value).is_err());
+}
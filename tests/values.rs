@@ -0,0 +1,92 @@
+mod common;
+
+run_test!(
+    numbers,
+    r#"
+print 1.5;
+print -0.25;
+print 0 / 0;
+print -(0 / 0);
+print 1 / 0;
+print -1 / 0;
+print 0 - 0;
+"#
+);
+
+run_test!(
+    other_values,
+    r#"
+fn f() {}
+
+let n = null;
+let t = true;
+let f2 = false;
+let s = "a string";
+let function = f;
+
+print n;
+print t;
+print f2;
+print s;
+print function;
+print input;
+"#
+);
+
+run_test!(
+    values_survive_calls,
+    r#"
+fn identity(x) {
+    return x;
+}
+
+print identity(1 / 0);
+print identity("hi") == "hi";
+print identity(null) == null;
+"#
+);
+
+run_test!(
+    compound_assignment,
+    r#"
+let x = 10;
+x += 5;
+print x;
+x -= 3;
+print x;
+x *= 2;
+print x;
+x /= 4;
+print x;
+x %= 4;
+print x;
+x += x;
+print x;
+"#
+);
+
+run_test_io!(
+    compound_assignment_type_error,
+    r#"
+let x = "string";
+x -= 1;
+"#,
+    ""
+);
+
+run_test_io!(
+    assignment_to_a_call,
+    r#"
+fn f() {}
+f() += 2;
+"#,
+    ""
+);
+
+run_test_io!(
+    assignment_to_a_literal,
+    r#"
+1 = 2;
+"#,
+    ""
+);
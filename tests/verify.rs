@@ -0,0 +1,121 @@
+mod common;
+
+use common::{instr::*, value};
+
+/// Runs the hand written bytecode, which must be rejected by the verifier
+macro_rules! verify_test {
+    ($name:ident, $functions:expr) => {
+        #[test]
+        fn $name() {
+            let output = common::_run_bytecode(&common::_bytecode_file($functions));
+            insta::assert_debug_snapshot!(output);
+        }
+    };
+}
+
+#[test]
+fn valid_code_runs() {
+    let output = common::_run_bytecode(&common::_bytecode_file(&[
+        (
+            "main",
+            0,
+            &[&value::num(1.0), &value::num(2.0), &value::function(1)],
+            &[
+                &push_const(0),
+                &push_const(1),
+                &push_const(2),
+                &call(2),
+                PRINT,
+                EXIT,
+            ],
+        ),
+        ("add", 2, &[], &[&load(0), &load(1), BIN_ADD, RETURN]),
+    ]));
+    assert_eq!(output.stdout, "3\n");
+    assert_eq!(output.stderr, "");
+}
+
+verify_test!(
+    stack_underflow,
+    &[("main", 0, &[], &[PUSH_NULL, BIN_ADD, EXIT])]
+);
+
+verify_test!(falls_off_the_end, &[("main", 0, &[], &[PUSH_NULL, PRINT])]);
+
+verify_test!(return_from_main, &[("main", 0, &[], &[PUSH_NULL, RETURN])]);
+
+verify_test!(
+    tail_call_from_main,
+    &[
+        (
+            "main",
+            0,
+            &[&value::function(1)],
+            &[&push_const(0), &tail_call(0)]
+        ),
+        ("f", 0, &[], &[PUSH_NULL, RETURN]),
+    ]
+);
+
+verify_test!(
+    missing_field_site,
+    &[("main", 0, &[], &[NEW_OBJECT, &get_field(0), PRINT, EXIT])]
+);
+
+verify_test!(jump_into_nowhere, &[("main", 0, &[], &[&jmp(-3), EXIT])]);
+
+verify_test!(
+    loop_grows_the_stack,
+    &[("main", 0, &[], &[NOP, PUSH_NULL, &jmp(-3), EXIT])]
+);
+
+verify_test!(
+    branches_disagree_on_depth,
+    &[("main", 0, &[], &[PUSH_NULL, &jmp_false(1), PUSH_NULL, EXIT])]
+);
+
+verify_test!(
+    load_outside_of_frame,
+    &[("main", 0, &[], &[PUSH_NULL, &load(1), EXIT])]
+);
+
+verify_test!(
+    store_into_bookkeeping,
+    &[
+        (
+            "main",
+            0,
+            &[&value::function(1)],
+            &[PUSH_NULL, &push_const(0), &call(1), EXIT]
+        ),
+        ("f", 1, &[], &[PUSH_NULL, &store(2), PUSH_NULL, RETURN]),
+    ]
+);
+
+verify_test!(
+    shrink_stack_into_parameters,
+    &[
+        (
+            "main",
+            0,
+            &[&value::function(1)],
+            &[PUSH_NULL, &push_const(0), &call(1), EXIT]
+        ),
+        ("f", 1, &[], &[&shrink_stack(1), PUSH_NULL, RETURN]),
+    ]
+);
+
+verify_test!(
+    call_without_function,
+    &[("main", 0, &[], &[PUSH_NULL, &call(1), EXIT])]
+);
+
+verify_test!(
+    push_unknown_function,
+    &[("main", 0, &[&value::function(1)], &[&push_const(0), EXIT])]
+);
+
+verify_test!(
+    push_unknown_constant,
+    &[("main", 0, &[&value::num(1.0)], &[&push_const(1), EXIT])]
+);